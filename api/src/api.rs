@@ -1,10 +1,14 @@
 //! A ```JSON``` only REST API.
-use crate::base64::Base64;
-use grpc_api::AssignmentId;
+use crate::base64::{Base64, Base64Bytes};
+use crate::db::SubmissionStatus;
+use chrono::{DateTime, Utc};
+use grpc_api::{AssignmentId, AssignmentResult};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use url::Url;
 
 /// The short version of an Assigment with only ```id``` and ```name```.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AssignmentShort {
     #[serde(rename = "assignmentId")]
@@ -12,6 +16,55 @@ pub struct AssignmentShort {
     pub name: String,
 }
 
+/// An exercise sheet grouping the assignments.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExerciseShort {
+    pub id: i32,
+    pub name: String,
+}
+
+/// All details of an assigment, without the solution.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssignmentDetail {
+    pub assignment_id: AssignmentId,
+    pub name: String,
+    pub description: String,
+    /// ```Bash```, ```Python3```, ```PowerShell```...
+    pub script_type: String,
+    /// The arguments the script is called with.
+    pub args: Vec<String>,
+    pub exercise: ExerciseShort,
+    pub active: bool,
+    /// The file run for zip submissions.
+    pub entry_point: Option<String>,
+    /// The names of the files which are available in the working dir while testing.
+    pub include_files: Vec<String>,
+    /// Attempts per student, unlimited if not set.
+    pub max_attempts: Option<i32>,
+    /// Seconds a student has to wait between two submissions.
+    pub submission_cooldown_secs: i32,
+}
+
+/// An assignment inside of an [Exercise](struct.Exercise.html).
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExerciseAssignment {
+    pub assignment_id: AssignmentId,
+    pub name: String,
+    pub script_type: String,
+}
+
+/// An exercise with its active assignments.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Exercise {
+    pub id: i32,
+    pub name: String,
+    pub assignments: Vec<ExerciseAssignment>,
+}
+
 /// An unique ID for the Ilias people for they usage. Each ```rust IliasId ``` stands for each [Submission](struct.Submission.html)
 #[derive(
     Debug,
@@ -21,6 +74,7 @@ pub struct AssignmentShort {
     PartialEq,
     Deserialize,
     Serialize,
+    JsonSchema,
     derive_more::Display,
     derive_more::From,
 )]
@@ -37,18 +91,36 @@ impl Default for IliasId {
 }
 /// The Submission uploaded by the Student which a to be tested.
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Submission {
     /// A unique ID crated and used by Ilias.
     pub ilias_id: IliasId,
+    /// The student who submitted, defaults to the ```iliasId```.
+    /// Each submission is a new attempt of the student on the assignment.
+    #[serde(default)]
+    pub student_id: Option<String>,
     /// A Base64 encoded representation for the provided source code.
+    /// Can be left out if ```sourceZip``` is given.
+    #[serde(default)]
+    #[schemars(with = "String")]
     pub source_code: Base64,
+    /// A Base64 encoded zip archive with multiple files, e.g. a main script with helper modules.
+    /// The entry point of the assignment is run, ```sourceCode``` is ignored then.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub source_zip: Option<Base64Bytes>,
     /// A unique ID (UUID) crated the postgresql to identify each assignment.
     pub assignment_id: AssignmentId,
+    /// Optional URL the result is POSTed to as soon as the test is done.
+    /// Internal hosts like localhost or private addresses are rejected unless they are allowed explicitly.
+    /// Only accepted if the API has a webhook secret to sign the results with.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub callback_url: Option<Url>,
 }
 /// Just a little example which I can return for nice error message if someone forgot hwo to use my api.
-#[derive(Debug, serde::Serialize, derive_more::Constructor)]
+#[derive(Debug, serde::Serialize, JsonSchema, derive_more::Constructor)]
 #[serde(rename_all = "camelCase")]
 pub struct SubmissionExample {
     pub ilias_id: IliasId,
@@ -56,22 +128,163 @@ pub struct SubmissionExample {
     pub assignment_id: AssignmentId,
 }
 
+/// Returned after a submission was stored.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionCreated {
+    pub ilias_id: IliasId,
+    pub student_id: String,
+    /// The number of the attempt of the student on the assignment, starting at 1.
+    pub attempt: i32,
+    /// Only set if the test finished within the ```wait``` seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<AssignmentResult>,
+}
+
+/// Waits at most ```wait``` seconds for the result of the submission.
+#[derive(Deserialize, Debug)]
+pub struct SubmitOptions {
+    pub wait: Option<u64>,
+}
+
+/// One attempt of a student on an assignment with its result.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Attempt {
+    pub attempt: i32,
+    pub assignment_id: AssignmentId,
+    pub ilias_id: IliasId,
+    pub status: SubmissionStatus,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// ```null``` until the attempt is tested.
+    pub result: Option<AssignmentResult>,
+}
+
+/// A status change of a submission pushed via Server-Sent Events. The result is only set when it is ```Done```.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmissionEvent {
+    /// Events of different submissions with the same iliasId are told apart by it.
+    #[serde(skip)]
+    pub submission_id: i32,
+    pub ilias_id: IliasId,
+    /// The attempt of the student the event belongs to, as returned when submitting.
+    pub attempt: i32,
+    pub status: SubmissionStatus,
+    pub result: Option<AssignmentResult>,
+}
+
+/// Only the attempts on this assignment.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AttemptFilter {
+    pub assignment_id: Option<AssignmentId>,
+}
+
+/// A submission which is waiting in the queue or currently being tested.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueJob {
+    pub ilias_id: IliasId,
+    pub student_id: String,
+    pub attempt: i32,
+    /// The testing server platform: ```linux``` or ```windows```.
+    pub platform: String,
+    pub status: SubmissionStatus,
+    /// Number of times the test was started.
+    pub attempts: i32,
+    /// The transport error of the last failed attempt.
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// When the job is claimed again, only relevant for retries.
+    pub next_run_at: DateTime<Utc>,
+}
+
 /// The current status of the booth RPC endpoints.
-#[derive(Serialize, Debug, Clone, derive_more::Constructor)]
+#[derive(Serialize, Debug, Clone, JsonSchema, derive_more::Constructor)]
 #[serde(rename_all = "camelCase")]
 pub struct Status {
     pub linux_rpc_status: EndPointStatus,
     pub windows_rpc_status: EndPointStatus,
+    /// Every single testing server.
+    pub nodes: Vec<NodeStatus>,
+}
+
+/// The status of one testing server.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStatus {
+    pub platform: &'static str,
+    #[schemars(with = "String")]
+    pub rpc_url: Url,
+    pub status: EndPointStatus,
+    /// A draining server gets no new tests.
+    pub draining: bool,
+    /// Number of tests currently running on the server.
+    pub outstanding: usize,
+}
+
+/// The public key the results are signed with, see the ```attestation``` of the results.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyInfo {
+    pub algorithm: &'static str,
+    pub key_id: String,
+    /// Base64 encoded raw 32 byte Ed25519 public key.
+    pub public_key: String,
+    /// The keys the results were signed with before, they are still verified.
+    pub retired_keys: Vec<RetiredKey>,
+}
+
+/// A former public key of the API.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RetiredKey {
+    pub key_id: String,
+    /// Base64 encoded raw 32 byte Ed25519 public key.
+    pub public_key: String,
+}
+
+/// A signed result to verify, optionally with the source code it should have been tested with.
+#[derive(Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyRequest {
+    pub result: AssignmentResult,
+    /// The Base64 encoded source code or zip archive of the submission.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub source: Option<Base64Bytes>,
+}
+
+/// The outcome of the verification.
+#[derive(Serialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    pub valid: bool,
+    /// Why the result is not valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Starts or stops draining a testing server, e.g. before maintenance.
+#[derive(Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainNode {
+    /// The url of the testing server as listed by ```/status```.
+    #[schemars(with = "String")]
+    pub rpc_url: Url,
+    pub draining: bool,
 }
 
 /// The Version of this crate only to be serialized.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Version {
     pub version: &'static str,
 }
 /// The current status of the one RPC endpoint.
-#[derive(serde::Serialize, Debug, Clone)]
+#[derive(serde::Serialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum EndPointStatus {
     Online,
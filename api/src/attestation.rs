@@ -0,0 +1,373 @@
+//! Signs every result with Ed25519, so a graded result can be audited later and proven to belong to the submitted code.
+//! The [Attestation](../../grpc_api/struct.Attestation.html) binds the result to the SHA-256 of the source code,
+//! the revision of the assignment, the testing server and the time of the test.
+//! The signed payload are these lines joined by ```\n```: ```depp-result-v1```, ```source_sha256```,
+//! ```assignment_id```, ```assignment_revision```, ```testing_server```, ```tested_at```, ```key_id``` and the
+//! hex encoded SHA-256 of the result serialized as JSON without the ```attestation```.
+use crate::api::{PublicKeyInfo, RetiredKey};
+use chrono::Utc;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use grpc_api::{AssignmentResult, Attestation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+/// The first line of the signed payload, changes if the payload changes.
+const PAYLOAD_VERSION: &str = "depp-result-v1";
+
+/// The attestation config via the environment variables using ```DEPP_API_``` as prefix.
+/// Example:
+/// ```DEPP_API_SIGNING_KEY="$(openssl rand -base64 32)"```
+#[derive(Deserialize, Debug, Default)]
+pub struct AttestationConfig {
+    /// Base64 encoded 32 byte Ed25519 secret key.
+    signing_key: Option<String>,
+    /// Signs with a key generated on each start if no ```signing_key``` is set, only for development.
+    #[serde(default)]
+    signing_key_temporary: bool,
+    /// Comma separated base64 encoded public keys of former signing keys, their results are still verified.
+    #[serde(default)]
+    retired_keys: Vec<String>,
+}
+
+/// Reading the environment variables.
+pub fn get_config() -> Result<AttestationConfig, envy::Error> {
+    envy::prefixed("DEPP_API_").from_env::<AttestationConfig>()
+}
+
+/// Why a result could not be verified.
+#[derive(failure::Fail, Debug)]
+pub enum VerifyError {
+    #[fail(display = "The result has no attestation")]
+    Unsigned,
+    #[fail(display = "The result was signed with the unknown key {}", _0)]
+    UnknownKey(String),
+    #[fail(display = "The signature does not match the result")]
+    BadSignature,
+    #[fail(display = "The result was not tested with the given source code")]
+    SourceMismatch,
+}
+
+/// Holds the key pair of the API and the public keys it signed with before.
+pub struct ResultSigner {
+    keypair: Keypair,
+    key_id: String,
+    /// The retired public keys by key id.
+    retired: Vec<(String, PublicKey)>,
+}
+
+/// Hex encoded SHA-256.
+pub fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// The first 16 hex digits of the SHA-256 of the public key.
+fn key_id(public: &PublicKey) -> String {
+    sha256(public.as_bytes())[..16].to_string()
+}
+
+impl ResultSigner {
+    /// Fails without ```DEPP_API_SIGNING_KEY``` unless ```DEPP_API_SIGNING_KEY_TEMPORARY``` is set,
+    /// the results signed with a temporary key can't be verified after a restart.
+    pub fn new(config: AttestationConfig) -> Result<ResultSigner, failure::Error> {
+        let keypair = match config.signing_key {
+            Some(key) => {
+                let secret = base64::decode(key.trim())
+                    .map_err(|_| failure::err_msg("DEPP_API_SIGNING_KEY is not base64"))?;
+                let secret = SecretKey::from_bytes(&secret).map_err(|_| {
+                    failure::err_msg("DEPP_API_SIGNING_KEY has to be a 32 byte Ed25519 key")
+                })?;
+                let public = PublicKey::from(&secret);
+                Keypair { secret, public }
+            }
+            None if config.signing_key_temporary => {
+                log::warn!(
+                    "DEPP_API_SIGNING_KEY is not set, the results are signed with a temporary key"
+                );
+                Keypair::generate(&mut rand::rngs::OsRng)
+            }
+            None => {
+                return Err(failure::err_msg(
+                    "DEPP_API_SIGNING_KEY is not set, set DEPP_API_SIGNING_KEY_TEMPORARY=true to sign with a temporary key during development",
+                ))
+            }
+        };
+        let retired = config
+            .retired_keys
+            .iter()
+            .map(|key| {
+                base64::decode(key.trim())
+                    .ok()
+                    .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+                    .map(|public| (key_id(&public), public))
+                    .ok_or_else(|| {
+                        failure::err_msg(format!(
+                            "DEPP_API_RETIRED_KEYS contains {} which is no base64 encoded 32 byte Ed25519 public key",
+                            key
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let key_id = key_id(&keypair.public);
+        log::info!("Signing results with the key {}", key_id);
+        Ok(ResultSigner {
+            keypair,
+            key_id,
+            retired,
+        })
+    }
+
+    pub fn public_key(&self) -> PublicKeyInfo {
+        PublicKeyInfo {
+            algorithm: "Ed25519",
+            key_id: self.key_id.clone(),
+            public_key: base64::encode(self.keypair.public.as_bytes()),
+            retired_keys: self
+                .retired
+                .iter()
+                .map(|(key_id, public)| RetiredKey {
+                    key_id: key_id.clone(),
+                    public_key: base64::encode(public.as_bytes()),
+                })
+                .collect(),
+        }
+    }
+
+    /// The current or a retired public key with the key id.
+    fn verifying_key(&self, key_id: &str) -> Option<&PublicKey> {
+        if key_id == self.key_id {
+            return Some(&self.keypair.public);
+        }
+        self.retired
+            .iter()
+            .find(|(retired_id, _)| retired_id == key_id)
+            .map(|(_, public)| public)
+    }
+
+    /// Replaces the attestation of the result with a new signed one.
+    pub fn attest(
+        &self,
+        result: &mut AssignmentResult,
+        source_sha256: &str,
+        assignment_id: &Uuid,
+        assignment_revision: i32,
+        testing_server: &str,
+    ) {
+        result.attestation = None;
+        let mut attestation = Attestation {
+            source_sha256: source_sha256.to_string(),
+            assignment_id: assignment_id.to_string(),
+            assignment_revision,
+            testing_server: testing_server.to_string(),
+            tested_at: Utc::now().to_rfc3339(),
+            key_id: self.key_id.clone(),
+            signature: String::new(),
+        };
+        let signature = self.keypair.sign(&payload(result, &attestation));
+        attestation.signature = base64::encode(&signature.to_bytes()[..]);
+        result.attestation = Some(attestation);
+    }
+
+    /// Checks the signature of the result and if ```source``` is given that it was tested with this source code
+    /// or zip archive.
+    pub fn verify(
+        &self,
+        result: &AssignmentResult,
+        source: Option<&[u8]>,
+    ) -> Result<(), VerifyError> {
+        let attestation = result.attestation.as_ref().ok_or(VerifyError::Unsigned)?;
+        let public = self
+            .verifying_key(&attestation.key_id)
+            .ok_or_else(|| VerifyError::UnknownKey(attestation.key_id.clone()))?;
+        let signature = base64::decode(&attestation.signature)
+            .ok()
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or(VerifyError::BadSignature)?;
+        let unsigned = AssignmentResult {
+            attestation: None,
+            ..result.clone()
+        };
+        public
+            .verify(&payload(&unsigned, attestation), &signature)
+            .map_err(|_| VerifyError::BadSignature)?;
+        match source {
+            Some(source) if sha256(source) != attestation.source_sha256 => {
+                Err(VerifyError::SourceMismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The signed bytes, ```result``` must not contain the attestation.
+fn payload(result: &AssignmentResult, attestation: &Attestation) -> Vec<u8> {
+    let json = serde_json::to_vec(result).unwrap_or_default();
+    [
+        PAYLOAD_VERSION,
+        &attestation.source_sha256,
+        &attestation.assignment_id,
+        &attestation.assignment_revision.to_string(),
+        &attestation.testing_server,
+        &attestation.tested_at,
+        &attestation.key_id,
+        &sha256(&json),
+    ]
+    .join("\n")
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"sort -n numbers.txt";
+
+    fn signer(secret: u8) -> ResultSigner {
+        ResultSigner::new(AttestationConfig {
+            signing_key: Some(base64::encode([secret; 32])),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn signed_result(signer: &ResultSigner) -> AssignmentResult {
+        let mut result = AssignmentResult {
+            passed: true,
+            message: Some("All checks passed".into()),
+            valid: true,
+            ..Default::default()
+        };
+        signer.attest(&mut result, &sha256(SOURCE), &Uuid::nil(), 3, "testing-1");
+        result
+    }
+
+    #[test]
+    fn signed_result_is_verified() {
+        let signer = signer(1);
+        let result = signed_result(&signer);
+        let attestation = result.attestation.as_ref().unwrap();
+        assert_eq!(attestation.key_id, signer.public_key().key_id);
+        assert_eq!(attestation.assignment_revision, 3);
+        assert!(signer.verify(&result, None).is_ok());
+        assert!(signer.verify(&result, Some(SOURCE)).is_ok());
+    }
+
+    #[test]
+    fn key_id_is_derived_from_the_key() {
+        assert_eq!(signer(1).key_id, signer(1).key_id);
+        assert_ne!(signer(1).key_id, signer(2).key_id);
+        assert_eq!(signer(1).key_id.len(), 16);
+        assert!(ResultSigner::new(AttestationConfig {
+            signing_key: Some("not base64!".into()),
+            ..Default::default()
+        })
+        .is_err());
+        assert!(ResultSigner::new(AttestationConfig {
+            signing_key: Some(base64::encode([1; 16])),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn tampered_result_is_rejected() {
+        let signer = signer(1);
+        let mut result = signed_result(&signer);
+        result.passed = false;
+        assert!(matches!(
+            signer.verify(&result, None),
+            Err(VerifyError::BadSignature)
+        ));
+        let mut result = signed_result(&signer);
+        result.attestation.as_mut().unwrap().assignment_revision = 4;
+        assert!(matches!(
+            signer.verify(&result, None),
+            Err(VerifyError::BadSignature)
+        ));
+        let mut result = signed_result(&signer);
+        result.attestation.as_mut().unwrap().signature = "garbage".into();
+        assert!(matches!(
+            signer.verify(&result, None),
+            Err(VerifyError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn other_source_is_rejected() {
+        let signer = signer(1);
+        let result = signed_result(&signer);
+        assert!(matches!(
+            signer.verify(&result, Some(b"sort numbers.txt")),
+            Err(VerifyError::SourceMismatch)
+        ));
+    }
+
+    #[test]
+    fn unknown_key_is_rejected() {
+        let result = signed_result(&signer(1));
+        match signer(2).verify(&result, None) {
+            Err(VerifyError::UnknownKey(key_id)) => assert_eq!(key_id, signer(1).key_id),
+            other => panic!("expected an unknown key, got {:?}", other),
+        }
+        // the key id is part of the payload, so it can't be swapped to another key
+        let other = signer(2);
+        let mut result = signed_result(&signer(1));
+        result.attestation.as_mut().unwrap().key_id = other.key_id.clone();
+        assert!(matches!(
+            other.verify(&result, None),
+            Err(VerifyError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn unsigned_result_is_rejected() {
+        let mut result = signed_result(&signer(1));
+        result.attestation = None;
+        assert!(matches!(
+            signer(1).verify(&result, None),
+            Err(VerifyError::Unsigned)
+        ));
+    }
+
+    #[test]
+    fn signing_key_is_required() {
+        assert!(ResultSigner::new(AttestationConfig::default()).is_err());
+        let temporary = ResultSigner::new(AttestationConfig {
+            signing_key_temporary: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(temporary.verify(&signed_result(&temporary), None).is_ok());
+    }
+
+    #[test]
+    fn retired_keys_are_verified() {
+        let old = signer(1);
+        let result = signed_result(&old);
+        let rotated = ResultSigner::new(AttestationConfig {
+            signing_key: Some(base64::encode([2; 32])),
+            retired_keys: vec![old.public_key().public_key],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(rotated.verify(&result, Some(SOURCE)).is_ok());
+        assert!(rotated.verify(&signed_result(&rotated), None).is_ok());
+        let info = rotated.public_key();
+        assert_eq!(info.key_id, signer(2).key_id);
+        assert_eq!(info.retired_keys.len(), 1);
+        assert_eq!(info.retired_keys[0].key_id, old.key_id);
+        let mut tampered = result;
+        tampered.passed = false;
+        assert!(matches!(
+            rotated.verify(&tampered, None),
+            Err(VerifyError::BadSignature)
+        ));
+        assert!(ResultSigner::new(AttestationConfig {
+            signing_key: Some(base64::encode([2; 32])),
+            retired_keys: vec![base64::encode([1; 16])],
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
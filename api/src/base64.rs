@@ -1,7 +1,8 @@
 //! Own Base64 module which supports deserialize.
 
 use serde::de::{self, Deserialize, Deserializer, Visitor};
-#[derive(Debug, Clone)]
+use serde::{Serialize, Serializer};
+#[derive(Debug, Clone, Default)]
 pub struct Base64(pub String);
 struct Base64Visitor;
 
@@ -38,3 +39,53 @@ impl<'de> Deserialize<'de> for Base64 {
         deserializer.deserialize_str(Base64Visitor)
     }
 }
+
+/// Needed for the default value in the JSON schema.
+impl Serialize for Base64 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(&self.0))
+    }
+}
+
+/// Base64 encoded binary data like a zip archive.
+#[derive(Debug, Clone, Default)]
+pub struct Base64Bytes(pub Vec<u8>);
+struct Base64BytesVisitor;
+
+impl<'de> Visitor<'de> for Base64BytesVisitor {
+    type Value = Base64Bytes;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("A base64 encoded buffer")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        base64::decode(value)
+            .map_err(|_| E::custom("Not base64"))
+            .map(Base64Bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for Base64Bytes {
+    fn deserialize<D>(deserializer: D) -> Result<Base64Bytes, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(Base64BytesVisitor)
+    }
+}
+
+impl Serialize for Base64Bytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(&self.0))
+    }
+}
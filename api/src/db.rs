@@ -0,0 +1,812 @@
+//! Storing submissions and their results in the PostgreSQL database, so a restart of the API does not lose them.
+use crate::api::{
+    AssignmentDetail, Attempt, Exercise, ExerciseAssignment, ExerciseShort, IliasId, QueueJob,
+    Submission, SubmissionEvent,
+};
+use crate::handlers::error::Error;
+use crate::webhook::Delivery;
+use chrono::NaiveDate;
+use db_lib::export::ExportScope;
+use deadpool_postgres::Pool;
+use grpc_api::{Assignment, AssignmentResult, RegexRule};
+use postgres_types::{FromSql, ToSql};
+use std::time::Duration;
+use tokio_postgres::types::Json;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// The status of a stored submission.
+#[derive(Debug, Clone, Copy, PartialEq, ToSql, FromSql, serde::Serialize, schemars::JsonSchema)]
+#[postgres(name = "submission_status")]
+pub enum SubmissionStatus {
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+}
+
+impl SubmissionStatus {
+    /// No further status changes follow.
+    pub fn is_final(self) -> bool {
+        self == SubmissionStatus::Done || self == SubmissionStatus::Cancelled
+    }
+}
+
+/// A submission as stored in the database, ready to be send to the RPC server.
+#[derive(Debug, Clone)]
+pub struct StoredSubmission {
+    pub id: i32,
+    pub ilias_id: String,
+    pub assignment_id: Uuid,
+    pub assignment_revision: i32,
+    pub source_code: String,
+    pub source_zip: Option<Vec<u8>>,
+    pub callback_url: Option<String>,
+    /// The attempt of the student on the assignment.
+    pub attempt: i32,
+    /// Number of times the submission was claimed for testing, including the current one.
+    pub attempts: i32,
+}
+
+/// The result of a submission which was not picked up yet.
+#[derive(Debug)]
+pub enum StoredResult {
+    /// Still waiting in the queue or being tested.
+    Pending,
+    Done(AssignmentResult),
+    Cancelled,
+}
+
+/// Query one assigment from the database.
+pub async fn assignment(pool: &Pool, uuid: &Uuid) -> Result<Assignment, Error> {
+    let client = pool.get().await?;
+    let stmt = client
+        .prepare(
+            r#"SELECT assignment_name, script_type, include_files, solution, args, compare_fs_solution,
+                                compare_stdout_solution, sort_stdout, custom_script, custom_script_type,
+                                lint_mode, lint_fail_severity, required_commands, forbidden_commands, entry_point,
+                                feedback_level
+                    FROM assignment
+                    WHERE assignment.uuid = $1;"#,
+        )
+        .await?;
+    let row = client
+        .query_one(&stmt, &[uuid])
+        .await
+        .map_err(|_| Error::NotAssignment(*uuid))?;
+    let mut assignment = Assignment::from(&row);
+    let stmt = client
+        .prepare(
+            r#"SELECT pattern, target, file_name, must_match, case_insensitive, multi_line, dot_all, message
+                    FROM regex_rule
+                    WHERE assignment_id = (SELECT id FROM assignment WHERE uuid = $1)
+                    ORDER BY position, id;"#,
+        )
+        .await?;
+    assignment.regex_rules = client
+        .query(&stmt, &[uuid])
+        .await?
+        .iter()
+        .map(RegexRule::from)
+        .collect();
+    Ok(assignment)
+}
+
+/// Query the details of one assigment, ```None``` if there is no such assignment.
+pub async fn assignment_detail(
+    pool: &Pool,
+    uuid: &Uuid,
+) -> Result<Option<AssignmentDetail>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"SELECT assignment_name, assignment.description, script_type::text AS script_type, args, active,
+                        entry_point, include_files, max_attempts, submission_cooldown_secs,
+                        exercise.id AS exercise_id, exercise.description AS exercise_name
+                    FROM assignment JOIN exercise
+                    ON assignment.exercise_id = exercise.id
+                    WHERE assignment.uuid = $1;"#,
+            &[uuid],
+        )
+        .await?;
+    Ok(row.map(|row| {
+        let include_files: Option<Vec<u8>> = row.get("include_files");
+        AssignmentDetail {
+            assignment_id: *uuid,
+            name: row.get("assignment_name"),
+            description: row.get("description"),
+            script_type: row.get("script_type"),
+            args: row.get("args"),
+            exercise: ExerciseShort {
+                id: row.get("exercise_id"),
+                name: row.get("exercise_name"),
+            },
+            active: row.get("active"),
+            entry_point: row.get("entry_point"),
+            include_files: zip_file_names(&include_files.unwrap_or_default()),
+            max_attempts: row.get("max_attempts"),
+            submission_cooldown_secs: row.get("submission_cooldown_secs"),
+        }
+    }))
+}
+
+/// The names of all files inside the zip, dirs are left out.
+fn zip_file_names(zip: &[u8]) -> Vec<String> {
+    let mut names = vec![];
+    if let Ok(mut zip) = zip::ZipArchive::new(std::io::Cursor::new(zip)) {
+        for i in 0..zip.len() {
+            if let Ok(file) = zip.by_index(i) {
+                if !file.is_dir() {
+                    names.push(file.name().to_string());
+                }
+            }
+        }
+    }
+    names
+}
+
+/// All exercises with their active assignments.
+pub async fn exercises(pool: &Pool) -> Result<Vec<Exercise>, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT exercise.id, exercise.description, assignment.uuid, assignment_name,
+                        script_type::text AS script_type
+                    FROM exercise LEFT JOIN assignment
+                    ON assignment.exercise_id = exercise.id AND assignment.active
+                    ORDER BY exercise.description, exercise.id, assignment_name;"#,
+            &[],
+        )
+        .await?;
+    let mut exercises: Vec<Exercise> = vec![];
+    for row in rows {
+        let id: i32 = row.get("id");
+        if exercises.last().map(|e| e.id) != Some(id) {
+            exercises.push(Exercise {
+                id,
+                name: row.get("description"),
+                assignments: vec![],
+            });
+        }
+        if let Some(uuid) = row.get::<_, Option<Uuid>>("uuid") {
+            if let Some(exercise) = exercises.last_mut() {
+                exercise.assignments.push(ExerciseAssignment {
+                    assignment_id: uuid,
+                    name: row.get("assignment_name"),
+                    script_type: row.get("script_type"),
+                });
+            }
+        }
+    }
+    Ok(exercises)
+}
+
+/// Fails if the student has no attempts left or has to wait ```wait_secs``` for the cooldown,
+/// ```wait_secs``` is negative if the cooldown is over.
+fn check_limits(
+    max_attempts: Option<i32>,
+    attempts: i32,
+    wait_secs: Option<i64>,
+) -> Result<(), Error> {
+    if let Some(max_attempts) = max_attempts.filter(|max| attempts >= *max) {
+        return Err(Error::AttemptsExhausted(max_attempts));
+    }
+    if let Some(wait_secs) = wait_secs.filter(|secs| *secs > 0) {
+        return Err(Error::Cooldown(wait_secs as u64));
+    }
+    Ok(())
+}
+
+/// Stores a new submission with the status ```Queued``` for the platform as the next attempt of the student
+/// and returns its id and attempt number.
+/// Fails if the student has used all attempts of the assignment or has to wait for the cooldown,
+/// cancelled submissions count as attempts as well.
+pub async fn insert_submission(
+    pool: &Pool,
+    submission: &Submission,
+    student_id: &str,
+    platform: &str,
+) -> Result<(i32, i32), Error> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    // concurrent submissions of the same student would get the same attempt number
+    transaction
+        .execute(
+            "SELECT pg_advisory_xact_lock(hashtext($1));",
+            &[&student_id],
+        )
+        .await?;
+    let limits = transaction
+        .query_opt(
+            r#"SELECT assignment.max_attempts, COALESCE(MAX(submission.attempt), 0) AS attempts,
+                        CEIL(EXTRACT(EPOCH FROM MAX(submission.created_at)
+                            + make_interval(secs => assignment.submission_cooldown_secs) - now()))::BIGINT AS wait_secs
+                    FROM assignment LEFT JOIN submission
+                    ON submission.assignment_id = assignment.id AND submission.student_id = $2
+                    WHERE assignment.uuid = $1
+                    GROUP BY assignment.id;"#,
+            &[&submission.assignment_id, &student_id],
+        )
+        .await?
+        .ok_or(Error::NotAssignment(submission.assignment_id))?;
+    check_limits(
+        limits.get("max_attempts"),
+        limits.get("attempts"),
+        limits.get("wait_secs"),
+    )?;
+    let row = transaction
+        .query_opt(
+            r#"INSERT INTO submission(ilias_id, student_id, assignment_id, attempt, source_code, source_zip, callback_url, platform)
+                    SELECT $1, $2, id,
+                        (SELECT COALESCE(MAX(attempt), 0) + 1 FROM submission
+                         WHERE student_id = $2 AND assignment_id = assignment.id),
+                        $4, $5, $6, $7
+                    FROM assignment WHERE uuid = $3
+                    RETURNING id, attempt;"#,
+            &[
+                &submission.ilias_id.to_string(),
+                &student_id,
+                &submission.assignment_id,
+                &submission.source_code.0,
+                &submission.source_zip.as_ref().map(|zip| &zip.0),
+                &submission.callback_url.as_ref().map(|url| url.as_str()),
+                &platform,
+            ],
+        )
+        .await?
+        .ok_or(Error::NotAssignment(submission.assignment_id))?;
+    transaction.commit().await?;
+    Ok((row.get("id"), row.get("attempt")))
+}
+
+/// Loads a stored submission.
+pub async fn submission(pool: &Pool, id: i32) -> Result<StoredSubmission, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_one(
+            r#"SELECT submission.id, ilias_id, assignment.uuid, assignment.revision, source_code, source_zip,
+                        callback_url, attempt, attempts
+                    FROM submission JOIN assignment
+                    ON submission.assignment_id = assignment.id
+                    WHERE submission.id = $1;"#,
+            &[&id],
+        )
+        .await?;
+    Ok(StoredSubmission {
+        id: row.get("id"),
+        ilias_id: row.get("ilias_id"),
+        assignment_id: row.get("uuid"),
+        assignment_revision: row.get("revision"),
+        source_code: row.get("source_code"),
+        source_zip: row.get("source_zip"),
+        callback_url: row.get("callback_url"),
+        attempt: row.get("attempt"),
+        attempts: row.get("attempts"),
+    })
+}
+
+/// Marks the oldest due submission of the platform as ```Running``` and returns its id.
+/// Locked rows are skipped, so no job is claimed twice.
+pub async fn claim_next(pool: &Pool, platform: &str) -> Result<Option<i32>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"UPDATE submission SET status = $2, started_at = now(), attempts = attempts + 1
+                    WHERE id = (SELECT id FROM submission
+                                WHERE status = $3 AND platform = $1 AND next_run_at <= now()
+                                ORDER BY next_run_at, id
+                                LIMIT 1
+                                FOR UPDATE SKIP LOCKED)
+                    RETURNING id;"#,
+            &[
+                &platform,
+                &SubmissionStatus::Running,
+                &SubmissionStatus::Queued,
+            ],
+        )
+        .await?;
+    Ok(row.map(|row| row.get("id")))
+}
+
+/// Puts the submission back into the queue, it is claimed again after the delay.
+/// Returns ```false``` if it was cancelled meanwhile.
+pub async fn retry_later(
+    pool: &Pool,
+    id: i32,
+    delay: Duration,
+    error: &str,
+) -> Result<bool, Error> {
+    let client = pool.get().await?;
+    let updated = client
+        .execute(
+            r#"UPDATE submission
+                    SET status = $2, started_at = NULL, next_run_at = now() + make_interval(secs => $3), last_error = $4
+                    WHERE id = $1 AND status = $5;"#,
+            &[
+                &id,
+                &SubmissionStatus::Queued,
+                &(delay.as_secs() as f64),
+                &error,
+                &SubmissionStatus::Running,
+            ],
+        )
+        .await?;
+    Ok(updated > 0)
+}
+
+/// Stores the result and marks the submission as ```Done``` in one transaction.
+/// ```detail``` is the unredacted result if the feedback level of the assignment hid something.
+/// Returns ```false``` and stores nothing if it was cancelled meanwhile.
+pub async fn store_result(
+    pool: &Pool,
+    id: i32,
+    result: &AssignmentResult,
+    detail: Option<&AssignmentResult>,
+) -> Result<bool, Error> {
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let updated = transaction
+        .execute(
+            "UPDATE submission SET status = $2, finished_at = now() WHERE id = $1 AND status = $3;",
+            &[&id, &SubmissionStatus::Done, &SubmissionStatus::Running],
+        )
+        .await?;
+    if updated == 0 {
+        return Ok(false);
+    }
+    transaction
+        .execute(
+            r#"INSERT INTO result(submission_id, passed, valid, compilation_failed, message, full_result, detail_result)
+                    VALUES($1, $2, $3, $4, $5, $6, $7)
+                    ON CONFLICT (submission_id) DO UPDATE
+                    SET passed = excluded.passed, valid = excluded.valid, compilation_failed = excluded.compilation_failed,
+                        message = excluded.message, full_result = excluded.full_result,
+                        detail_result = excluded.detail_result, created_at = now();"#,
+            &[
+                &id,
+                &result.passed,
+                &result.valid,
+                &result.compilation_failed,
+                &result.message,
+                &Json(result),
+                &detail.map(Json),
+            ],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Cancels the latest submission with the ilias id if it's still waiting or being tested.
+/// Returns its id, attempt and previous status.
+pub async fn cancel_submission(
+    pool: &Pool,
+    ilias_id: &IliasId,
+) -> Result<Option<(i32, i32, SubmissionStatus)>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"UPDATE submission SET status = $2, finished_at = now()
+                    FROM (SELECT id, status FROM submission
+                          WHERE ilias_id = $1 AND status IN ($3, $4)
+                          ORDER BY id DESC
+                          LIMIT 1
+                          FOR UPDATE) AS latest
+                    WHERE submission.id = latest.id
+                    RETURNING submission.id, submission.attempt, latest.status;"#,
+            &[
+                &ilias_id.to_string(),
+                &SubmissionStatus::Cancelled,
+                &SubmissionStatus::Queued,
+                &SubmissionStatus::Running,
+            ],
+        )
+        .await?;
+    Ok(row.map(|row| (row.get(0), row.get(1), row.get(2))))
+}
+
+/// The result of the latest submission with the given ilias id, ```None``` if there is no such submission
+/// or the result was already picked up.
+pub async fn result(pool: &Pool, ilias_id: &IliasId) -> Result<Option<StoredResult>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"SELECT status, full_result
+                    FROM submission LEFT JOIN result
+                    ON result.submission_id = submission.id
+                    WHERE ilias_id = $1 AND acknowledged_at IS NULL
+                    ORDER BY submission.id DESC
+                    LIMIT 1;"#,
+            &[&ilias_id.to_string()],
+        )
+        .await?;
+    Ok(row.map(|row| {
+        let result: Option<Json<AssignmentResult>> = row.get("full_result");
+        match (row.get("status"), result) {
+            (SubmissionStatus::Done, Some(Json(result))) => StoredResult::Done(result),
+            (SubmissionStatus::Cancelled, _) => StoredResult::Cancelled,
+            _ => StoredResult::Pending,
+        }
+    }))
+}
+
+/// The unredacted result of the latest submission with the given ilias id for tutors,
+/// also if the result was already picked up.
+pub async fn result_detail(pool: &Pool, ilias_id: &IliasId) -> Result<Option<StoredResult>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"SELECT status, coalesce(detail_result, full_result) AS detail_result
+                    FROM submission LEFT JOIN result
+                    ON result.submission_id = submission.id
+                    WHERE ilias_id = $1
+                    ORDER BY submission.id DESC
+                    LIMIT 1;"#,
+            &[&ilias_id.to_string()],
+        )
+        .await?;
+    Ok(row.map(|row| {
+        let result: Option<Json<AssignmentResult>> = row.get("detail_result");
+        match (row.get("status"), result) {
+            (SubmissionStatus::Done, Some(Json(result))) => StoredResult::Done(result),
+            (SubmissionStatus::Cancelled, _) => StoredResult::Cancelled,
+            _ => StoredResult::Pending,
+        }
+    }))
+}
+
+const EVENT_SELECT: &str = r#"SELECT submission.id, ilias_id, attempt, status, full_result
+                    FROM submission LEFT JOIN result
+                    ON result.submission_id = submission.id"#;
+
+impl From<&Row> for SubmissionEvent {
+    fn from(row: &Row) -> Self {
+        let result: Option<Json<AssignmentResult>> = row.get("full_result");
+        SubmissionEvent {
+            submission_id: row.get("id"),
+            ilias_id: row.get::<_, String>("ilias_id").into(),
+            attempt: row.get("attempt"),
+            status: row.get("status"),
+            result: result.map(|Json(result)| result),
+        }
+    }
+}
+
+/// The current status of the latest submission with the given ilias id with the same rules as ```result```.
+pub async fn submission_event(
+    pool: &Pool,
+    ilias_id: &IliasId,
+) -> Result<Option<SubmissionEvent>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            format!(
+                r#"{}
+                    WHERE ilias_id = $1 AND acknowledged_at IS NULL
+                    ORDER BY submission.id DESC
+                    LIMIT 1;"#,
+                EVENT_SELECT
+            )
+            .as_str(),
+            &[&ilias_id.to_string()],
+        )
+        .await?;
+    Ok(row.as_ref().map(SubmissionEvent::from))
+}
+
+/// The current status of the submission with the given id.
+pub async fn submission_event_by_id(
+    pool: &Pool,
+    submission_id: i32,
+) -> Result<Option<SubmissionEvent>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            format!("{} WHERE submission.id = $1;", EVENT_SELECT).as_str(),
+            &[&submission_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(SubmissionEvent::from))
+}
+
+/// Marks the results as picked up, returns ```false``` if there was no finished result.
+/// The attempts stay in the history of the student.
+pub async fn acknowledge_result(pool: &Pool, ilias_id: &IliasId) -> Result<bool, Error> {
+    let client = pool.get().await?;
+    let updated = client
+        .execute(
+            r#"UPDATE submission SET acknowledged_at = now()
+                    WHERE ilias_id = $1 AND acknowledged_at IS NULL AND status = $2;"#,
+            &[&ilias_id.to_string(), &SubmissionStatus::Done],
+        )
+        .await?;
+    Ok(updated > 0)
+}
+
+const ATTEMPT_SELECT: &str = r#"SELECT attempt, assignment.uuid, ilias_id, status, submission.created_at, finished_at, full_result
+                    FROM submission JOIN assignment
+                    ON submission.assignment_id = assignment.id
+                    LEFT JOIN result
+                    ON result.submission_id = submission.id"#;
+
+impl From<&Row> for Attempt {
+    fn from(row: &Row) -> Self {
+        let result: Option<Json<AssignmentResult>> = row.get("full_result");
+        Attempt {
+            attempt: row.get("attempt"),
+            assignment_id: row.get("uuid"),
+            ilias_id: row.get::<_, String>("ilias_id").into(),
+            status: row.get("status"),
+            created_at: row.get("created_at"),
+            finished_at: row.get("finished_at"),
+            result: result.map(|Json(result)| result),
+        }
+    }
+}
+
+/// All attempts of the student, optionally only on one assignment.
+pub async fn attempts(
+    pool: &Pool,
+    student_id: &str,
+    assignment_id: Option<&Uuid>,
+) -> Result<Vec<Attempt>, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            format!(
+                r#"{}
+                    WHERE student_id = $1 AND ($2::uuid IS NULL OR assignment.uuid = $2)
+                    ORDER BY assignment.uuid, attempt;"#,
+                ATTEMPT_SELECT
+            )
+            .as_str(),
+            &[&student_id, &assignment_id],
+        )
+        .await?;
+    Ok(rows.iter().map(Attempt::from).collect())
+}
+
+/// The tested attempt of the student with the highest attempt number.
+pub async fn latest_attempt(
+    pool: &Pool,
+    student_id: &str,
+    assignment_id: &Uuid,
+) -> Result<Option<Attempt>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            format!(
+                r#"{}
+                    WHERE student_id = $1 AND assignment.uuid = $2 AND status = $3
+                    ORDER BY attempt DESC
+                    LIMIT 1;"#,
+                ATTEMPT_SELECT
+            )
+            .as_str(),
+            &[&student_id, assignment_id, &SubmissionStatus::Done],
+        )
+        .await?;
+    Ok(row.as_ref().map(Attempt::from))
+}
+
+/// The best tested attempt of the student: passed before valid before the most passed checks.
+/// On a tie the later attempt wins.
+pub async fn best_attempt(
+    pool: &Pool,
+    student_id: &str,
+    assignment_id: &Uuid,
+) -> Result<Option<Attempt>, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            format!(
+                r#"{}
+                    WHERE student_id = $1 AND assignment.uuid = $2 AND status = $3
+                    ORDER BY result.passed DESC, result.valid DESC,
+                        (SELECT count(*) FROM jsonb_array_elements(full_result->'checks') AS c
+                         WHERE (c->>'passed')::boolean) DESC,
+                        attempt DESC
+                    LIMIT 1;"#,
+                ATTEMPT_SELECT
+            )
+            .as_str(),
+            &[&student_id, assignment_id, &SubmissionStatus::Done],
+        )
+        .await?;
+    Ok(row.as_ref().map(Attempt::from))
+}
+
+/// Resets all submissions which were interrupted by a restart back to ```Queued```
+/// and returns how many there were.
+pub async fn requeue_unfinished(pool: &Pool) -> Result<u64, Error> {
+    let client = pool.get().await?;
+    let requeued = client
+        .execute(
+            "UPDATE submission SET status = $1, started_at = NULL WHERE status = $2;",
+            &[&SubmissionStatus::Queued, &SubmissionStatus::Running],
+        )
+        .await?;
+    Ok(requeued)
+}
+
+/// The ids of the submissions which are ```Running``` for more than ```secs``` seconds
+/// although their test is not among the ```running``` ones.
+pub async fn stale_submissions(pool: &Pool, secs: u64, running: &[i32]) -> Result<Vec<i32>, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT id FROM submission
+                    WHERE status = $1 AND started_at < now() - make_interval(secs => $2)
+                    AND id <> ALL($3);"#,
+            &[&SubmissionStatus::Running, &(secs as f64), &running],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("id")).collect())
+}
+
+/// Marks the results which were not picked up within ```hours``` as picked up, returns how many expired.
+pub async fn expire_results(pool: &Pool, hours: i32) -> Result<u64, Error> {
+    let client = pool.get().await?;
+    let expired = client
+        .execute(
+            r#"UPDATE submission SET acknowledged_at = now()
+                    WHERE acknowledged_at IS NULL AND status = $1
+                    AND finished_at < now() - make_interval(hours => $2);"#,
+            &[&SubmissionStatus::Done, &hours],
+        )
+        .await?;
+    Ok(expired)
+}
+
+/// Deletes the tested and the cancelled submissions created before the date with their results,
+/// returns how many were deleted. Submissions which are still queued or running are kept.
+/// The attempt numbers are counted from the stored submissions, so the attempts of the purged ones
+/// start again at 1 and don't count towards the ```max_attempts``` of the assignment anymore.
+pub async fn purge_submissions(pool: &Pool, before: NaiveDate) -> Result<u64, Error> {
+    let client = pool.get().await?;
+    let purged = client
+        .execute(
+            "DELETE FROM submission WHERE status IN ($1, $2) AND created_at < $3::date;",
+            &[
+                &SubmissionStatus::Done,
+                &SubmissionStatus::Cancelled,
+                &before,
+            ],
+        )
+        .await?;
+    Ok(purged)
+}
+
+/// Whether the assignment or exercise which should be exported exists.
+pub async fn export_scope_exists(pool: &Pool, scope: ExportScope) -> Result<bool, Error> {
+    let client = pool.get().await?;
+    let row = match scope {
+        ExportScope::Assignment(uuid) => {
+            client
+                .query_opt("SELECT 1 FROM assignment WHERE uuid = $1;", &[&uuid])
+                .await?
+        }
+        ExportScope::Exercise(id) => {
+            client
+                .query_opt("SELECT 1 FROM exercise WHERE id = $1;", &[&id])
+                .await?
+        }
+    };
+    Ok(row.is_some())
+}
+
+/// All jobs which are waiting or being tested, oldest first.
+pub async fn queue_jobs(pool: &Pool) -> Result<Vec<QueueJob>, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT ilias_id, student_id, attempt, platform, status, attempts, last_error, created_at, next_run_at
+                    FROM submission
+                    WHERE status IN ($1, $2)
+                    ORDER BY created_at, id;"#,
+            &[&SubmissionStatus::Queued, &SubmissionStatus::Running],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| QueueJob {
+            ilias_id: row.get::<_, String>("ilias_id").into(),
+            student_id: row.get("student_id"),
+            attempt: row.get("attempt"),
+            platform: row.get("platform"),
+            status: row.get("status"),
+            attempts: row.get("attempts"),
+            last_error: row.get("last_error"),
+            created_at: row.get("created_at"),
+            next_run_at: row.get("next_run_at"),
+        })
+        .collect())
+}
+
+/// Logs one attempt to deliver the result of a submission to its callback url.
+pub async fn log_delivery(
+    pool: &Pool,
+    submission_id: i32,
+    attempt: i32,
+    status_code: Option<i32>,
+    error: Option<&str>,
+    delivered: bool,
+) -> Result<(), Error> {
+    let client = pool.get().await?;
+    client
+        .execute(
+            r#"INSERT INTO webhook_delivery(submission_id, attempt, status_code, error, delivered)
+                    VALUES($1, $2, $3, $4, $5);"#,
+            &[&submission_id, &attempt, &status_code, &error, &delivered],
+        )
+        .await?;
+    Ok(())
+}
+
+/// All finished results with a callback url which were not delivered yet and have attempts left.
+pub async fn pending_deliveries(pool: &Pool, max_attempts: i32) -> Result<Vec<Delivery>, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT submission.id, ilias_id, callback_url, full_result,
+                        (SELECT count(*)::integer FROM webhook_delivery WHERE submission_id = submission.id) AS attempts
+                    FROM submission JOIN result
+                    ON result.submission_id = submission.id
+                    WHERE callback_url IS NOT NULL AND status = $1
+                    AND NOT EXISTS (SELECT 1 FROM webhook_delivery WHERE submission_id = submission.id AND delivered);"#,
+            &[&SubmissionStatus::Done],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let Json(result): Json<AssignmentResult> = row.get("full_result");
+            Delivery {
+                submission_id: row.get("id"),
+                ilias_id: row.get("ilias_id"),
+                callback_url: row.get("callback_url"),
+                result,
+                attempts: row.get("attempts"),
+            }
+        })
+        .filter(|delivery| delivery.attempts < max_attempts)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attempts_are_limited() {
+        assert!(check_limits(None, 100, None).is_ok());
+        assert!(check_limits(Some(3), 0, None).is_ok());
+        assert!(check_limits(Some(3), 2, None).is_ok());
+        assert!(matches!(
+            check_limits(Some(3), 3, None),
+            Err(Error::AttemptsExhausted(3))
+        ));
+        assert!(matches!(
+            check_limits(Some(3), 4, None),
+            Err(Error::AttemptsExhausted(3))
+        ));
+    }
+
+    #[test]
+    fn cooldown_has_to_be_over() {
+        assert!(check_limits(None, 1, Some(-5)).is_ok());
+        assert!(check_limits(None, 1, Some(0)).is_ok());
+        assert!(matches!(
+            check_limits(None, 1, Some(30)),
+            Err(Error::Cooldown(30))
+        ));
+    }
+
+    #[test]
+    fn exhausted_attempts_are_reported_before_the_cooldown() {
+        assert!(matches!(
+            check_limits(Some(1), 1, Some(30)),
+            Err(Error::AttemptsExhausted(1))
+        ));
+    }
+}
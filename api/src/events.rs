@@ -0,0 +1,146 @@
+//! Pushes the status changes of submissions via Server-Sent Events at ```/api/result/{iliasId}/events```,
+//! so clients don't have to poll the result. The events only live in memory,
+//! the current status is read from the database as soon as a client connects.
+use crate::api::{IliasId, SubmissionEvent};
+use crate::db::{self, SubmissionStatus};
+use crate::handlers::error::Error;
+use crate::state::State;
+use actix_web::HttpResponse;
+use bytes::Bytes;
+use grpc_api::AssignmentResult;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, RecvError};
+use tokio::time::timeout;
+
+/// Slow clients miss events if more are published meanwhile, they read the status from the database again.
+const CHANNEL_CAPACITY: usize = 256;
+/// Proxies close idle connections, so a comment is sent if nothing happened.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Broadcasts the status changes of all submissions to every connected client.
+pub struct Events {
+    sender: broadcast::Sender<SubmissionEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Events { sender }
+    }
+}
+
+impl Events {
+    /// Sends the event to all clients, it's dropped if nobody is listening.
+    pub fn publish(
+        &self,
+        submission_id: i32,
+        ilias_id: &IliasId,
+        attempt: i32,
+        status: SubmissionStatus,
+    ) {
+        self.publish_event(SubmissionEvent {
+            submission_id,
+            ilias_id: ilias_id.clone(),
+            attempt,
+            status,
+            result: None,
+        });
+    }
+
+    pub fn publish_event(&self, event: SubmissionEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Receives all events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SubmissionEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl SubmissionEvent {
+    fn to_sse(&self) -> Bytes {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        Bytes::from(format!(
+            "event: {}\ndata: {}\n\n",
+            format!("{:?}", self.status).to_lowercase(),
+            data
+        ))
+    }
+}
+
+/// Waits until the submission is tested, ```None``` if it took longer than ```wait``` or it was cancelled.
+/// The receiver has to be subscribed before the submission is queued, otherwise a fast result could be missed.
+pub async fn wait_for_result(
+    state: &State,
+    mut receiver: broadcast::Receiver<SubmissionEvent>,
+    submission_id: i32,
+    wait: Duration,
+) -> Result<Option<AssignmentResult>, Error> {
+    let done = async {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    if event.submission_id == submission_id && event.status.is_final() {
+                        return Ok::<_, Error>(event.result);
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {
+                    if let Some(event) =
+                        db::submission_event_by_id(&state.db_pool, submission_id).await?
+                    {
+                        if event.status.is_final() {
+                            return Ok(event.result);
+                        }
+                    }
+                }
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    };
+    timeout(wait, done).await.unwrap_or(Ok(None))
+}
+
+/// Streams the current status of the latest submission with the ilias id and every change of it.
+/// If the ilias id is submitted again the stream follows the new submission, events of older ones are dropped.
+/// The stream ends after the result was sent or the submission was cancelled.
+pub async fn stream(state: &State, ilias_id: IliasId) -> Result<HttpResponse, Error> {
+    // subscribe first, so no event between reading the status and listening is lost
+    let mut receiver = state.events.sender.subscribe();
+    let current = db::submission_event(&state.db_pool, &ilias_id)
+        .await?
+        .ok_or_else(|| Error::NotFoundIliasId(ilias_id.clone()))?;
+    let state = state.clone();
+    let body = async_stream::stream! {
+        let mut last = current;
+        yield Ok::<_, actix_web::Error>(last.to_sse());
+        while !last.status.is_final() {
+            match timeout(KEEP_ALIVE, receiver.recv()).await {
+                Err(_) => yield Ok(Bytes::from_static(b": keep-alive\n\n")),
+                Ok(Ok(event)) => {
+                    if event.ilias_id == ilias_id && event.submission_id >= last.submission_id {
+                        last = event;
+                        yield Ok(last.to_sse());
+                    }
+                }
+                Ok(Err(RecvError::Lagged(_))) => {
+                    match db::submission_event(&state.db_pool, &ilias_id).await {
+                        Ok(Some(event)) => {
+                            if event.submission_id != last.submission_id
+                                || event.status != last.status
+                            {
+                                last = event;
+                                yield Ok(last.to_sse());
+                            }
+                        }
+                        _ => break,
+                    }
+                }
+                Ok(Err(RecvError::Closed)) => break,
+            }
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(body))
+}
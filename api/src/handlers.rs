@@ -3,6 +3,8 @@
 pub mod auth;
 pub mod error;
 
+/// Handler for all ```delete``` request.
+pub mod delete;
 /// Handler for all ```get``` request.
 pub mod get;
 /// Handler for all ```post``` request.
@@ -1,32 +1,149 @@
-//! Implements **HTTP basic access authentication**. Stores the credentials like username and password(encrypted) provided by environment variables in memory.
+//! Authentication of the API clients. Each client stored in the ```api_client``` table sends its own **bearer token** and
+//! may only use the routes of its scopes. Clients are managed in the Assignment Manager.
+//! The old **HTTP basic access authentication** with the credentials provided by environment variables still works if a password is set,
+//! but only for the routes which existed before the API clients: submitting, reading results and listing assignments.
 
 use crate::handlers::error::Error;
 use crate::state::State;
-use actix_web::dev::ServiceRequest;
-use actix_web::web;
-use actix_web_httpauth::extractors::basic::BasicAuth;
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::http::header::Header;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use actix_web_httpauth::extractors::AuthExtractor;
+use actix_web_httpauth::headers::authorization::{Authorization, Basic, Bearer};
+use futures::future::{ready, Ready};
+use postgres_types::{FromSql, ToSql};
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 
-pub async fn handle_basic_auth(
+/// What an API client is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, ToSql, FromSql, Serialize, schemars::JsonSchema)]
+#[postgres(name = "api_scope")]
+pub enum ApiScope {
+    /// Upload submissions.
+    Submit,
+    /// Get and drop results.
+    ReadResults,
+    ListAssignments,
+    /// Everything, including the queue.
+    Admin,
+}
+
+/// The authenticated client, available in each handler.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    pub name: String,
+    scopes: Vec<ApiScope>,
+}
+
+impl ApiClient {
+    /// Fails with ```Forbidden``` if the client lacks the scope. ```Admin``` grants every scope.
+    pub fn require(&self, scope: ApiScope) -> Result<(), Error> {
+        if self.scopes.contains(&scope) || self.scopes.contains(&ApiScope::Admin) {
+            Ok(())
+        } else {
+            Err(Error::Forbidden(scope))
+        }
+    }
+}
+
+impl FromRequest for ApiClient {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<ApiClient>()
+                .cloned()
+                .ok_or(Error::Unauthorized),
+        )
+    }
+}
+
+/// Either a bearer token or the legacy basic auth credentials.
+pub enum ClientCredentials {
+    Bearer(String),
+    Basic(Basic),
+}
+
+impl AuthExtractor for ClientCredentials {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_service_request(req: &ServiceRequest) -> Self::Future {
+        let credentials = Authorization::<Bearer>::parse(req)
+            .map(|auth| ClientCredentials::Bearer(auth.into_scheme().token().to_string()))
+            .or_else(|_| {
+                Authorization::<Basic>::parse(req)
+                    .map(|auth| ClientCredentials::Basic(auth.into_scheme()))
+            })
+            .map_err(|_| Error::Unauthorized.into_actix_web_err());
+        ready(credentials)
+    }
+}
+
+pub async fn handle_auth(
     req: ServiceRequest,
-    credentials: BasicAuth,
+    credentials: ClientCredentials,
 ) -> Result<ServiceRequest, actix_web::Error> {
     let state: web::Data<State> = req.app_data().unwrap();
-    match credentials.password() {
-        Some(cred) => {
+    let client = match credentials {
+        ClientCredentials::Bearer(token) => find_client(&state, &token)
+            .await
+            .map_err(|e| e.into_actix_web_err())?,
+        ClientCredentials::Basic(basic) => legacy_client(&state, &basic)?,
+    };
+    state
+        .rate_limiter
+        .check(&client.name)
+        .map_err(|wait| Error::RateLimited((wait.as_secs_f64().ceil() as u64).max(1)))?;
+    log::info!("{} {} by client {}", req.method(), req.path(), &client.name);
+    req.extensions_mut().insert(client);
+    Ok(req)
+}
+
+/// Looks up the not revoked client by the hash of its token.
+async fn find_client(state: &State, token: &str) -> Result<ApiClient, Error> {
+    let token_hash = sha2::Sha256::digest(token.as_bytes()).to_vec();
+    let db_client = state.db_pool.get().await?;
+    let row = db_client
+        .query_opt(
+            "SELECT name, scopes FROM api_client WHERE token_hash = $1 AND revoked_at IS NULL;",
+            &[&token_hash],
+        )
+        .await?
+        .ok_or(Error::Unauthorized)?;
+    Ok(ApiClient {
+        name: row.get("name"),
+        scopes: row.get("scopes"),
+    })
+}
+
+/// The scopes of the basic auth user.
+const LEGACY_SCOPES: &[ApiScope] = &[
+    ApiScope::Submit,
+    ApiScope::ReadResults,
+    ApiScope::ListAssignments,
+];
+
+/// The basic auth user from the environment variables, only if a password is configured.
+fn legacy_client(state: &State, credentials: &Basic) -> Result<ApiClient, actix_web::Error> {
+    match (credentials.password(), state.credentials.password()) {
+        (Some(cred), Some(password)) => {
             let pwd = sha2::Sha256::digest(cred.as_bytes()).to_vec();
-            if credentials.user_id() == state.credentials.username()
-                && pwd == state.credentials.password()
-            {
-                Ok(req)
+            if credentials.user_id() == state.credentials.username() && pwd == password {
+                Ok(ApiClient {
+                    name: format!("{} (basic auth)", credentials.user_id()),
+                    scopes: LEGACY_SCOPES.to_vec(),
+                })
             } else {
                 Err(Error::Unauthorized.into_actix_web_err())
             }
         }
-        None => Err(Error::Unauthorized.into_actix_web_err()),
+        _ => Err(Error::Unauthorized.into_actix_web_err()),
     }
 }
 
@@ -34,47 +151,50 @@ pub async fn handle_basic_auth(
 fn default_user() -> String {
     String::from("user")
 }
-/// Default password: ```wasd4221```
-fn default_pwd() -> String {
-    String::from("wasd4221")
-}
-/// Credentials stored in memory.
+/// Credentials stored in memory, without a password basic auth is disabled.
 #[derive(Debug, serde::Deserialize)]
 pub struct Credentials {
     username: String,
-    password: Sha256,
+    password: Option<Sha256>,
 }
 
 /// Credentials provided by the environment prefixed by "DEPP_API_".  
 /// Example:  
 /// ```DEPP_API_USERNAME="tom"```  
-/// ```DEPP_API_USERNAME="passwordone"```
+/// ```DEPP_API_PASSWORD="passwordone"```
 #[derive(Debug, serde::Deserialize)]
 pub struct CredentialsEnv {
     #[serde(default = "default_user")]
     username: String,
-    #[serde(default = "default_pwd")]
-    password: String,
+    password: Option<String>,
 }
 /// Sha256 Wrapper that supports serialisation.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sha256(#[serde(with = "hex_serde")] pub Vec<u8>);
 
 impl Credentials {
+    pub fn new(username: &str, password: Option<&str>) -> Self {
+        Credentials {
+            username: username.into(),
+            password: password.map(|pwd| pwd.to_string().try_into().unwrap()),
+        }
+    }
     pub fn username(&self) -> &str {
         &self.username
     }
-    pub fn password(&self) -> Vec<u8> {
-        self.password.0.to_vec()
+    pub fn password(&self) -> Option<&[u8]> {
+        self.password.as_ref().map(|pwd| pwd.0.as_slice())
     }
 }
 
 pub fn get_credentials() -> Credentials {
     match envy::prefixed("DEPP_API_").from_env::<CredentialsEnv>() {
-        Ok(cred) => Credentials {
-            username: cred.username,
-            password: cred.password.try_into().unwrap(),
-        },
+        Ok(cred) => {
+            if cred.password.is_none() {
+                log::info!("DEPP_API_PASSWORD is not set, basic auth is disabled");
+            }
+            Credentials::new(&cred.username, cred.password.as_deref())
+        }
         Err(err) => panic!("Bad credentials! err: {}", err),
     }
 }
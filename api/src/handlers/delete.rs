@@ -0,0 +1,32 @@
+use crate::api::IliasId;
+use crate::db::{self, SubmissionStatus};
+use crate::handlers::auth::{ApiClient, ApiScope};
+use crate::handlers::error::Error;
+use crate::state::State;
+use actix_web::{web, HttpResponse};
+
+/// Cancels the latest submission with the IliasId if it's still waiting or being tested.
+/// A running test is aborted, the testing server removes its container and the slot is free for the next one.
+/// Returns 404 if there is no such submission or it was already tested.
+pub async fn cancel_submission(
+    state: web::Data<State>,
+    para: web::Path<String>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::Submit)?;
+    let ilias_id: IliasId = para.into_inner().into();
+    let (submission_id, attempt, status) = db::cancel_submission(&state.db_pool, &ilias_id)
+        .await?
+        .ok_or_else(|| Error::NotFoundIliasId(ilias_id.clone()))?;
+    if status == SubmissionStatus::Running && state.queue.abort(submission_id) {
+        log::info!("Aborted the running test of {}", &ilias_id);
+    }
+    log::info!("Cancelled submission {} of {}", submission_id, &ilias_id);
+    state.events.publish(
+        submission_id,
+        &ilias_id,
+        attempt,
+        SubmissionStatus::Cancelled,
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,20 +1,21 @@
 //! Error handling using [failure](https://docs.rs/crate/failure) as error library.
 use crate::api::{IliasId, SubmissionExample};
+use crate::handlers::auth::ApiScope;
 use crate::rpc_conf::RpcMeta;
 use actix_web::error::JsonPayloadError;
-use actix_web::http::StatusCode;
+use actix_web::http::{header, StatusCode};
 use actix_web::{HttpResponse, ResponseError};
 use grpc_api::AssignmentId;
 use uuid::Uuid;
 
 /// No valid ```JSON``` was send
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, schemars::JsonSchema)]
 pub struct ErrJson {
     msg: String,
 }
 
 /// Not valid [Submission](../../api/struct.Submission.html) was send was send.
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, schemars::JsonSchema)]
 pub struct ErrSubmission {
     msg: String,
     example: SubmissionExample,
@@ -29,9 +30,19 @@ impl From<&Error> for ErrJson {
 impl ResponseError for Error {
     fn status_code(&self) -> StatusCode {
         match self {
-            Error::DuplicateIliasId => StatusCode::CONFLICT,
-            Error::NotFoundIliasId(_) | Error::NotAssignment(_) => StatusCode::NOT_FOUND,
+            Error::NotFoundIliasId(_)
+            | Error::NotFoundStudent(_)
+            | Error::NotAssignment(_)
+            | Error::NotExercise(_)
+            | Error::NotNode(_) => StatusCode::NOT_FOUND,
+            Error::Cancelled(_) => StatusCode::GONE,
+            Error::Draining => StatusCode::SERVICE_UNAVAILABLE,
+            Error::RateLimited(_) | Error::Cooldown(_) | Error::AttemptsExhausted(_) => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
             Error::BadRequest => StatusCode::BAD_REQUEST,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
             Error::Submission(_e) => StatusCode::BAD_REQUEST,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -42,10 +53,22 @@ impl ResponseError for Error {
         let code = self.status_code();
         let mut response = HttpResponse::build(code);
         log::error!("{}", &self);
+        if let Some(secs) = self.retry_after() {
+            response.header(header::RETRY_AFTER, secs.to_string());
+        }
         match self {
-            Error::DuplicateIliasId | Error::NotFoundIliasId(_) | Error::NotAssignment(_) => {
-                response.json(err)
-            }
+            Error::NotFoundIliasId(_)
+            | Error::Cancelled(_)
+            | Error::NotFoundStudent(_)
+            | Error::NotAssignment(_)
+            | Error::NotExercise(_)
+            | Error::NotNode(_)
+            | Error::Draining
+            | Error::RateLimited(_)
+            | Error::Cooldown(_)
+            | Error::AttemptsExhausted(_)
+            | Error::Unauthorized
+            | Error::Forbidden(_) => response.json(err),
             Error::Submission(_e) => response.json(ErrSubmission {
                 msg: self.to_string(),
                 example: SubmissionExample::new(
@@ -63,13 +86,30 @@ impl ResponseError for Error {
 pub enum Error {
     #[fail(display = "Generic Error {}", _0)]
     General(Box<dyn std::error::Error + Sync + Send>),
-    #[fail(display = "Duplicate IliasID")]
-    DuplicateIliasId,
     // maybe return the ilias id back
     #[fail(display = "No Results not found for given IliasID: {}", _0)]
     NotFoundIliasId(IliasId),
+    #[fail(display = "The submission with the IliasID {} was cancelled", _0)]
+    Cancelled(IliasId),
+    #[fail(display = "No Results not found for given student: {}", _0)]
+    NotFoundStudent(String),
     #[fail(display = "No Results not found for given AssignmentID: {}", _0)]
     NotAssignment(AssignmentId),
+    #[fail(display = "No exercise found for given ExerciseID: {}", _0)]
+    NotExercise(i32),
+    #[fail(display = "No testing server found for given url: {}", _0)]
+    NotNode(url::Url),
+    #[fail(display = "The API is shutting down, try again later")]
+    Draining,
+    #[fail(display = "Too many requests, retry in {} seconds", _0)]
+    RateLimited(u64),
+    #[fail(
+        display = "The student has to wait {} seconds before submitting again",
+        _0
+    )]
+    Cooldown(u64),
+    #[fail(display = "The student has used all {} attempts of the assignment", _0)]
+    AttemptsExhausted(i32),
     #[fail(
         display = "Incorrect json received error: {}. Maybe there are some fields missing or the types does not match.",
         _0
@@ -79,6 +119,8 @@ pub enum Error {
     BadRequest,
     #[fail(display = " Wrong credentials")]
     Unauthorized,
+    #[fail(display = "The API client is missing the scope {:?}", _0)]
+    Forbidden(ApiScope),
     #[fail(display = "{}", _0)]
     Submission(BadSubmission),
 }
@@ -119,6 +161,14 @@ pub fn sub_extractor(ae: actix_web::error::Error) -> Error {
 }
 
 impl Error {
+    /// The seconds for the ```Retry-After``` header, exhausted attempts can't be retried.
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            Error::RateLimited(secs) | Error::Cooldown(secs) => Some(*secs),
+            _ => None,
+        }
+    }
+
     pub(crate) fn into_actix_web_err(self) -> actix_web::Error {
         actix_web::error::ErrorUnauthorized(self.to_string())
     }
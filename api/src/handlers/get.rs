@@ -1,10 +1,16 @@
 //! This is an REST API using only ```JSON```.
 
-use crate::api::{AssignmentShort, Status, Version};
+use crate::api::{AssignmentShort, AttemptFilter, Status, Version};
+use crate::db::{self, StoredResult};
+use crate::events;
+use crate::handlers::auth::{ApiClient, ApiScope};
 use crate::handlers::error::Error;
 use crate::state::State;
+use actix_web::http::header::CONTENT_DISPOSITION;
 use actix_web::http::{Method, StatusCode};
 use actix_web::{web, HttpRequest, HttpResponse};
+use db_lib::export::{ExportFormat, ExportQuery, ExportScope};
+use grpc_api::AssignmentId;
 use tokio_postgres::row::Row;
 /// Return test result for a script or
 /// 404 if the given IliasId does not exists.
@@ -12,36 +18,182 @@ pub async fn get_result(
     req: HttpRequest,
     state: web::Data<State>,
     para: web::Path<String>,
+    client: ApiClient,
 ) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
     let id = para.into_inner().into();
     match req.method().to_owned() {
         Method::POST => {
-            if state.pending_results.remove(&id).is_some() {
+            if db::acknowledge_result(&state.db_pool, &id).await? {
                 Ok(HttpResponse::Ok().body(""))
             } else {
                 Err(Error::NotFoundIliasId(id))
             }
         }
-        Method::GET => {
-            let state = state.into_inner();
-            if state.to_test_assignments.read().await.contains(&id) {
-                return Ok(HttpResponse::new(StatusCode::ACCEPTED));
-            }
-            if let Some(ret) = state
-                .pending_results
-                .get(&id)
-                .map(|ret| ret.value().clone())
-            {
-                Ok(HttpResponse::Ok().json(ret))
-            } else {
-                Err(Error::NotFoundIliasId(id))
-            }
-        }
+        Method::GET => match db::result(&state.db_pool, &id).await? {
+            Some(StoredResult::Pending) => Ok(HttpResponse::new(StatusCode::ACCEPTED)),
+            Some(StoredResult::Done(ret)) => Ok(HttpResponse::Ok().json(ret)),
+            Some(StoredResult::Cancelled) => Err(Error::Cancelled(id)),
+            None => Err(Error::NotFoundIliasId(id)),
+        },
         _ => Err(Error::BadRequest),
     }
 }
+/// Return the unredacted test result for tutors, regardless of the feedback level of the assignment, or
+/// 404 if the given IliasId does not exists.
+pub async fn get_result_detail(
+    state: web::Data<State>,
+    para: web::Path<String>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::Admin)?;
+    let id = para.into_inner().into();
+    match db::result_detail(&state.db_pool, &id).await? {
+        Some(StoredResult::Pending) => Ok(HttpResponse::new(StatusCode::ACCEPTED)),
+        Some(StoredResult::Done(ret)) => Ok(HttpResponse::Ok().json(ret)),
+        Some(StoredResult::Cancelled) => Err(Error::Cancelled(id)),
+        None => Err(Error::NotFoundIliasId(id)),
+    }
+}
+/// Streams the status changes and the result of the latest submission with the IliasId via Server-Sent Events or
+/// 404 if the given IliasId does not exists.
+pub async fn get_result_events(
+    state: web::Data<State>,
+    para: web::Path<String>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
+    events::stream(&state, para.into_inner().into()).await
+}
+/// Return all attempts of a student with their results, optionally only on one assignment.
+pub async fn get_attempts(
+    state: web::Data<State>,
+    para: web::Path<String>,
+    filter: web::Query<AttemptFilter>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
+    let attempts = db::attempts(
+        &state.db_pool,
+        &para.into_inner(),
+        filter.assignment_id.as_ref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(attempts))
+}
+/// Return the best tested attempt of a student on an assignment or
+/// 404 if no attempt was tested yet.
+pub async fn get_best_attempt(
+    state: web::Data<State>,
+    para: web::Path<(String, AssignmentId)>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
+    let (student_id, assignment_id) = para.into_inner();
+    match db::best_attempt(&state.db_pool, &student_id, &assignment_id).await? {
+        Some(attempt) => Ok(HttpResponse::Ok().json(attempt)),
+        None => Err(Error::NotFoundStudent(student_id)),
+    }
+}
+/// Return the latest tested attempt of a student on an assignment or
+/// 404 if no attempt was tested yet.
+pub async fn get_latest_attempt(
+    state: web::Data<State>,
+    para: web::Path<(String, AssignmentId)>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
+    let (student_id, assignment_id) = para.into_inner();
+    match db::latest_attempt(&state.db_pool, &student_id, &assignment_id).await? {
+        Some(attempt) => Ok(HttpResponse::Ok().json(attempt)),
+        None => Err(Error::NotFoundStudent(student_id)),
+    }
+}
+/// Return all tested attempts of an assignment as ```JSON``` or ```CSV``` or
+/// 404 if the given AssignmentId does not exists.
+pub async fn get_assignment_export(
+    state: web::Data<State>,
+    para: web::Path<AssignmentId>,
+    query: web::Query<ExportQuery>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
+    let id = para.into_inner();
+    let scope = ExportScope::Assignment(id);
+    if !db::export_scope_exists(&state.db_pool, scope).await? {
+        return Err(Error::NotAssignment(id));
+    }
+    export(&state, scope, &query, format!("assignment-{}", id)).await
+}
+/// Return all tested attempts of all assignments of an exercise as ```JSON``` or ```CSV``` or
+/// 404 if the given exercise does not exists.
+pub async fn get_exercise_export(
+    state: web::Data<State>,
+    para: web::Path<i32>,
+    query: web::Query<ExportQuery>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ReadResults)?;
+    let id = para.into_inner();
+    let scope = ExportScope::Exercise(id);
+    if !db::export_scope_exists(&state.db_pool, scope).await? {
+        return Err(Error::NotExercise(id));
+    }
+    export(&state, scope, &query, format!("exercise-{}", id)).await
+}
+
+async fn export(
+    state: &State,
+    scope: ExportScope,
+    query: &ExportQuery,
+    name: String,
+) -> Result<HttpResponse, Error> {
+    let rows = db_lib::export::export(&state.db_pool, scope, query).await?;
+    Ok(match query.format {
+        ExportFormat::Json => HttpResponse::Ok()
+            .header(
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.json\"", name),
+            )
+            .json(rows),
+        ExportFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .header(
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.csv\"", name),
+            )
+            .body(db_lib::export::to_csv(&rows)),
+    })
+}
+/// Return the details of an assignment or
+/// 404 if the given AssignmentId does not exists.
+pub async fn get_assignment(
+    state: web::Data<State>,
+    para: web::Path<AssignmentId>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ListAssignments)?;
+    let id = para.into_inner();
+    match db::assignment_detail(&state.db_pool, &id).await? {
+        Some(assignment) => Ok(HttpResponse::Ok().json(assignment)),
+        None => Err(Error::NotAssignment(id)),
+    }
+}
+/// Return all exercises with their active assignments.
+pub async fn get_exercises(
+    state: web::Data<State>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ListAssignments)?;
+    let exercises = db::exercises(&state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(exercises))
+}
 /// Return all assignments which currently active.
-pub async fn get_assignments(state: web::Data<State>) -> Result<HttpResponse, Error> {
+pub async fn get_assignments(
+    state: web::Data<State>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::ListAssignments)?;
     let client = &state.db_pool.get().await?;
     let query = r#"SELECT format('%s/%s (%s)', exercise.description, assignment_name, script_type) as name, uuid
                         FROM assignment JOIN exercise
@@ -74,12 +226,23 @@ pub async fn version() -> HttpResponse {
         version: env!("CARGO_PKG_VERSION"),
     })
 }
-/// Return status of booth RPC Endpoints.
+/// Return the public key the results are signed with, needs no authentication.
+pub async fn public_key(state: web::Data<State>) -> HttpResponse {
+    HttpResponse::Ok().json(state.signer.public_key())
+}
+/// Return all submissions which are waiting in the queue or currently being tested.
+pub async fn get_queue(state: web::Data<State>, client: ApiClient) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::Admin)?;
+    let jobs = db::queue_jobs(&state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(jobs))
+}
+/// Return status of booth RPC Endpoints and of each testing server.
 pub async fn status(state: web::Data<State>) -> HttpResponse {
     let rpc = &state.rpc_conf;
     let status = rpc.status().await;
     HttpResponse::Ok().json(Status {
         linux_rpc_status: status.linux,
         windows_rpc_status: status.windows,
+        nodes: status.nodes,
     })
 }
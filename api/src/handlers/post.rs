@@ -1,96 +1,148 @@
-use crate::api::Submission;
-use crate::handlers::error::{sub_extractor, Error};
+use crate::api::{
+    DrainNode, Submission, SubmissionCreated, SubmitOptions, Verification, VerifyRequest,
+};
+use crate::db::{self, SubmissionStatus};
+use crate::handlers::auth::{ApiClient, ApiScope};
+use crate::handlers::error::{sub_extractor, BadSubmission, Error};
 use crate::state::State;
-use actix_web::{web, HttpResponse};
-use deadpool_postgres::Pool;
-use grpc_api::test_client::TestClient;
-use grpc_api::{Assignment, AssignmentMsg, AssignmentResult};
+use crate::{events, queue};
+use actix_web::{http, web, HttpResponse};
+use grpc_api::DrainRequest;
 use std::time::Duration;
-use tokio::time::timeout;
-/*
-use tonic::transport::Channel;
-*/
-use uuid::Uuid;
 
-/// Run the script test by calling a method via RPC.
-/// Spawns for each test in a new "thread".  
-/// Storing the test result in the global thread safe HasMap ```pending_results```.
+/// Max size of the submission body, the Base64 encoded zip archives are the largest part.
+/// The testing server checks the unpacked size and the number of files.
+pub const MAX_SUBMISSION_BYTES: usize = 4 * 1024 * 1024;
+/// Every zip archive starts with a local file header.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+/// Upper bound of the ```wait``` query parameter, so requests don't hang forever.
+const MAX_WAIT_SECS: u64 = 60;
+
+/// Checks the attestation of a result and, if the source is given, that it was tested with this source.
+pub async fn verify_result(
+    state: web::Data<State>,
+    json: web::Json<VerifyRequest>,
+    _client: ApiClient,
+) -> HttpResponse {
+    let request = json.into_inner();
+    let source = request.source.as_ref().map(|source| source.0.as_slice());
+    let verification = match state.signer.verify(&request.result, source) {
+        Ok(()) => Verification {
+            valid: true,
+            reason: None,
+        },
+        Err(e) => Verification {
+            valid: false,
+            reason: Some(e.to_string()),
+        },
+    };
+    HttpResponse::Ok().json(verification)
+}
+
+/// Starts or stops draining a testing server. A draining server gets no new tests and finishes the running ones,
+/// it's empty once ```outstanding``` of the returned status is 0.
+pub async fn drain_node(
+    state: web::Data<State>,
+    json: web::Json<DrainNode>,
+    client: ApiClient,
+) -> Result<HttpResponse, Error> {
+    client.require(ApiScope::Admin)?;
+    let drain = json.into_inner();
+    let node = state
+        .rpc_conf
+        .node(&drain.rpc_url)
+        .ok_or_else(|| Error::NotNode(drain.rpc_url.clone()))?;
+    let mut rpc = queue::connect(&node.meta).await?;
+    let reply = rpc
+        .drain(tonic::Request::new(DrainRequest {
+            draining: Some(drain.draining),
+        }))
+        .await?
+        .into_inner();
+    node.set_draining(reply.draining);
+    log::info!(
+        "{} set draining of {} to {}, {} tests are running",
+        &client.name,
+        &drain.rpc_url,
+        reply.draining,
+        reply.running
+    );
+    Ok(HttpResponse::Ok().json(node.node_status()))
+}
+
+/// Stores the submission in the database where it waits in the [queue](../../queue/index.html) to be tested via RPC.
+/// Each submission is a new attempt of the student on the assignment, returns the attempt number.
+/// Accepted even while the testing server is offline, the result is stored in the database as well.
+/// If a ```callbackUrl``` is given, the result is also POSTed to it.
+/// With ```?wait=<seconds>``` it waits for the result and returns it inline with 200,
+/// or 202 with the location of the result if the test takes longer.
 pub async fn add_submission(
     state: web::Data<State>,
+    options: web::Query<SubmitOptions>,
     json: Result<web::Json<Submission>, actix_web::error::Error>,
+    client: ApiClient,
 ) -> Result<HttpResponse, Error> {
-    let submission = json.map_err(sub_extractor)?;
-    let assignment = db_assignment(&state.db_pool, &submission.assignment_id)
-        .await
-        .map_err(|_| Error::NotAssignment(submission.assignment_id))?;
-    if state.pending_results.contains_key(&submission.ilias_id)
-        || state
-            .to_test_assignments
-            .read()
-            .await
-            .contains(&submission.ilias_id)
-    {
-        return Err(Error::DuplicateIliasId);
+    client.require(ApiScope::Submit)?;
+    if state.queue.is_draining() {
+        return Err(Error::Draining);
     }
-    let rpc = state.rpc_conf.meta(&assignment.script_type.into()).clone();
-    let rpc_url = rpc.rpc_url.to_string();
-    log::info!("Calling RPC Endpoint: {} ", &rpc_url);
-    let mut client = timeout(Duration::from_secs(1), TestClient::connect(rpc_url))
-        .await
-        .map_err(|_| Error::RpcOffline {
-            reason: rpc.clone(),
-        })?
-        .map_err(|_| Error::RpcOffline {
-            reason: rpc.clone(),
-        })?;
-    tokio::task::spawn(async move {
-        let state = state.into_inner();
-        let ilias_id = &submission.ilias_id;
+    let submission = json.map_err(sub_extractor)?;
+    if let Some(url) = &submission.callback_url {
         state
-            .to_test_assignments
-            .write()
-            .await
-            .insert(ilias_id.clone());
-        let request = tonic::Request::new(AssignmentMsg {
-            assignment: Some(assignment),
-            code_to_test: submission.source_code.0.clone(),
-        });
-
-        match client.run_test(request).await {
-            Ok(response) => {
-                state
-                    .pending_results
-                    .insert(ilias_id.clone(), response.into_inner());
-            }
-            Err(e) => {
-                state.pending_results.insert(
-                    ilias_id.clone(),
-                    AssignmentResult {
-                        passed: false,
-                        message: Some(e.to_string()),
-                        valid: false,
-                    },
-                );
-            }
+            .webhook
+            .check_callback_url(url)
+            .map_err(BadSubmission::Other)?;
+    }
+    if let Some(zip) = &submission.source_zip {
+        if !zip.0.starts_with(ZIP_MAGIC) {
+            return Err(BadSubmission::Other("sourceZip must be a zip archive".into()).into());
         }
-        state.to_test_assignments.write().await.remove(&ilias_id);
-    });
-    Ok(HttpResponse::Created().body(""))
-}
-/// Query one assigment from the database.
-async fn db_assignment(pool: &Pool, uuid: &Uuid) -> Result<Assignment, Error> {
-    let client = pool.get().await?;
-    let stmt = client
-        .prepare(
-            r#"SELECT assignment_name, script_type, include_files, solution, args, compare_fs_solution, 
-                                compare_stdout_solution, regex, regex_check_mode, sort_stdout, custom_script
-                    FROM assignment 
-                    WHERE assignment.uuid = $1;"#,
-        )
-        .await?;
-    let row = client
-        .query_one(&stmt, &[uuid])
+    } else if submission.source_code.0.is_empty() {
+        return Err(BadSubmission::Other("sourceCode or sourceZip is required".into()).into());
+    }
+    let assignment = db::assignment(&state.db_pool, &submission.assignment_id)
         .await
-        .map_err(|_| Error::NotAssignment(*uuid))?;
-    Ok(Assignment::from(&row))
+        .map_err(|_| Error::NotAssignment(submission.assignment_id))?;
+    let rpc = state.rpc_conf.pool(&assignment.script_type.into());
+    let student_id = submission
+        .student_id
+        .clone()
+        .unwrap_or_else(|| submission.ilias_id.to_string());
+    // subscribe before queuing, a fast test could finish before we listen otherwise
+    let receiver = options.wait.map(|_| state.events.subscribe());
+    let (id, attempt) =
+        db::insert_submission(&state.db_pool, &submission, &student_id, rpc.platform).await?;
+    log::info!(
+        "Queued submission {} (attempt {} of {}) for {}",
+        id,
+        attempt,
+        &student_id,
+        rpc.platform
+    );
+    state
+        .events
+        .publish(id, &submission.ilias_id, attempt, SubmissionStatus::Queued);
+    state.queue.notify(rpc.platform);
+    let mut created = SubmissionCreated {
+        ilias_id: submission.ilias_id.clone(),
+        student_id,
+        attempt,
+        result: None,
+    };
+    let (receiver, wait) = match (receiver, options.wait) {
+        (Some(receiver), Some(wait)) => (receiver, wait.min(MAX_WAIT_SECS)),
+        _ => return Ok(HttpResponse::Created().json(created)),
+    };
+    created.result =
+        events::wait_for_result(&state, receiver, id, Duration::from_secs(wait)).await?;
+    if created.result.is_some() {
+        Ok(HttpResponse::Ok().json(created))
+    } else {
+        Ok(HttpResponse::Accepted()
+            .header(
+                http::header::LOCATION,
+                format!("/api/result/{}", submission.ilias_id),
+            )
+            .json(created))
+    }
 }
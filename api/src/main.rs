@@ -1,53 +1,101 @@
 //! This is the REST API which uses `JSON`. It communicates via RPC to the RPC servers (Windows | Linux) by retrieving all assignments from the PostgreSQL Database.
 //! [actix-web](https://github.com/actix/actix-web) is used for the http component with [tokio](https://github.com/tokio-rs/tokio) as the async runtime.
 mod api;
+mod attestation;
 mod base64;
+mod db;
+mod events;
 mod handlers;
+mod openapi;
+mod queue;
+mod rate_limit;
+mod retention;
 mod routes;
 mod state;
+mod webhook;
 use actix_cors::Cors;
 use actix_web::middleware::Logger;
 use actix_web::{middleware, App, HttpServer};
-use actix_web_httpauth::middleware::HttpAuthentication;
-use failure::_core::time::Duration;
 use futures::prelude::*;
-use handlers::{auth::get_credentials, auth::handle_basic_auth};
+use handlers::auth::get_credentials;
 mod rpc_conf;
 use state::State;
+use std::time::Duration;
+
+/// How often all testing servers are checked, unhealthy ones are taken out of rotation.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 /// Real main function. Starting the middleware and global initialization the state.
 async fn run() -> Result<(), failure::Error> {
     std::env::set_var("RUST_LOG", "api=info,error,warn,actix_web=info,warn");
     let db_pool = db_lib::connect_migrate().await?;
     let env_conf = rpc_conf::get_config()?;
-    let state = State::new(env_conf, get_credentials(), db_pool);
+    env_logger::init();
+    let state = State::new(
+        env_conf,
+        get_credentials(),
+        webhook::get_config()?,
+        queue::get_config()?,
+        attestation::ResultSigner::new(attestation::get_config()?)?,
+        rate_limit::get_config()?,
+        db_pool,
+    );
+    queue::start(&state).await?;
+    retention::start(&state, retention::get_config()?);
     let c_state = state.clone();
     tokio::task::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(60 * 10));
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
         while let Some(_) = interval.next().await {
-            c_state.pending_results.shrink_to_fit();
+            c_state.rpc_conf.status().await;
         }
     });
-    env_logger::init();
-    HttpServer::new(move || {
+    webhook::resume(&state).await?;
+    let swagger_ui = openapi::get_config()?.swagger_ui;
+    let c_state = state.clone();
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(middleware::Compress::default())
             .wrap(Logger::default())
-            .wrap(HttpAuthentication::basic(handle_basic_auth))
-            .configure(routes::register_routes)
+            .configure(move |cfg| routes::register_routes(cfg, swagger_ui))
             .wrap(
                 Cors::new()
-                    .allowed_methods(vec!["GET", "POST"])
+                    .allowed_methods(vec!["GET", "POST", "DELETE"])
                     .send_wildcard()
                     .finish(),
             )
             .data(state.clone())
     })
     .bind("0.0.0.0:6000")?
-    .run()
-    .await?;
+    // the queue is drained before the server stops, so results can still be fetched meanwhile
+    .disable_signals()
+    .run();
+    let c_server = server.clone();
+    actix_rt::spawn(async move {
+        shutdown_signal().await;
+        queue::drain(&c_state).await;
+        log::info!("Stopping the http server");
+        c_server.stop(true).await;
+    });
+    server.await?;
     Ok(())
 }
 
+/// Resolves on Ctrl-C or ```SIGTERM```, which is sent by ```docker stop```.
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(e) => {
+            log::error!("Can't listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
 /// Fake main function calls only the ```run``` function.
 fn main() {
     if let Err(e) = actix_rt::System::new("api-main").block_on(run()) {
@@ -0,0 +1,521 @@
+//! The OpenAPI 3 description of the REST API served at ```/api/openapi.json```.
+//! The schemas are generated from the actual types with [schemars](https://docs.rs/schemars),
+//! the operations are listed in ```operations()``` for the routes of ```API_ROUTES``` and tests check that both match.
+use crate::api::{
+    AssignmentDetail, AssignmentShort, Attempt, DrainNode, Exercise, NodeStatus, PublicKeyInfo,
+    QueueJob, Status, Submission, SubmissionCreated, SubmissionEvent, Verification, VerifyRequest,
+    Version,
+};
+use crate::handlers::auth::ApiScope;
+use crate::handlers::error::{ErrJson, ErrSubmission};
+use crate::routes::{ApiRoute, API_ROUTES};
+use actix_web::HttpResponse;
+use grpc_api::AssignmentResult;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// The Swagger UI page, the assets are loaded from unpkg.
+const SWAGGER_UI: &str = include_str!("../openapi/swagger-ui.html");
+
+/// The docs config via the environment variables using ```DEPP_API_``` as prefix.
+#[derive(Deserialize, Debug, Default)]
+pub struct DocsConfig {
+    /// Serves the Swagger UI at ```/api/docs```.
+    #[serde(default)]
+    pub swagger_ui: bool,
+}
+
+/// Reading the environment variables.
+pub fn get_config() -> Result<DocsConfig, envy::Error> {
+    envy::prefixed("DEPP_API_").from_env::<DocsConfig>()
+}
+
+/// One documented route, the path is relative to ```/api```.
+struct Operation {
+    route: &'static ApiRoute,
+    operation_id: &'static str,
+    summary: &'static str,
+    /// ```None``` for routes every client may use.
+    scope: Option<ApiScope>,
+    request_body: Option<Schema>,
+    responses: Vec<(u16, &'static str, Option<Schema>)>,
+}
+
+impl Operation {
+    fn to_json(&self) -> Value {
+        let mut responses: Map<String, Value> = self
+            .responses
+            .iter()
+            .map(|(status, description, schema)| {
+                let mut response = json!({ "description": description });
+                if let Some(schema) = schema {
+                    response["content"] = json!({ "application/json": { "schema": schema } });
+                }
+                (status.to_string(), response)
+            })
+            .collect();
+        if !self.route.public {
+            responses.insert(
+                "401".into(),
+                json!({ "description": "Missing or wrong credentials" }),
+            );
+            responses.entry("429").or_insert_with(|| {
+                json!({ "description": "Rate limit of the API client exceeded, see the Retry-After header" })
+            });
+        }
+        let mut operation = json!({
+            "operationId": self.operation_id,
+            "summary": self.summary,
+            "tags": ["/api"],
+            "responses": responses,
+        });
+        if self.route.public {
+            operation["security"] = json!([]);
+        }
+        if let Some(scope) = self.scope {
+            operation["description"] = json!(format!("Needs the scope ```{:?}```.", scope));
+            operation["responses"]["403"] =
+                json!({ "description": "The API client is missing the scope" });
+        }
+        let parameters = path_parameters(self.route.path);
+        if !parameters.is_empty() {
+            operation["parameters"] = Value::Array(parameters);
+        }
+        if let Some(schema) = &self.request_body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema } },
+            });
+        }
+        operation
+    }
+}
+
+/// Every ```{name}``` segment is a required path parameter.
+fn path_parameters(path: &str) -> Vec<Value> {
+    path.split('/')
+        .filter(|segment| segment.starts_with('{') && segment.ends_with('}'))
+        .map(|segment| {
+            json!({
+                "name": &segment[1..segment.len() - 1],
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            })
+        })
+        .collect()
+}
+
+/// The route of ```API_ROUTES``` which is documented.
+fn route(method: &str, path: &str) -> &'static ApiRoute {
+    API_ROUTES
+        .iter()
+        .find(|route| route.method == method && route.path == path)
+        .unwrap_or_else(|| panic!("{} {} is not routed", method, path))
+}
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Option<Schema> {
+    Some(gen.subschema_for::<T>())
+}
+
+/// All documented routes.
+fn operations(gen: &mut SchemaGenerator) -> Vec<Operation> {
+    vec![
+        Operation {
+            route: route("get", "/version"),
+            operation_id: "version",
+            summary: "The version of the API.",
+            scope: None,
+            request_body: None,
+            responses: vec![(200, "", schema::<Version>(gen))],
+        },
+        Operation {
+            route: route("get", "/status"),
+            operation_id: "status",
+            summary: "The current status of the booth platforms and of each testing server.",
+            scope: None,
+            request_body: None,
+            responses: vec![(200, "", schema::<Status>(gen))],
+        },
+        Operation {
+            route: route("get", "/assignments"),
+            operation_id: "getAssignments",
+            summary: "All active assignments.",
+            scope: Some(ApiScope::ListAssignments),
+            request_body: None,
+            responses: vec![(200, "", schema::<Vec<AssignmentShort>>(gen))],
+        },
+        Operation {
+            route: route("get", "/assignments/{assignmentId}"),
+            operation_id: "getAssignment",
+            summary: "The details of an assignment with the names of the included files, without the solution.",
+            scope: Some(ApiScope::ListAssignments),
+            request_body: None,
+            responses: vec![
+                (200, "", schema::<AssignmentDetail>(gen)),
+                (404, "AssignmentID does not exits", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/assignments/{assignmentId}/export"),
+            operation_id: "exportAssignment",
+            summary: "All tested attempts of the assignment with score and the outcome of each checker. \
+                      The query parameter format is json (default) or csv, from and to (YYYY-MM-DD, inclusive) \
+                      limit the submission date and latest=true keeps only the latest attempt of each student.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (200, "A JSON array or a CSV file with one line per attempt", None),
+                (404, "AssignmentID does not exits", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/exercises"),
+            operation_id: "getExercises",
+            summary: "All exercises with their active assignments.",
+            scope: Some(ApiScope::ListAssignments),
+            request_body: None,
+            responses: vec![(200, "", schema::<Vec<Exercise>>(gen))],
+        },
+        Operation {
+            route: route("get", "/exercises/{exerciseId}/export"),
+            operation_id: "exportExercise",
+            summary: "All tested attempts of all assignments of the exercise, \
+                      with the same query parameters as exportAssignment.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (200, "A JSON array or a CSV file with one line per attempt", None),
+                (404, "ExerciseID does not exits", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("post", "/submission"),
+            operation_id: "addSubmission",
+            summary: "Upload of a new student submission as the next attempt of the student on the assignment. \
+                      It waits in the queue until it is tested, also while the testing server is offline. \
+                      The query parameter wait (seconds, at most 60) waits for the result and returns it inline.",
+            scope: Some(ApiScope::Submit),
+            request_body: schema::<Submission>(gen),
+            responses: vec![
+                (201, "Submission created", schema::<SubmissionCreated>(gen)),
+                (
+                    200,
+                    "Submission tested within the wait time, the result is included",
+                    schema::<SubmissionCreated>(gen),
+                ),
+                (
+                    202,
+                    "Still testing after the wait time, the Location header points to the result",
+                    schema::<SubmissionCreated>(gen),
+                ),
+                (
+                    400,
+                    "Incorrect json received. Maybe there are some fields missing or the types does not match.",
+                    schema::<ErrSubmission>(gen),
+                ),
+                (404, "AssignmentID does not exits", schema::<ErrJson>(gen)),
+                (
+                    429,
+                    "The student has used all attempts or has to wait for the cooldown given by the Retry-After header. \
+                     Also returned if the rate limit of the API client is exceeded.",
+                    schema::<ErrJson>(gen),
+                ),
+                (503, "The API is shutting down", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("delete", "/submission/{iliasId}"),
+            operation_id: "cancelSubmission",
+            summary: "Cancels the latest submission with the iliasId while it is waiting or being tested. \
+                      A running test is aborted and its container removed.",
+            scope: Some(ApiScope::Submit),
+            request_body: None,
+            responses: vec![
+                (204, "Submission cancelled", None),
+                (
+                    404,
+                    "No waiting or running submission with the iliasId",
+                    schema::<ErrJson>(gen),
+                ),
+            ],
+        },
+        Operation {
+            route: route("get", "/result/{iliasId}"),
+            operation_id: "getResults",
+            summary: "Returns the result of the latest submission with the iliasId and keeps it until the post method is invoked for the iliasId.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (
+                    200,
+                    "The result of the submission. Tests are invalid if some internal server errors occur while testing.",
+                    schema::<AssignmentResult>(gen),
+                ),
+                (202, "Submission is still in the queue or being tested", None),
+                (404, "No Results not found for given iliasId", schema::<ErrJson>(gen)),
+                (410, "The submission was cancelled", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("post", "/result/{iliasId}"),
+            operation_id: "rmResults",
+            summary: "Marks the result as picked up, it stays in the attempt history of the student.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (200, "Result picked up", None),
+                (404, "No Results not found for given iliasId", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/result/{iliasId}/detail"),
+            operation_id: "getResultDetail",
+            summary: "Returns the unredacted result of the latest submission with the iliasId for tutors, \
+                      regardless of the feedback level of the assignment and also after it was picked up.",
+            scope: Some(ApiScope::Admin),
+            request_body: None,
+            responses: vec![
+                (200, "The full result of the submission", schema::<AssignmentResult>(gen)),
+                (202, "Submission is still in the queue or being tested", None),
+                (404, "No Results not found for given iliasId", schema::<ErrJson>(gen)),
+                (410, "The submission was cancelled", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/result/{iliasId}/events"),
+            operation_id: "getResultEvents",
+            summary: "Server-Sent Events stream of the latest submission with the iliasId. \
+                      The first event is the current status, followed by every change until the result is sent.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (
+                    200,
+                    "text/event-stream, the event name is the lowercase status and the data a SubmissionEvent",
+                    None,
+                ),
+                (404, "No Results not found for given iliasId", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/students/{studentId}/attempts"),
+            operation_id: "getAttempts",
+            summary: "All attempts of the student with their results. \
+                      The query parameter assignmentId limits them to one assignment.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![(200, "", schema::<Vec<Attempt>>(gen))],
+        },
+        Operation {
+            route: route("get", "/students/{studentId}/assignments/{assignmentId}/best"),
+            operation_id: "getBestAttempt",
+            summary: "The best tested attempt of the student on the assignment: \
+                      passed before valid before the most passed checks.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (200, "", schema::<Attempt>(gen)),
+                (404, "No attempt was tested yet", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/students/{studentId}/assignments/{assignmentId}/latest"),
+            operation_id: "getLatestAttempt",
+            summary: "The latest tested attempt of the student on the assignment.",
+            scope: Some(ApiScope::ReadResults),
+            request_body: None,
+            responses: vec![
+                (200, "", schema::<Attempt>(gen)),
+                (404, "No attempt was tested yet", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/queue"),
+            operation_id: "queue",
+            summary: "All submissions which are waiting in the queue or currently being tested, oldest first.",
+            scope: Some(ApiScope::Admin),
+            request_body: None,
+            responses: vec![(200, "", schema::<Vec<QueueJob>>(gen))],
+        },
+        Operation {
+            route: route("post", "/nodes/drain"),
+            operation_id: "drainNode",
+            summary: "Starts or stops draining a testing server. A draining server gets no new tests \
+                      and finishes the running ones, it's empty once outstanding is 0.",
+            scope: Some(ApiScope::Admin),
+            request_body: schema::<DrainNode>(gen),
+            responses: vec![
+                (200, "", schema::<NodeStatus>(gen)),
+                (404, "No testing server has the url", schema::<ErrJson>(gen)),
+            ],
+        },
+        Operation {
+            route: route("get", "/attestation/key"),
+            operation_id: "getPublicKey",
+            summary: "The Ed25519 public key the results are signed with and the retired keys which are still verified. \
+                      The attestation section of the api README describes the signed payload.",
+            scope: None,
+            request_body: None,
+            responses: vec![(200, "", schema::<PublicKeyInfo>(gen))],
+        },
+        Operation {
+            route: route("post", "/attestation/verify"),
+            operation_id: "verifyResult",
+            summary: "Checks the signature of a result and, if the source is given, that it was tested with it.",
+            scope: None,
+            request_body: schema::<VerifyRequest>(gen),
+            responses: vec![(200, "", schema::<Verification>(gen))],
+        },
+        Operation {
+            route: route("get", "/openapi.json"),
+            operation_id: "openapi",
+            summary: "This OpenAPI description.",
+            scope: None,
+            request_body: None,
+            responses: vec![(200, "", None)],
+        },
+    ]
+}
+
+/// Builds the whole OpenAPI document.
+pub fn spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    // only referenced by the description of the event stream
+    gen.subschema_for::<SubmissionEvent>();
+    let mut paths = Map::new();
+    for operation in operations(&mut gen) {
+        let path = paths
+            .entry(operation.route.path.to_string())
+            .or_insert_with(|| json!({}));
+        path[operation.route.method] = operation.to_json();
+    }
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": "DEPP Project API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "This Rest API uses `JSON only`. Everytime an error occurs, an ApiError json object is send.",
+        },
+        "servers": [{ "url": "/api" }],
+        "security": [{ "bearerAuth": [] }, { "basicAuth": [] }],
+        "paths": paths,
+        "components": {
+            "schemas": gen.definitions(),
+            "securitySchemes": {
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "The token of an API client created in the Assignment Manager.",
+                },
+                "basicAuth": {
+                    "type": "http",
+                    "scheme": "basic",
+                    "description": "The legacy credentials of DEPP_API_USERNAME and DEPP_API_PASSWORD, \
+                                    only if a password is set and only for submitting, results and assignments.",
+                },
+            },
+        },
+    })
+}
+
+/// Returns the OpenAPI description.
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(spec())
+}
+
+/// Returns the Swagger UI page for the OpenAPI description.
+pub async fn swagger_ui() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(SWAGGER_UI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::auth::Credentials;
+    use crate::routes::register_routes;
+    use crate::state::State;
+    use crate::{attestation, queue, rate_limit, rpc_conf, webhook};
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{test, App};
+    use std::collections::BTreeSet;
+
+    /// The pool never connects, so handlers using the database fail with 500 but are still routed.
+    fn test_state() -> State {
+        std::env::set_var("DEPP_API_SIGNING_KEY_TEMPORARY", "true");
+        let manager =
+            deadpool_postgres::Manager::new(tokio_postgres::Config::new(), tokio_postgres::NoTls);
+        State::new(
+            rpc_conf::get_config().unwrap(),
+            Credentials::new("user", Some("wasd4221")),
+            webhook::get_config().unwrap(),
+            queue::get_config().unwrap(),
+            attestation::ResultSigner::new(attestation::get_config().unwrap()).unwrap(),
+            rate_limit::get_config().unwrap(),
+            deadpool_postgres::Pool::new(manager, 1),
+        )
+    }
+
+    /// Each documented operation has to be routed and a documented path must not accept other methods.
+    #[actix_rt::test]
+    async fn spec_and_routes_do_not_drift() {
+        let mut app = test::init_service(
+            App::new()
+                .data(test_state())
+                .configure(|cfg| register_routes(cfg, false)),
+        )
+        .await;
+        let operations = operations(&mut SchemaSettings::openapi3().into_generator());
+        let credentials = format!("Basic {}", ::base64::encode("user:wasd4221"));
+        for operation in &operations {
+            // typed path parameters which can't be parsed are answered with 404
+            let uri = format!(
+                "/api{}",
+                operation
+                    .route
+                    .path
+                    .replace("{iliasId}", "some_ilias_id")
+                    .replace("{assignmentId}", "936da01f-9abd-4d9d-80c7-02af85c822a8")
+                    .replace("{exerciseId}", "1")
+            );
+            for method in &["get", "post", "put", "delete"] {
+                let request = test::TestRequest::with_uri(&uri)
+                    .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                    .header("Authorization", credentials.as_str())
+                    .to_request();
+                let status = test::call_service(&mut app, request).await.status();
+                let routed =
+                    status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED;
+                let documented = operations
+                    .iter()
+                    .any(|op| op.route.path == operation.route.path && op.route.method == *method);
+                assert_eq!(
+                    routed, documented,
+                    "{} {} is documented: {}, but routed: {} ({})",
+                    method, uri, documented, routed, status
+                );
+            }
+        }
+    }
+
+    /// Each registered route is documented once.
+    #[test]
+    fn every_route_is_documented() {
+        let operations = operations(&mut SchemaSettings::openapi3().into_generator());
+        let documented: BTreeSet<_> = operations
+            .iter()
+            .map(|operation| (operation.route.method, operation.route.path))
+            .collect();
+        let routed: BTreeSet<_> = API_ROUTES
+            .iter()
+            .map(|route| (route.method, route.path))
+            .collect();
+        assert_eq!(documented, routed);
+        assert_eq!(operations.len(), API_ROUTES.len());
+    }
+}
@@ -0,0 +1,484 @@
+//! The job queue between the API and the RPC testing servers. The ```submission``` table is the queue, so no job gets lost on a restart.
+//! Each platform has its own dispatcher which runs a bounded number of tests per testing server at the same time.
+//! Transport errors like an offline testing server are retried with exponential backoff.
+//! A job whose test ended with an error instead of a result is retried the same way, after the last attempt it gets an invalid result.
+//! Jobs which are ```Running``` in the database without a running test are requeued by a periodic sweep.
+//! If the submission has a callback url, the result is delivered via the [webhook](../webhook/index.html) as well.
+//! A running test can be aborted, dropping the RPC call cancels it on the testing server which removes the container.
+//! On shutdown no further jobs are claimed, the running tests get a grace period and are aborted afterwards,
+//! the aborted jobs are requeued on the next start.
+use crate::api::{IliasId, SubmissionEvent};
+use crate::attestation;
+use crate::db::{self, StoredSubmission, SubmissionStatus};
+use crate::handlers::error::Error;
+use crate::rpc_conf::{RpcConfig, RpcMeta, RpcPool};
+use crate::state::State;
+use crate::webhook::{self, Delivery};
+use futures::future::{AbortHandle, Abortable};
+use grpc_api::test_client::TestClient;
+use grpc_api::{Assignment, AssignmentMsg, AssignmentResult, TestReply};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
+use tokio::time::timeout;
+use tonic::transport::Channel;
+
+/// How often the dispatchers look for jobs whose retry is due.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the shutdown checks if all tests are done.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often the sweep looks for stale jobs.
+const STALE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_max_attempts() -> i32 {
+    5
+}
+
+fn default_backoff_secs() -> u64 {
+    10
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    120
+}
+
+fn default_stale_secs() -> u64 {
+    300
+}
+
+/// The queue config via the environment variables using ```DEPP_API_``` as prefix.
+#[derive(Deserialize, Debug, Clone)]
+pub struct QueueConfig {
+    /// Max number of tests running at the same time on each testing server.
+    #[serde(default = "default_concurrency")]
+    queue_concurrency: usize,
+    #[serde(default = "default_max_attempts")]
+    queue_max_attempts: i32,
+    /// The delay before the first retry, doubled on each further retry.
+    #[serde(default = "default_backoff_secs")]
+    queue_backoff_secs: u64,
+    /// The time the running tests get to finish on shutdown before they are aborted.
+    #[serde(default = "default_shutdown_grace_secs")]
+    shutdown_grace_secs: u64,
+    /// Jobs which are ```Running``` for longer without a running test are requeued.
+    #[serde(default = "default_stale_secs")]
+    queue_stale_secs: u64,
+}
+
+/// Reading the environment variables.
+pub fn get_config() -> Result<QueueConfig, envy::Error> {
+    envy::prefixed("DEPP_API_").from_env::<QueueConfig>()
+}
+
+/// Wakes up the dispatcher of an endpoint as soon as a new job is stored.
+pub struct Queue {
+    config: QueueConfig,
+    notifiers: HashMap<&'static str, Notify>,
+    /// The running tests by submission id.
+    running: Mutex<HashMap<i32, AbortHandle>>,
+    /// Set on shutdown, no further jobs are claimed and new submissions are rejected.
+    draining: AtomicBool,
+}
+
+impl Queue {
+    pub fn new(config: QueueConfig, rpc_conf: &RpcConfig) -> Self {
+        Queue {
+            config,
+            notifiers: rpc_conf
+                .endpoints()
+                .iter()
+                .map(|rpc| (rpc.platform, Notify::new()))
+                .collect(),
+            running: Mutex::new(HashMap::new()),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Aborts the running test of the submission, returns ```false``` if it's not running.
+    pub fn abort(&self, submission_id: i32) -> bool {
+        match self.running.lock().unwrap().remove(&submission_id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tells the dispatcher of the platform that a new job is waiting.
+    pub fn notify(&self, platform: &str) {
+        if let Some(notify) = self.notifiers.get(platform) {
+            notify.notify();
+        }
+    }
+
+    /// Ids of the submissions whose test is running in this process.
+    fn running_ids(&self) -> Vec<i32> {
+        self.running.lock().unwrap().keys().copied().collect()
+    }
+
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = (attempts - 1).clamp(0, 10) as u32;
+        Duration::from_secs(self.config.queue_backoff_secs * 2u64.pow(exponent))
+    }
+}
+
+/// Connects to the RPC server, gives up after one second.
+pub async fn connect(rpc: &RpcMeta) -> Result<TestClient<Channel>, Error> {
+    let rpc_url = rpc.rpc_url.to_string();
+    log::info!("Calling RPC Endpoint: {} ", &rpc_url);
+    timeout(Duration::from_secs(1), TestClient::connect(rpc_url))
+        .await
+        .map_err(|_| Error::RpcOffline {
+            reason: rpc.clone(),
+        })?
+        .map_err(|_| Error::RpcOffline {
+            reason: rpc.clone(),
+        })
+}
+
+/// Puts the jobs interrupted by a restart back into the queue and starts one dispatcher for each platform
+/// and the sweep for stale jobs.
+pub async fn start(state: &State) -> Result<(), Error> {
+    let requeued = db::requeue_unfinished(&state.db_pool).await?;
+    if requeued > 0 {
+        log::info!("Requeued {} interrupted submissions", requeued);
+    }
+    for pool in state.rpc_conf.endpoints().iter() {
+        tokio::task::spawn(dispatch(state.clone(), pool.platform, pool.node_count()));
+    }
+    tokio::task::spawn(sweep(state.clone()));
+    Ok(())
+}
+
+/// Stops claiming jobs and waits up to the grace period for the running tests, the rest is aborted.
+pub async fn drain(state: &State) {
+    let queue = &state.queue;
+    queue.draining.store(true, Ordering::SeqCst);
+    log::info!(
+        "Draining the queue, waiting up to {}s for {} running tests",
+        queue.config.shutdown_grace_secs,
+        queue.running.lock().unwrap().len()
+    );
+    let grace = Duration::from_secs(queue.config.shutdown_grace_secs);
+    let idle = async {
+        while !queue.running.lock().unwrap().is_empty() {
+            tokio::time::delay_for(IDLE_POLL_INTERVAL).await;
+        }
+    };
+    if timeout(grace, idle).await.is_err() {
+        let running: Vec<_> = queue.running.lock().unwrap().drain().collect();
+        for (_, handle) in &running {
+            handle.abort();
+        }
+        log::warn!(
+            "Grace period is over, aborted {} tests which are requeued on the next start",
+            running.len()
+        );
+    }
+}
+
+/// Claims the next due job of the platform as soon as a slot is free.
+async fn dispatch(state: State, platform: &'static str, node_count: usize) {
+    let semaphore = Arc::new(Semaphore::new(
+        state.queue.config.queue_concurrency * node_count,
+    ));
+    let notify = &state.queue.notifiers[platform];
+    loop {
+        semaphore.acquire().await.forget();
+        let claimed = if state.queue.is_draining() {
+            Ok(None)
+        } else {
+            db::claim_next(&state.db_pool, platform).await
+        };
+        match claimed {
+            Ok(Some(submission_id)) => {
+                let state = state.clone();
+                let semaphore = semaphore.clone();
+                let (handle, registration) = AbortHandle::new_pair();
+                state
+                    .queue
+                    .running
+                    .lock()
+                    .unwrap()
+                    .insert(submission_id, handle);
+                tokio::task::spawn(async move {
+                    match Abortable::new(run(&state, submission_id), registration).await {
+                        Ok(Err(e)) => {
+                            log::error!("Testing submission {} failed: {}", submission_id, e);
+                            if let Err(e) = recover(&state, submission_id, e.to_string()).await {
+                                log::error!(
+                                    "Requeuing submission {} failed, it is left to the stale job sweep: {}",
+                                    submission_id,
+                                    e
+                                );
+                            }
+                        }
+                        Ok(Ok(())) => {}
+                        Err(_) => log::info!("Testing submission {} was cancelled", submission_id),
+                    }
+                    state.queue.running.lock().unwrap().remove(&submission_id);
+                    semaphore.add_permits(1);
+                });
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("Claiming the next {} job failed: {}", platform, e),
+        }
+        semaphore.add_permits(1);
+        let _ = timeout(POLL_INTERVAL, notify.notified()).await;
+    }
+}
+
+/// Requeues the jobs which are ```Running``` in the database for longer than ```queue_stale_secs```
+/// without a running test, e.g. because their result could not be stored.
+async fn sweep(state: State) {
+    let stale_secs = state.queue.config.queue_stale_secs;
+    loop {
+        tokio::time::delay_for(STALE_SWEEP_INTERVAL).await;
+        if state.queue.is_draining() {
+            continue;
+        }
+        let running = state.queue.running_ids();
+        let stale = match db::stale_submissions(&state.db_pool, stale_secs, &running).await {
+            Ok(stale) => stale,
+            Err(e) => {
+                log::error!("Looking for stale submissions failed: {}", e);
+                continue;
+            }
+        };
+        for submission_id in stale {
+            log::warn!(
+                "Submission {} is running for more than {}s without a test, requeuing it",
+                submission_id,
+                stale_secs
+            );
+            let reason = format!("The test did not finish within {}s", stale_secs);
+            if let Err(e) = recover(&state, submission_id, reason).await {
+                log::error!("Requeuing submission {} failed: {}", submission_id, e);
+            }
+        }
+        for platform in state.queue.notifiers.keys() {
+            state.queue.notify(platform);
+        }
+    }
+}
+
+async fn run(state: &State, submission_id: i32) -> Result<(), Error> {
+    let submission = db::submission(&state.db_pool, submission_id).await?;
+    log::info!(
+        "Testing submission of ilias id: {} (attempt {})",
+        &submission.ilias_id,
+        submission.attempts
+    );
+    state.events.publish(
+        submission.id,
+        &IliasId::from(submission.ilias_id.clone()),
+        submission.attempt,
+        SubmissionStatus::Running,
+    );
+    let tested = match db::assignment(&state.db_pool, &submission.assignment_id).await {
+        Ok(assignment) => {
+            let pool = state.rpc_conf.pool(&assignment.script_type.into());
+            test(
+                pool,
+                assignment,
+                submission.source_code.clone(),
+                submission.source_zip.clone().unwrap_or_default(),
+            )
+            .await
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    let (result, detail, testing_server) = match tested {
+        Ok((
+            TestReply {
+                result: Some(result),
+                detail,
+            },
+            server,
+        )) => (result, detail, server),
+        Ok((TestReply { result: None, .. }, server)) => (
+            invalid_result("The testing server sent no result".into()),
+            None,
+            server,
+        ),
+        Err(reason) if submission.attempts < state.queue.config.queue_max_attempts => {
+            return retry(state, &submission, &reason).await;
+        }
+        Err(reason) => (invalid_result(reason), None, String::new()),
+    };
+    finish(state, submission, result, detail, &testing_server).await
+}
+
+/// Handles a job whose test ended with an error instead of a result, so it doesn't stay ```Running```.
+/// It is retried with backoff while it has attempts left, afterwards it gets an invalid result.
+async fn recover(state: &State, submission_id: i32, reason: String) -> Result<(), Error> {
+    let submission = db::submission(&state.db_pool, submission_id).await?;
+    if submission.attempts < state.queue.config.queue_max_attempts {
+        retry(state, &submission, &reason).await
+    } else {
+        finish(state, submission, invalid_result(reason), None, "").await
+    }
+}
+
+/// Puts the job back into the queue, it is claimed again after the backoff.
+async fn retry(state: &State, submission: &StoredSubmission, reason: &str) -> Result<(), Error> {
+    let delay = state.queue.backoff(submission.attempts);
+    log::warn!(
+        "Testing submission of ilias id: {} failed, retry in {}s: {}",
+        &submission.ilias_id,
+        delay.as_secs(),
+        reason
+    );
+    if db::retry_later(&state.db_pool, submission.id, delay, reason).await? {
+        state.events.publish(
+            submission.id,
+            &IliasId::from(submission.ilias_id.clone()),
+            submission.attempt,
+            SubmissionStatus::Queued,
+        );
+    }
+    Ok(())
+}
+
+/// Attests and stores the result, then publishes it and delivers it to the callback url.
+async fn finish(
+    state: &State,
+    submission: StoredSubmission,
+    mut result: AssignmentResult,
+    mut detail: Option<AssignmentResult>,
+    testing_server: &str,
+) -> Result<(), Error> {
+    let source_sha256 = match &submission.source_zip {
+        Some(zip) => attestation::sha256(zip),
+        None => attestation::sha256(submission.source_code.as_bytes()),
+    };
+    for unsigned in std::iter::once(&mut result).chain(detail.as_mut()) {
+        state.signer.attest(
+            unsigned,
+            &source_sha256,
+            &submission.assignment_id,
+            submission.assignment_revision,
+            testing_server,
+        );
+    }
+    if !db::store_result(&state.db_pool, submission.id, &result, detail.as_ref()).await? {
+        log::info!("Submission {} was cancelled while testing", submission.id);
+        return Ok(());
+    }
+    state.events.publish_event(SubmissionEvent {
+        submission_id: submission.id,
+        ilias_id: IliasId::from(submission.ilias_id.clone()),
+        attempt: submission.attempt,
+        status: SubmissionStatus::Done,
+        result: Some(result.clone()),
+    });
+    if let Some(callback_url) = submission.callback_url {
+        webhook::spawn(
+            state.clone(),
+            Delivery {
+                submission_id: submission.id,
+                ilias_id: submission.ilias_id,
+                callback_url,
+                result,
+                attempts: 0,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Sends the test via RPC to the least busy healthy testing server of the platform
+/// and returns the reply with the url of the server.
+/// Transport errors are returned as ```Err``` so the job can be retried,
+/// an unreachable server is taken out of rotation until the next health check.
+async fn test(
+    pool: &RpcPool,
+    assignment: Assignment,
+    code_to_test: String,
+    source_zip: Vec<u8>,
+) -> Result<(TestReply, String), String> {
+    let node = pool.pick().ok_or_else(|| pool.to_string())?;
+    let mut client = connect(&node.meta).await.map_err(|_| {
+        node.set_healthy(false);
+        node.meta.to_string()
+    })?;
+    let request = tonic::Request::new(AssignmentMsg {
+        assignment: Some(assignment),
+        code_to_test,
+        source_zip,
+    });
+    match client.run_test(request).await {
+        Ok(response) => Ok((response.into_inner(), node.meta.rpc_url.to_string())),
+        Err(status) if is_transport_error(&status) => {
+            if status.code() == tonic::Code::Unavailable {
+                node.set_healthy(false);
+            }
+            Err(status.to_string())
+        }
+        Err(status) => Ok((
+            TestReply {
+                result: Some(invalid_result(status.to_string())),
+                detail: None,
+            },
+            node.meta.rpc_url.to_string(),
+        )),
+    }
+}
+
+/// Only a testing server which can't be reached or doesn't answer in time is worth a retry.
+/// ```Unknown``` is returned for errors and panics of the testing server, those end with an invalid result.
+fn is_transport_error(status: &tonic::Status) -> bool {
+    use tonic::Code;
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+}
+
+fn invalid_result(message: String) -> AssignmentResult {
+    AssignmentResult {
+        passed: false,
+        message: Some(message),
+        valid: false,
+        compilation_failed: false,
+        diagnostics: vec![],
+        checks: vec![],
+        attestation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::{Code, Status};
+
+    #[test]
+    fn only_unreachable_testing_servers_are_retried() {
+        assert!(is_transport_error(&Status::unavailable(
+            "connection refused"
+        )));
+        assert!(is_transport_error(&Status::deadline_exceeded("timeout")));
+        for code in &[
+            Code::Unknown,
+            Code::Aborted,
+            Code::Internal,
+            Code::InvalidArgument,
+        ] {
+            assert!(
+                !is_transport_error(&Status::new(*code, "error")),
+                "{:?}",
+                code
+            );
+        }
+    }
+}
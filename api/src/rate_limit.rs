@@ -0,0 +1,142 @@
+//! Rate limit of the requests per API client, checked after the authentication.
+//! Each client has a token bucket which holds the requests of one minute and refills continuously,
+//! so short bursts are fine but a client can't exceed the limit over time.
+//! The per student limits of the submissions are configured per assignment, see ```db::insert_submission```.
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+fn default_rate_limit() -> u32 {
+    600
+}
+
+/// The rate limit config via the environment variables using ```DEPP_API_``` as prefix.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Max requests per minute of each API client, 0 disables the limit.
+    #[serde(default = "default_rate_limit")]
+    rate_limit_per_minute: u32,
+}
+
+/// Reading the environment variables.
+pub fn get_config() -> Result<RateLimitConfig, envy::Error> {
+    envy::prefixed("DEPP_API_").from_env::<RateLimitConfig>()
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// The token buckets by client name.
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            per_minute: config.rate_limit_per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes one request from the bucket of the client,
+    /// returns how long the client has to wait if the bucket is empty.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(self.per_minute);
+        let per_sec = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            refilled_at: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_minute: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            rate_limit_per_minute: per_minute,
+        })
+    }
+
+    #[test]
+    fn burst_of_one_minute_is_allowed() {
+        let limiter = limiter(60);
+        let now = Instant::now();
+        for _ in 0..60 {
+            assert_eq!(limiter.check_at("lti", now), Ok(()));
+        }
+        let wait = limiter.check_at("lti", now).unwrap_err();
+        assert!(wait > Duration::from_millis(990) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bucket_refills_continuously() {
+        let limiter = limiter(60);
+        let start = Instant::now();
+        for _ in 0..60 {
+            limiter.check_at("lti", start).unwrap();
+        }
+        let later = start + Duration::from_secs(10);
+        for _ in 0..10 {
+            assert_eq!(limiter.check_at("lti", later), Ok(()));
+        }
+        assert!(limiter.check_at("lti", later).is_err());
+        let half_second = later + Duration::from_millis(500);
+        let wait = limiter.check_at("lti", half_second).unwrap_err();
+        assert!(wait > Duration::from_millis(490) && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn bucket_holds_at_most_one_minute() {
+        let limiter = limiter(60);
+        let start = Instant::now();
+        limiter.check_at("lti", start).unwrap();
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..60 {
+            assert_eq!(limiter.check_at("lti", later), Ok(()));
+        }
+        assert!(limiter.check_at("lti", later).is_err());
+    }
+
+    #[test]
+    fn clients_have_their_own_bucket() {
+        let limiter = limiter(1);
+        let now = Instant::now();
+        assert_eq!(limiter.check_at("lti", now), Ok(()));
+        assert!(limiter.check_at("lti", now).is_err());
+        assert_eq!(limiter.check_at("ilias", now), Ok(()));
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let limiter = limiter(0);
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert_eq!(limiter.check_at("lti", now), Ok(()));
+        }
+    }
+}
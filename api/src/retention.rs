@@ -0,0 +1,70 @@
+//! Removes old data in the background, otherwise the database grows with every submission.
+//! Results which were not picked up expire after ```DEPP_API_RESULT_TTL_HOURS```, they can't be fetched anymore
+//! but stay in the attempt history. After the semester end ```DEPP_API_PURGE_BEFORE``` all tested or cancelled submissions
+//! created before that date are deleted together with their results, the attempts of the students start again at 1.
+use crate::db;
+use crate::handlers::error::Error;
+use crate::state::State;
+use chrono::{NaiveDate, Utc};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::time::Duration;
+
+fn default_reaper_interval_secs() -> u64 {
+    60 * 60
+}
+
+/// The retention config via the environment variables using ```DEPP_API_``` as prefix.
+/// Example:
+/// ```DEPP_API_RESULT_TTL_HOURS=72``` ```DEPP_API_PURGE_BEFORE=2021-03-31```
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetentionConfig {
+    /// Unclaimed results expire after this many hours, they are kept forever if not set.
+    result_ttl_hours: Option<i32>,
+    /// The semester end, once it has passed all submissions created before it are deleted.
+    purge_before: Option<NaiveDate>,
+    #[serde(default = "default_reaper_interval_secs")]
+    reaper_interval_secs: u64,
+}
+
+/// Reading the environment variables.
+pub fn get_config() -> Result<RetentionConfig, envy::Error> {
+    envy::prefixed("DEPP_API_").from_env::<RetentionConfig>()
+}
+
+/// Starts the reaper which runs once at startup and then every ```reaper_interval_secs```.
+pub fn start(state: &State, config: RetentionConfig) {
+    if config.result_ttl_hours.is_none() && config.purge_before.is_none() {
+        log::info!("No retention policy configured, results and submissions are kept forever");
+        return;
+    }
+    let state = state.clone();
+    tokio::task::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.reaper_interval_secs.max(1)));
+        while let Some(_) = interval.next().await {
+            if let Err(e) = reap(&state, &config).await {
+                log::error!("Reaper failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn reap(state: &State, config: &RetentionConfig) -> Result<(), Error> {
+    let expired = match config.result_ttl_hours {
+        Some(hours) => db::expire_results(&state.db_pool, hours).await?,
+        None => 0,
+    };
+    let purged = match config.purge_before {
+        Some(date) if Utc::today().naive_utc() >= date => {
+            db::purge_submissions(&state.db_pool, date).await?
+        }
+        _ => 0,
+    };
+    log::info!(
+        "Reaper expired {} unclaimed results and purged {} submissions",
+        expired,
+        purged
+    );
+    Ok(())
+}
@@ -1,33 +1,151 @@
 //! All routes an here defined to keep the ```main()``` cleaner.
-use actix_web::web;
+use actix_web::{web, Resource, Route};
+use actix_web_httpauth::middleware::HttpAuthentication;
 
 use crate::handlers::{
-    get::get_assignments, get::get_result, get::index, get::status, get::version,
-    post::add_submission,
+    auth::handle_auth, delete::cancel_submission, get::get_assignment, get::get_assignment_export,
+    get::get_assignments, get::get_attempts, get::get_best_attempt, get::get_exercise_export,
+    get::get_exercises, get::get_latest_attempt, get::get_queue, get::get_result,
+    get::get_result_detail, get::get_result_events, get::index, get::public_key, get::status,
+    get::version, post::add_submission, post::drain_node, post::verify_result,
+    post::MAX_SUBMISSION_BYTES,
 };
+use crate::openapi::{openapi_json, swagger_ui};
 
 /// Registers all routes
 /// # Routes
 /// **Note:** That all routes have a ```/api``` prefix.
-/// See the openapi docs for more information.
-/// * ```/assignments```
-/// * ```/submission```
-/// * ```/result/{iliasId}```
-/// * ```/status```
-/// * ```/version```
+/// See the OpenAPI description at ```/api/openapi.json``` for more information,
+/// it and ```/attestation/key``` are the only routes which need no authentication.
+/// * all routes of ```API_ROUTES```
+/// * ```/docs``` the Swagger UI, only if enabled
 ///
-pub fn register_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/api")
-            .route("", web::get().to(index)) // for testing only
-            .route("/version", web::get().to(version))
-            .route("/status", web::get().to(status))
-            .route("/assignments", web::get().to(get_assignments))
-            .service(web::resource("/submission").route(web::post().to(add_submission)))
-            .service(
-                web::resource("/result/{iliasId}")
-                    .route(web::get().to(get_result))
-                    .route(web::post().to(get_result)),
-            ),
-    );
+pub fn register_routes(cfg: &mut web::ServiceConfig, with_swagger_ui: bool) {
+    let mut api = web::scope("/api")
+        .wrap(HttpAuthentication::with_fn(handle_auth))
+        .route("", web::get().to(index)); // for testing only
+    for path in paths() {
+        // public routes have to be registered before the scope, which would catch them otherwise
+        if API_ROUTES
+            .iter()
+            .any(|route| route.path == path && route.public)
+        {
+            cfg.service(resource("/api", path));
+        } else {
+            api = api.service(resource("", path));
+        }
+    }
+    if with_swagger_ui {
+        cfg.route("/api/docs", web::get().to(swagger_ui));
+    }
+    cfg.service(api);
+}
+
+/// A route below ```/api```, the [OpenAPI description](../openapi/index.html) documents exactly these.
+pub struct ApiRoute {
+    pub method: &'static str,
+    pub path: &'static str,
+    /// Public routes don't need any authentication.
+    pub public: bool,
+}
+
+const fn route(method: &'static str, path: &'static str) -> ApiRoute {
+    ApiRoute {
+        method,
+        path,
+        public: false,
+    }
+}
+
+const fn public(method: &'static str, path: &'static str) -> ApiRoute {
+    ApiRoute {
+        method,
+        path,
+        public: true,
+    }
+}
+
+/// All routes except the index and the Swagger UI.
+pub const API_ROUTES: &[ApiRoute] = &[
+    public("get", "/openapi.json"),
+    public("get", "/attestation/key"),
+    route("get", "/version"),
+    route("get", "/status"),
+    route("post", "/attestation/verify"),
+    route("get", "/queue"),
+    route("post", "/nodes/drain"),
+    route("get", "/assignments"),
+    route("get", "/assignments/{assignmentId}"),
+    route("get", "/assignments/{assignmentId}/export"),
+    route("get", "/exercises"),
+    route("get", "/exercises/{exerciseId}/export"),
+    route("post", "/submission"),
+    route("delete", "/submission/{iliasId}"),
+    route("get", "/result/{iliasId}"),
+    route("post", "/result/{iliasId}"),
+    route("get", "/result/{iliasId}/detail"),
+    route("get", "/result/{iliasId}/events"),
+    route("get", "/students/{studentId}/attempts"),
+    route(
+        "get",
+        "/students/{studentId}/assignments/{assignmentId}/best",
+    ),
+    route(
+        "get",
+        "/students/{studentId}/assignments/{assignmentId}/latest",
+    ),
+];
+
+/// The paths of ```API_ROUTES``` in their order, each only once.
+fn paths() -> Vec<&'static str> {
+    let mut paths: Vec<&'static str> = Vec::new();
+    for route in API_ROUTES {
+        if !paths.contains(&route.path) {
+            paths.push(route.path);
+        }
+    }
+    paths
+}
+
+/// All routes of the path, submissions and verifications may have a larger body than the default limit.
+fn resource(prefix: &str, path: &str) -> Resource {
+    let mut resource = web::resource(format!("{}{}", prefix, path).as_str());
+    if path == "/submission" || path == "/attestation/verify" {
+        resource = resource.data(web::JsonConfig::default().limit(MAX_SUBMISSION_BYTES));
+    }
+    API_ROUTES
+        .iter()
+        .filter(|route| route.path == path)
+        .fold(resource, |resource, route| resource.route(handler(route)))
+}
+
+fn handler(route: &ApiRoute) -> Route {
+    match (route.method, route.path) {
+        ("get", "/openapi.json") => web::get().to(openapi_json),
+        ("get", "/attestation/key") => web::get().to(public_key),
+        ("get", "/version") => web::get().to(version),
+        ("get", "/status") => web::get().to(status),
+        ("post", "/attestation/verify") => web::post().to(verify_result),
+        ("get", "/queue") => web::get().to(get_queue),
+        ("post", "/nodes/drain") => web::post().to(drain_node),
+        ("get", "/assignments") => web::get().to(get_assignments),
+        ("get", "/assignments/{assignmentId}") => web::get().to(get_assignment),
+        ("get", "/assignments/{assignmentId}/export") => web::get().to(get_assignment_export),
+        ("get", "/exercises") => web::get().to(get_exercises),
+        ("get", "/exercises/{exerciseId}/export") => web::get().to(get_exercise_export),
+        ("post", "/submission") => web::post().to(add_submission),
+        ("delete", "/submission/{iliasId}") => web::delete().to(cancel_submission),
+        ("get", "/result/{iliasId}") => web::get().to(get_result),
+        ("post", "/result/{iliasId}") => web::post().to(get_result),
+        ("get", "/result/{iliasId}/detail") => web::get().to(get_result_detail),
+        ("get", "/result/{iliasId}/events") => web::get().to(get_result_events),
+        ("get", "/students/{studentId}/attempts") => web::get().to(get_attempts),
+        ("get", "/students/{studentId}/assignments/{assignmentId}/best") => {
+            web::get().to(get_best_attempt)
+        }
+        ("get", "/students/{studentId}/assignments/{assignmentId}/latest") => {
+            web::get().to(get_latest_attempt)
+        }
+        (method, path) => unreachable!("{} {} has no handler", method, path),
+    }
 }
@@ -1,8 +1,11 @@
 //! RPC Config
-use crate::api::EndPointStatus;
-use grpc_api::{Script, TargetOs};
+use crate::api::{EndPointStatus, NodeStatus};
+use futures::future;
+use grpc_api::{DrainRequest, Script, TargetOs};
 use serde::Deserialize;
 use std::fmt::{Debug, Formatter};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::Duration;
 use tokio::time::timeout;
 use url::Url;
@@ -12,25 +15,27 @@ use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
 
 // DEPP_API_ as prefix
 /// Default is: ```http://127.0.0.2:50051```
-fn default_addr() -> Url {
-    Url::parse("http://127.0.0.1:50051").unwrap()
+fn default_addr() -> Vec<Url> {
+    vec![Url::parse("http://127.0.0.1:50051").unwrap()]
 }
 /// Reading the environment variables.
 pub fn get_config() -> Result<RpcEnvConfig, envy::Error> {
     envy::prefixed("DEPP_API_").from_env::<RpcEnvConfig>()
 }
 /// The RPC config via the environment variables using ```DEPP_API_``` as prefix.
+/// Each platform takes a comma separated list of testing servers.
 #[derive(Deserialize, Debug)]
 pub struct RpcEnvConfig {
     #[serde(default = "default_addr")]
-    linux_rpc_url: Url,
+    linux_rpc_url: Vec<Url>,
     #[serde(default = "default_addr")]
-    ms_rpc_url: Url,
+    ms_rpc_url: Vec<Url>,
 }
 /// Bucket for storing the Windows and Linux RPC status.
 pub struct AllEndpointStatus {
     pub windows: EndPointStatus,
     pub linux: EndPointStatus,
+    pub nodes: Vec<NodeStatus>,
 }
 
 /// To distinguish which RPC host is on which platform.
@@ -49,13 +54,188 @@ impl RpcMeta {
     }
 }
 
+/// One testing server with its health and the number of tests currently sent to it.
+#[derive(Debug)]
+pub struct RpcNode {
+    pub meta: RpcMeta,
+    outstanding: AtomicUsize,
+    healthy: AtomicBool,
+    /// A draining server gets no new tests, see ```/nodes/drain```.
+    draining: AtomicBool,
+}
+
+impl RpcNode {
+    fn new(meta: RpcMeta) -> Self {
+        RpcNode {
+            meta,
+            outstanding: AtomicUsize::new(0),
+            healthy: AtomicBool::new(true),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn set_draining(&self, draining: bool) {
+        if self.draining.swap(draining, Ordering::SeqCst) != draining {
+            log::info!(
+                "RPC {} {} server is {}",
+                &self.meta.platform,
+                &self.meta.rpc_url,
+                if draining {
+                    "draining"
+                } else {
+                    "no longer draining"
+                }
+            );
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
+    }
+
+    /// Takes the node out of or back into rotation.
+    pub fn set_healthy(&self, healthy: bool) {
+        if self.healthy.swap(healthy, Ordering::SeqCst) != healthy {
+            if healthy {
+                log::info!(
+                    "RPC {} {} server is online",
+                    &self.meta.platform,
+                    &self.meta.rpc_url
+                );
+            } else {
+                log::warn!(
+                    "RPC {} {} seems to be offline",
+                    &self.meta.platform,
+                    &self.meta.rpc_url
+                );
+            }
+        }
+    }
+
+    fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::SeqCst)
+    }
+
+    /// Connects to the server and updates the health and whether it's draining.
+    async fn check(&self) -> EndPointStatus {
+        use grpc_api::test_client::TestClient;
+        let rpc_url = self.meta.rpc_url.to_string();
+        match timeout(Duration::from_secs(1), TestClient::connect(rpc_url)).await {
+            Ok(Ok(mut client)) => {
+                self.set_healthy(true);
+                let request = tonic::Request::new(DrainRequest { draining: None });
+                if let Ok(Ok(reply)) = timeout(Duration::from_secs(1), client.drain(request)).await
+                {
+                    self.set_draining(reply.into_inner().draining);
+                }
+            }
+            _ => self.set_healthy(false),
+        }
+        self.status()
+    }
+
+    fn status(&self) -> EndPointStatus {
+        if self.is_healthy() {
+            EndPointStatus::Online
+        } else {
+            EndPointStatus::Offline
+        }
+    }
+
+    pub fn node_status(&self) -> NodeStatus {
+        NodeStatus {
+            platform: self.meta.platform,
+            rpc_url: self.meta.rpc_url.clone(),
+            status: self.status(),
+            draining: self.is_draining(),
+            outstanding: self.outstanding(),
+        }
+    }
+}
+
+/// Counts the test as outstanding on the node until it is dropped.
+pub struct NodeGuard<'a> {
+    node: &'a RpcNode,
+}
+
+impl Deref for NodeGuard<'_> {
+    type Target = RpcNode;
+    fn deref(&self) -> &Self::Target {
+        self.node
+    }
+}
+
+impl Drop for NodeGuard<'_> {
+    fn drop(&mut self) {
+        self.node.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// All testing servers of one platform.
+#[derive(Debug)]
+pub struct RpcPool {
+    pub platform: &'static str,
+    nodes: Vec<RpcNode>,
+}
+
+impl RpcPool {
+    fn new(urls: Vec<Url>, platform: &'static str) -> Self {
+        RpcPool {
+            platform,
+            nodes: urls
+                .into_iter()
+                .map(|url| RpcNode::new(RpcMeta::new(url, platform)))
+                .collect(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The healthy node with the least outstanding tests, ```None``` if all nodes are offline or draining.
+    pub fn pick(&self) -> Option<NodeGuard<'_>> {
+        let node = self
+            .nodes
+            .iter()
+            .filter(|node| node.is_healthy() && !node.is_draining())
+            .min_by_key(|node| node.outstanding())?;
+        node.outstanding.fetch_add(1, Ordering::SeqCst);
+        Some(NodeGuard { node })
+    }
+
+    /// Checks all nodes, the platform is online if at least one node is.
+    async fn check(&self) -> EndPointStatus {
+        let status = future::join_all(self.nodes.iter().map(|node| node.check())).await;
+        if status.iter().any(|s| *s == EndPointStatus::Online) {
+            EndPointStatus::Online
+        } else {
+            EndPointStatus::Offline
+        }
+    }
+}
+
+impl std::fmt::Display for RpcPool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "All {} testing servers seem to be not reachable",
+            self.platform
+        )
+    }
+}
+
 impl From<RpcEnvConfig> for RpcConfig {
     fn from(rpc_config: RpcEnvConfig) -> Self {
         //let cert = std::fs::read_to_string("./rootCA.pem").unwrap();
 
         Self {
-            windows: RpcMeta::new(rpc_config.ms_rpc_url, "windows"),
-            linux: RpcMeta::new(rpc_config.linux_rpc_url, "linux"),
+            windows: RpcPool::new(rpc_config.ms_rpc_url, "windows"),
+            linux: RpcPool::new(rpc_config.linux_rpc_url, "linux"),
             /*            tls_config: ClientTlsConfig::new()
             .ca_certificate(Certificate::from_pem(&cert))
             .domain_name("localhost".to_string()),*/
@@ -72,52 +252,48 @@ impl std::fmt::Display for RpcMeta {
         )
     }
 }
-/// This is the RPC config which uses the RPC client.It's used for the communication between the api and the testing server. Two server are required in order to test all necessary kind of scripts.
-/// The Linux server test all the ```Bash, Python, sed...``` scripts.
-/// And the Windows is only there to test ```PowerShell``` and ```batch``` scripts natively.
+/// This is the RPC config which uses the RPC client.It's used for the communication between the api and the testing server. Two platforms are required in order to test all necessary kind of scripts.
+/// The Linux servers test all the ```Bash, Python, sed...``` scripts.
+/// And the Windows servers are only there to test ```PowerShell``` and ```batch``` scripts natively.
+/// Each platform can have multiple servers, the load is balanced by the least outstanding tests.
 pub struct RpcConfig {
-    /// The Windows RPC Host.
-    windows: RpcMeta,
-    /// The Linux RPC Host.
-    linux: RpcMeta,
+    /// The Windows RPC Hosts.
+    windows: RpcPool,
+    /// The Linux RPC Hosts.
+    linux: RpcPool,
 }
 
 impl RpcConfig {
     /// Decides which script belongs to which platform to test.
-    pub fn meta(&self, script_type: &Script) -> &RpcMeta {
+    pub fn pool(&self, script_type: &Script) -> &RpcPool {
         match script_type.target_os() {
             TargetOs::Windows => &self.windows,
             TargetOs::Unix => &self.linux,
         }
     }
-    /// The status of the RPC Host.
+    /// Both platforms.
+    pub fn endpoints(&self) -> [&RpcPool; 2] {
+        [&self.windows, &self.linux]
+    }
+    /// Checks all RPC Hosts, unhealthy ones are taken out of rotation until they are reachable again.
     pub async fn status(&self) -> AllEndpointStatus {
-        use grpc_api::test_client::TestClient;
-        let linux_rpc_url = self.linux.rpc_url.to_string();
-        let ms_rpc_url = self.windows.rpc_url.to_string();
-        let time_out_duration = Duration::from_secs(1);
-        let linux_status = timeout(time_out_duration, TestClient::connect(linux_rpc_url)).await;
-        let windows_status = timeout(time_out_duration, TestClient::connect(ms_rpc_url)).await;
+        let (windows, linux) = future::join(self.windows.check(), self.linux.check()).await;
         AllEndpointStatus {
-            windows: endpoint_status(windows_status, &self.windows),
-            linux: endpoint_status(linux_status, &self.linux),
+            windows,
+            linux,
+            nodes: self
+                .endpoints()
+                .iter()
+                .flat_map(|pool| pool.nodes.iter())
+                .map(RpcNode::node_status)
+                .collect(),
         }
     }
-}
-fn endpoint_status<T, E>(r: Result<T, E>, context: &RpcMeta) -> EndPointStatus {
-    if r.is_ok() {
-        log::info!(
-            "RPC {} {} server is online",
-            &context.platform,
-            &context.rpc_url
-        );
-        EndPointStatus::Online
-    } else {
-        log::warn!(
-            "RPC {} {} seems to be offline",
-            &context.platform,
-            &context.rpc_url
-        );
-        EndPointStatus::Offline
+    /// The testing server with the url of any platform.
+    pub fn node(&self, rpc_url: &Url) -> Option<&RpcNode> {
+        self.endpoints()
+            .iter()
+            .flat_map(|pool| pool.nodes.iter())
+            .find(|node| &node.meta.rpc_url == rpc_url)
     }
 }
@@ -1,12 +1,15 @@
 //! Maneging the global state accessed by multiple threads.
-use crate::api::{EndPointStatus, IliasId};
+use crate::api::EndPointStatus;
+use crate::attestation::ResultSigner;
+use crate::events::Events;
 use crate::handlers::auth::Credentials;
+use crate::queue::{Queue, QueueConfig};
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
 use crate::rpc_conf::{RpcConfig, RpcEnvConfig};
+use crate::webhook::WebhookConfig;
 use deadpool_postgres::Pool;
-use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 /// Wrapper for the [InnerState](struct.InnerState.html) which uses [Arc](https://doc.rust-lang.org/std/sync/struct.Arc.html) to have thread save shareable state.
 #[derive(Clone)]
@@ -15,26 +18,44 @@ pub struct State {
 }
 /// You can call it the real state
 pub struct InnerState {
-    /// All ready test results waiting to be picked up. Maybe store them in a DB instead of memory?!.
-    pub pending_results: dashmap::DashMap<IliasId, grpc_api::AssignmentResult>,
     /// RPC Config so all handlers have access to it.
     pub rpc_conf: RpcConfig,
     ///The HTTP basic access authentication credentials.
     pub credentials: Credentials,
-    /// Thread safe hashset of all submissions which are not tested yet.
-    pub to_test_assignments: RwLock<HashSet<IliasId>>,
-    /// DB connection pool using deadpool
+    /// Wakes up the dispatchers of the job queue.
+    pub queue: Queue,
+    /// Config for delivering results to the callback urls.
+    pub webhook: WebhookConfig,
+    /// The status changes of the submissions for the Server-Sent Events.
+    pub events: Events,
+    /// Signs the results, see [attestation](../attestation/index.html).
+    pub signer: ResultSigner,
+    /// Limits the requests of each API client.
+    pub rate_limiter: RateLimiter,
+    /// DB connection pool using deadpool, submissions and their results are stored there too.
     pub db_pool: Pool,
 }
 
 impl State {
-    pub fn new(rpc_conf: RpcEnvConfig, credentials: Credentials, db_pool: Pool) -> State {
+    pub fn new(
+        rpc_conf: RpcEnvConfig,
+        credentials: Credentials,
+        webhook: WebhookConfig,
+        queue: QueueConfig,
+        signer: ResultSigner,
+        rate_limit: RateLimitConfig,
+        db_pool: Pool,
+    ) -> State {
+        let rpc_conf = rpc_conf.into();
         State {
             inner: Arc::new(InnerState {
-                pending_results: dashmap::DashMap::new(),
-                rpc_conf: rpc_conf.into(),
+                queue: Queue::new(queue, &rpc_conf),
+                rpc_conf,
                 credentials,
-                to_test_assignments: RwLock::new(HashSet::new()),
+                webhook,
+                events: Events::default(),
+                signer,
+                rate_limiter: RateLimiter::new(rate_limit),
                 db_pool,
             }),
         }
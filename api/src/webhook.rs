@@ -0,0 +1,387 @@
+//! POSTs the result of a submission to its ```callbackUrl``` as soon as the test is done.
+//! Each request is signed with HMAC-SHA256 and failed deliveries are retried with exponential backoff.
+//! Every attempt is logged in the ```webhook_delivery``` table.
+use crate::db;
+use crate::handlers::error::Error;
+use crate::state::State;
+use actix_web::client::Client;
+use actix_web::http::header::CONTENT_TYPE;
+use bytes::Bytes;
+use grpc_api::AssignmentResult;
+use hmac::{Hmac, Mac, NewMac};
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use url::{Host, Url};
+
+/// Header containing the hex encoded HMAC-SHA256 of the body: ```sha256=<hex>```.
+pub const SIGNATURE_HEADER: &str = "X-Depp-Signature";
+/// Header containing the ilias id of the submission.
+pub const ILIAS_ID_HEADER: &str = "X-Depp-Ilias-Id";
+
+fn default_max_attempts() -> i32 {
+    8
+}
+
+fn default_backoff_secs() -> u64 {
+    5
+}
+
+/// The webhook config via the environment variables using ```DEPP_API_``` as prefix.
+/// Example:
+/// ```DEPP_API_WEBHOOK_SECRET="secret"```
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    /// Key for signing the body, without it submissions with a ```callbackUrl``` are rejected.
+    webhook_secret: Option<String>,
+    #[serde(default = "default_max_attempts")]
+    webhook_max_attempts: i32,
+    /// The delay before the first retry, doubled on each further retry.
+    #[serde(default = "default_backoff_secs")]
+    webhook_backoff_secs: u64,
+    /// Comma separated hosts the results may be POSTed to, e.g. ```lti.example.org,moodle.example.org```.
+    /// Without it every host is allowed except localhost and loopback, private or link local addresses.
+    #[serde(default)]
+    webhook_allowed_hosts: Vec<String>,
+}
+
+/// Reading the environment variables.
+pub fn get_config() -> Result<WebhookConfig, envy::Error> {
+    let config = envy::prefixed("DEPP_API_").from_env::<WebhookConfig>()?;
+    if config.webhook_secret.is_none() {
+        log::warn!(
+            "DEPP_API_WEBHOOK_SECRET is not set, submissions with a callbackUrl are rejected"
+        );
+    }
+    Ok(config)
+}
+
+impl WebhookConfig {
+    pub fn max_attempts(&self) -> i32 {
+        self.webhook_max_attempts
+    }
+
+    /// Rejects callback urls which are not http(s) or point to a host the API must not POST to.
+    /// Without a secret no callback url is accepted, the receiver could not verify the results.
+    pub fn check_callback_url(&self, url: &Url) -> Result<(), String> {
+        if self.webhook_secret.is_none() {
+            return Err(
+                "callbackUrl is not supported, the API has no secret to sign the results".into(),
+            );
+        }
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err("callbackUrl must be a http or https URL".into());
+        }
+        let host = url.host().ok_or("callbackUrl must have a host")?;
+        let allowed = if self.webhook_allowed_hosts.is_empty() {
+            !is_internal(&host)
+        } else {
+            let host = host.to_string();
+            self.webhook_allowed_hosts
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("callbackUrl host {} is not allowed", host))
+        }
+    }
+
+    /// Resolves the host of the callback url right before a delivery and rejects it if any of its addresses is internal.
+    /// Skipped if the hosts are configured with ```webhook_allowed_hosts```, those are trusted.
+    async fn check_resolved_host(&self, callback_url: &str) -> Result<(), String> {
+        if !self.webhook_allowed_hosts.is_empty() {
+            return Ok(());
+        }
+        let url = Url::parse(callback_url).map_err(|e| e.to_string())?;
+        let host = match url.host() {
+            Some(Host::Domain(domain)) => domain.to_string(),
+            Some(Host::Ipv4(ip)) => ip.to_string(),
+            Some(Host::Ipv6(ip)) => ip.to_string(),
+            None => return Err("callbackUrl has no host".into()),
+        };
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addresses = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| format!("resolving {} failed: {}", host, e))?;
+        for address in addresses {
+            if is_internal_ip(&address.ip()) {
+                return Err(format!(
+                    "{} resolves to the internal address {}",
+                    host,
+                    address.ip()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// ```sha256=<hex>``` of the body or ```None``` if no secret is configured.
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        self.webhook_secret.as_ref().map(|secret| {
+            let mut mac = Hmac::<sha2::Sha256>::new_varkey(secret.as_bytes())
+                .expect("HMAC accepts keys of any size");
+            mac.update(body);
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+        })
+    }
+
+    /// The delay before the given attempt, the first one is send immediately.
+    fn backoff(&self, attempt: i32) -> Duration {
+        if attempt <= 1 {
+            return Duration::from_secs(0);
+        }
+        let exponent = (attempt - 2).min(10) as u32;
+        Duration::from_secs(self.webhook_backoff_secs * 2u64.pow(exponent))
+    }
+}
+
+/// Localhost, loopback, private, link local and unspecified addresses.
+/// Names are only resolved at delivery by ```check_resolved_host```.
+fn is_internal(host: &Host<&str>) -> bool {
+    match host {
+        Host::Domain(domain) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+        Host::Ipv4(ip) => is_internal_v4(ip),
+        Host::Ipv6(ip) => is_internal_v6(ip),
+    }
+}
+
+fn is_internal_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => is_internal_v6(ip),
+    }
+}
+
+fn is_internal_v4(ip: &Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // shared address space of carrier-grade NAT, 100.64.0.0/10
+        || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+}
+
+fn is_internal_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // unique local fc00::/7 and link local fe80::/10
+        || first & 0xfe00 == 0xfc00
+        || first & 0xffc0 == 0xfe80
+        || ip.to_ipv4().map(|ip| is_internal_v4(&ip)).unwrap_or(false)
+}
+
+/// A result waiting to be delivered.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub submission_id: i32,
+    pub ilias_id: String,
+    pub callback_url: String,
+    pub result: AssignmentResult,
+    /// Number of failed attempts so far.
+    pub attempts: i32,
+}
+
+/// Delivers the result in the background.
+pub fn spawn(state: State, delivery: Delivery) {
+    actix_rt::spawn(async move {
+        let submission_id = delivery.submission_id;
+        if let Err(e) = deliver(&state, delivery).await {
+            log::error!("Webhook of submission {} failed: {}", submission_id, e);
+        }
+    });
+}
+
+/// Continues all deliveries which were interrupted by a restart.
+pub async fn resume(state: &State) -> Result<(), Error> {
+    for delivery in db::pending_deliveries(&state.db_pool, state.webhook.max_attempts()).await? {
+        spawn(state.clone(), delivery);
+    }
+    Ok(())
+}
+
+async fn deliver(state: &State, delivery: Delivery) -> Result<(), Error> {
+    let body = Bytes::from(serde_json::to_vec(&delivery.result)?);
+    let signature = match state.webhook.signature(&body) {
+        Some(signature) => signature,
+        None => {
+            log::error!(
+                "Not delivering result of {}, DEPP_API_WEBHOOK_SECRET is not set",
+                &delivery.ilias_id
+            );
+            return Ok(());
+        }
+    };
+    let client = Client::new();
+    for attempt in delivery.attempts + 1..=state.webhook.max_attempts() {
+        tokio::time::delay_for(state.webhook.backoff(attempt)).await;
+        let request = client
+            .post(&delivery.callback_url)
+            .timeout(Duration::from_secs(10))
+            .header(CONTENT_TYPE, "application/json")
+            .header(ILIAS_ID_HEADER, delivery.ilias_id.as_str())
+            .header(SIGNATURE_HEADER, signature.as_str());
+        let (status_code, error) = match state
+            .webhook
+            .check_resolved_host(&delivery.callback_url)
+            .await
+        {
+            Err(e) => (None, Some(e)),
+            Ok(()) => match request.send_body(body.clone()).await {
+                Ok(response) if response.status().is_success() => (Some(response.status()), None),
+                Ok(response) => (
+                    Some(response.status()),
+                    Some(format!("unexpected status {}", response.status())),
+                ),
+                Err(e) => (None, Some(e.to_string())),
+            },
+        };
+        let delivered = error.is_none();
+        db::log_delivery(
+            &state.db_pool,
+            delivery.submission_id,
+            attempt,
+            status_code.map(|code| code.as_u16() as i32),
+            error.as_deref(),
+            delivered,
+        )
+        .await?;
+        if delivered {
+            log::info!(
+                "Delivered result of {} to {}",
+                &delivery.ilias_id,
+                &delivery.callback_url
+            );
+            return Ok(());
+        }
+        log::warn!(
+            "Delivering result of {} to {} failed (attempt {}): {}",
+            &delivery.ilias_id,
+            &delivery.callback_url,
+            attempt,
+            error.unwrap_or_default()
+        );
+    }
+    log::error!(
+        "Giving up delivering result of {} to {}",
+        &delivery.ilias_id,
+        &delivery.callback_url
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(secret: Option<&str>, allowed_hosts: &[&str]) -> WebhookConfig {
+        WebhookConfig {
+            webhook_secret: secret.map(String::from),
+            webhook_max_attempts: default_max_attempts(),
+            webhook_backoff_secs: default_backoff_secs(),
+            webhook_allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+        }
+    }
+
+    fn check(config: &WebhookConfig, url: &str) -> Result<(), String> {
+        config.check_callback_url(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn signature_is_hex_hmac_sha256() {
+        // RFC 4231 test case 2, lti::submit::verify_signature expects the same format
+        assert_eq!(
+            config(Some("Jefe"), &[]).signature(b"what do ya want for nothing?"),
+            Some(
+                "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+                    .to_string()
+            )
+        );
+        assert_eq!(config(None, &[]).signature(b"body"), None);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let config = config(None, &[]);
+        assert_eq!(config.backoff(1), Duration::from_secs(0));
+        assert_eq!(config.backoff(2), Duration::from_secs(5));
+        assert_eq!(config.backoff(4), Duration::from_secs(20));
+        assert_eq!(config.backoff(100), Duration::from_secs(5 * 1024));
+    }
+
+    #[test]
+    fn public_callback_urls() {
+        let config = config(Some("secret"), &[]);
+        assert!(check(&config, "https://lti.example.org/lti/result/1").is_ok());
+        assert!(check(&config, "http://93.184.216.34:8080/").is_ok());
+        assert!(check(&config, "http://[2001:db8::1]/").is_ok());
+        assert!(check(&config, "ftp://lti.example.org/").is_err());
+    }
+
+    #[test]
+    fn internal_callback_urls_are_rejected() {
+        let config = config(Some("secret"), &[]);
+        for url in &[
+            "http://localhost:7000/",
+            "http://LOCALHOST./",
+            "http://api.localhost/",
+            "http://127.0.0.1/",
+            "http://0.0.0.0/",
+            "http://10.1.2.3/",
+            "http://172.16.0.1/",
+            "http://192.168.178.1/",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+        ] {
+            assert!(check(&config, url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn allowed_hosts() {
+        let config = config(Some("secret"), &["lti", "192.168.1.10"]);
+        assert!(check(&config, "http://lti:7000/lti/result/1").is_ok());
+        assert!(check(&config, "http://LTI/").is_ok());
+        assert!(check(&config, "http://192.168.1.10/").is_ok());
+        assert!(check(&config, "https://lti.example.org/").is_err());
+        assert!(check(&config, "http://127.0.0.1/").is_err());
+    }
+
+    #[test]
+    fn callback_urls_need_a_secret() {
+        let config = config(None, &["lti.example.org"]);
+        assert!(check(&config, "https://lti.example.org/lti/result/1").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn resolved_addresses_are_checked() {
+        let public = config(Some("secret"), &[]);
+        assert!(public
+            .check_resolved_host("http://93.184.216.34:8080/")
+            .await
+            .is_ok());
+        for url in &[
+            "http://localhost:7000/",
+            "http://127.0.0.1/",
+            "http://[::1]/",
+        ] {
+            assert!(public.check_resolved_host(url).await.is_err(), "{}", url);
+        }
+        let allowed = config(Some("secret"), &["localhost"]);
+        assert!(allowed
+            .check_resolved_host("http://localhost:7000/")
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,122 @@
+//! Managing the clients of the REST API. Each client gets its own bearer token and scopes.
+//! Only the sha256 of the token is stored, the token itself is shown once after creating the client.
+use crate::assignments::get::parse_path;
+use crate::db::rows_into;
+use crate::error::HttpError;
+use crate::handler::{redirect, render_template, HttpResult};
+use crate::template::TEMPLATES;
+use crate::State;
+use actix_web::web;
+use deadpool_postgres::Pool;
+use postgres_types::{FromSql, ToSql};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use sha2::Digest;
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[derive(Debug, Clone, Copy, PartialEq, ToSql, FromSql, serde::Serialize)]
+#[postgres(name = "api_scope")]
+pub enum ApiScope {
+    Submit,
+    ReadResults,
+    ListAssignments,
+    Admin,
+}
+
+#[derive(Debug, Clone, PostgresMapper, serde::Serialize)]
+#[pg_mapper(table = "api_client")]
+pub struct ApiClient {
+    id: i32,
+    name: String,
+    token_prefix: String,
+    scopes: Vec<ApiScope>,
+    created_at: String,
+    revoked: bool,
+}
+
+/// The scopes are single checkboxes.
+#[derive(serde::Deserialize)]
+pub struct ApiClientForm {
+    name: String,
+    scope_submit: Option<String>,
+    scope_read_results: Option<String>,
+    scope_list_assignments: Option<String>,
+    scope_admin: Option<String>,
+}
+
+impl ApiClientForm {
+    fn scopes(&self) -> Vec<ApiScope> {
+        vec![
+            (&self.scope_submit, ApiScope::Submit),
+            (&self.scope_read_results, ApiScope::ReadResults),
+            (&self.scope_list_assignments, ApiScope::ListAssignments),
+            (&self.scope_admin, ApiScope::Admin),
+        ]
+        .into_iter()
+        .filter(|(checked, _)| checked.is_some())
+        .map(|(_, scope)| scope)
+        .collect()
+    }
+}
+
+/// Renders all clients, the new token is only passed once after creating a client.
+async fn render_clients(pool: &Pool, new_token: Option<&str>) -> HttpResult {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT id, name, token_prefix, scopes, to_char(created_at, 'DD.MM.YYYY HH24:MI') AS created_at,
+                      revoked_at IS NOT NULL AS revoked
+               FROM api_client
+               ORDER BY revoked, name;"#,
+            &[],
+        )
+        .await?;
+    let mut context = tera::Context::new();
+    context.insert("clients", &rows_into::<ApiClient>(rows));
+    context.insert("new_token", &new_token);
+    render_template(&TEMPLATES, "api_clients.html", &context)
+}
+
+/// Renders the ```api_clients.html``` page.
+pub async fn page(data: web::Data<State>) -> HttpResult {
+    render_clients(&data.db_pool, None).await
+}
+
+/// Creates a new client with a random token.
+pub async fn insert(form: web::Form<ApiClientForm>, data: web::Data<State>) -> HttpResult {
+    let token = format!(
+        "depp_{}",
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(40)
+            .collect::<String>()
+    );
+    let token_hash = sha2::Sha256::digest(token.as_bytes()).to_vec();
+    let client = data.db_pool.get().await?;
+    let stmt = client
+        .prepare(
+            "INSERT INTO api_client(name, token_hash, token_prefix, scopes) VALUES($1, $2, $3, $4)",
+        )
+        .await?;
+    client
+        .execute(
+            &stmt,
+            &[&form.name.trim(), &token_hash, &&token[..9], &form.scopes()],
+        )
+        .await?;
+    log::info!("Created API client {}", &form.name);
+    render_clients(&data.db_pool, Some(&token)).await
+}
+
+/// Revokes the token of the client, it can't be used anymore.
+pub async fn revoke(data: web::Data<State>, path: web::Path<String>) -> HttpResult {
+    let id = parse_path(&path.into_inner())?;
+    let client = data.db_pool.get().await?;
+    let stmt = client
+        .prepare("UPDATE api_client SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL")
+        .await?;
+    if client.execute(&stmt, &[&id]).await? == 0 {
+        return Err(HttpError::NotFound(format!("API-Client {}", id)));
+    }
+    Ok(redirect("/api_clients"))
+}
@@ -3,3 +3,4 @@ pub mod edit;
 pub mod file;
 pub mod get;
 pub mod new;
+pub mod regex_rule;
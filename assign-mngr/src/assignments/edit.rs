@@ -1,4 +1,5 @@
 use crate::assignments::get::Assignment;
+use crate::assignments::regex_rule;
 use crate::handler::HttpResult;
 use crate::State;
 use actix_web::{http, web, HttpRequest, HttpResponse};
@@ -9,18 +10,24 @@ pub async fn update(
     path: web::Path<uuid::Uuid>,
     req: HttpRequest,
 ) -> HttpResult {
-    let client = data.db_pool.get().await?;
+    let mut client = data.db_pool.get().await?;
     let uuid = path.into_inner();
     let asign = form.into_inner();
+    asign.validate()?;
 
-    let stmt = client.prepare(r#"
+    let transaction = client.transaction().await?;
+    let stmt = transaction.prepare(r#"
     UPDATE assignment
     SET assignment_name = $1, solution = $2, script_type = $3, description = $4, active = $5, 
                     exercise_id = $6, args = $7, compare_fs_solution = $8, compare_stdout_solution = $9,
-                    custom_script = $10, regex = $11, regex_check_mode = $12, sort_stdout = $13
-    WHERE uuid = $14
+                    custom_script = $10, sort_stdout = $11,
+                    lint_mode = $12, lint_fail_severity = $13, required_commands = $14, forbidden_commands = $15,
+                    custom_script_type = $16, entry_point = $17, feedback_level = $18,
+                    max_attempts = $19, submission_cooldown_secs = $20,
+                    revision = revision + 1
+    WHERE uuid = $21
     "#).await?;
-    client
+    transaction
         .execute(
             &stmt,
             &[
@@ -34,13 +41,22 @@ pub async fn update(
                 &asign.compare_fs_solution,
                 &asign.compare_stdout_solution,
                 &asign.custom_script,
-                &asign.regex,
-                &asign.regex_check_mode,
                 &asign.sort_stdout,
+                &asign.lint_mode,
+                &asign.lint_fail_severity,
+                &asign.required_commands,
+                &asign.forbidden_commands,
+                &asign.custom_script_type,
+                &asign.entry_point,
+                &asign.feedback_level,
+                &asign.max_attempts,
+                &asign.submission_cooldown_secs,
                 &uuid,
             ],
         )
         .await?;
+    regex_rule::save(&transaction, &uuid, &asign.regex_rules).await?;
+    transaction.commit().await?;
     Ok(HttpResponse::Found()
         .header(http::header::LOCATION, req.path())
         .finish())
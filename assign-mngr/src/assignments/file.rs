@@ -54,7 +54,9 @@ pub async fn update_files(
 
     let client = data.db_pool.get().await?;
     let stmt = client
-        .prepare("UPDATE assignment SET include_files = $1 WHERE uuid = $2")
+        .prepare(
+            "UPDATE assignment SET include_files = $1, revision = revision + 1 WHERE uuid = $2",
+        )
         .await?;
     client.execute(&stmt, &[&zip_file, &uuid]).await?;
     Ok(redirect(format!("/assignment/{}", &uuid)))
@@ -1,12 +1,13 @@
 use crate::assignments::file;
 use crate::assignments::new::fix_newlines;
+use crate::assignments::regex_rule::RegexRules;
 use crate::db::{get_exercise_description_for_id, rows_into, ScriptType};
 use crate::error::HttpError;
 use crate::handler::{render_template, HttpResult};
 use crate::template::TEMPLATES;
 use crate::{db, State};
 use actix_web::web;
-use grpc_api::{RegexMode, SortStdoutBy};
+use grpc_api::{FeedbackLevel, LintMode, Severity, SortStdoutBy};
 use serde::{Deserialize, Deserializer};
 use tokio_pg_mapper::FromTokioPostgresRow;
 use tokio_pg_mapper_derive::PostgresMapper;
@@ -52,6 +53,7 @@ pub async fn all_assignments_for_exercise(
     let mut context = tera::Context::new();
     context.insert("assignments", &assignments);
     context.insert("exercise_name", &exercise_name);
+    context.insert("exercise_id", &id);
     render_template(&TEMPLATES, "assignments_list.html", &context)
 }
 
@@ -65,6 +67,64 @@ where
     }
 }
 
+/// Comma separated command names, empty entries are dropped.
+fn to_commands<'de, D>(deserial: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserial).unwrap_or_default();
+    Ok(split_commands(&s))
+}
+
+pub fn split_commands(s: &str) -> Vec<String> {
+    s.split(",")
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// The file run for zip submissions, only the file name is kept.
+fn to_entry_point<'de, D>(deserial: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserial).unwrap_or_default();
+    Ok(entry_point(&s))
+}
+
+/// Max attempts per student, empty or 0 means unlimited.
+fn to_max_attempts<'de, D>(deserial: D) -> Result<Option<i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserial).unwrap_or_default();
+    Ok(max_attempts(&s))
+}
+
+pub fn max_attempts(s: &str) -> Option<i32> {
+    s.trim().parse::<i32>().ok().filter(|n| *n > 0)
+}
+
+/// Seconds between two submissions of a student, empty means no cooldown.
+fn to_cooldown_secs<'de, D>(deserial: D) -> Result<i32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserial).unwrap_or_default();
+    Ok(cooldown_secs(&s))
+}
+
+pub fn cooldown_secs(s: &str) -> i32 {
+    s.trim().parse::<i32>().unwrap_or_default().max(0)
+}
+
+pub fn entry_point(s: &str) -> Option<String> {
+    std::path::Path::new(s.trim())
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+}
+
 fn to_args<'de, D>(deserial: D) -> Result<Vec<String>, D::Error>
 where
     D: Deserializer<'de>,
@@ -125,10 +185,44 @@ pub struct Assignment {
     pub compare_stdout_solution: bool,
     #[serde(deserialize_with = "de_some_string", default)]
     pub custom_script: Option<String>,
-    #[serde(deserialize_with = "de_some_string", default)]
-    pub regex: Option<String>,
-    pub regex_check_mode: RegexMode,
+    pub custom_script_type: ScriptType,
     pub sort_stdout: SortStdoutBy,
+    pub lint_mode: LintMode,
+    pub lint_fail_severity: Severity,
+    pub feedback_level: FeedbackLevel,
+    #[serde(deserialize_with = "to_max_attempts", default)]
+    pub max_attempts: Option<i32>,
+    #[serde(deserialize_with = "to_cooldown_secs", default)]
+    pub submission_cooldown_secs: i32,
+    #[serde(deserialize_with = "to_commands", default)]
+    pub required_commands: Vec<String>,
+    #[serde(deserialize_with = "to_commands", default)]
+    pub forbidden_commands: Vec<String>,
+    #[serde(deserialize_with = "to_entry_point", default)]
+    pub entry_point: Option<String>,
+    #[serde(default)]
+    pub regex_rules: RegexRules,
+}
+
+impl Assignment {
+    /// Rejects settings the testing server can't check.
+    pub fn validate(&self) -> Result<(), HttpError> {
+        let has_commands =
+            !self.required_commands.is_empty() || !self.forbidden_commands.is_empty();
+        let checks_commands = matches!(
+            self.script_type,
+            ScriptType::Bash | ScriptType::Shell | ScriptType::PowerShell
+        );
+        if has_commands && !checks_commands {
+            return Err(HttpError::InvalidAssignment(
+                "Erforderliche und verbotene Befehle können nur für Bash, Shell und PowerShell geprüft werden.".into(),
+            ));
+        }
+        for rule in &self.regex_rules.0 {
+            rule.validate().map_err(HttpError::InvalidAssignment)?;
+        }
+        Ok(())
+    }
 }
 
 pub async fn single_assignment(path: web::Path<uuid::Uuid>, data: web::Data<State>) -> HttpResult {
@@ -136,7 +230,11 @@ pub async fn single_assignment(path: web::Path<uuid::Uuid>, data: web::Data<Stat
     let pool = &data.db_pool;
     let client = pool.get().await?;
     let stmt = client.prepare(r#"SELECT assignment_name as name, script_type, active, include_files, solution, description, 
-                                                         uuid, args, exercise_id, compare_fs_solution, compare_stdout_solution, regex, custom_script, regex_check_mode, sort_stdout
+                                                         uuid, args, exercise_id, compare_fs_solution, compare_stdout_solution, custom_script, custom_script_type, sort_stdout,
+                                                         lint_mode, lint_fail_severity, required_commands, forbidden_commands, entry_point, feedback_level,
+                                                         max_attempts, submission_cooldown_secs,
+                                                         (SELECT coalesce(json_agg(r ORDER BY r.position, r.id), '[]')
+                                                          FROM regex_rule r WHERE r.assignment_id = assignment.id) as regex_rules
                                                    FROM assignment
                                                    WHERE uuid = $1;"#).await?;
 
@@ -1,6 +1,10 @@
 use crate::assignments::file::check_type_is_zip;
-use crate::assignments::get::Assignment;
+use crate::assignments::get::{
+    cooldown_secs, entry_point, max_attempts, split_commands, Assignment,
+};
+use crate::assignments::regex_rule;
 use crate::db::ScriptType;
+use crate::error::HttpError;
 use crate::handler::{redirect, render_template, HttpResult};
 use crate::template::TEMPLATES;
 use crate::{db, State};
@@ -10,12 +14,19 @@ use futures::StreamExt;
 use std::collections::HashMap;
 use std::str::FromStr;
 
-fn into_assignment_form(h: &mut HashMap<String, String>, zip: &[u8]) -> Assignment {
+fn into_assignment_form(
+    h: &mut HashMap<String, String>,
+    zip: &[u8],
+) -> Result<Assignment, HttpError> {
+    let regex_rules =
+        regex_rule::parse(h.get("regex_rules").unwrap_or(&String::new())).map_err(|e| {
+            HttpError::InvalidAssignment(format!("Die Regex-Regeln sind ungültig: {}", e))
+        })?;
     let fixed_args = match h.get("args") {
         Some(a) => a.split(",").map(&remove_whitespace).collect(),
         None => vec![],
     };
-    Assignment {
+    Ok(Assignment {
         uuid: Default::default(),
         name: h.get("name").unwrap_or(&String::new()).into(),
         solution: fix_newlines(h.get("solution").unwrap_or(&String::new())),
@@ -32,10 +43,24 @@ fn into_assignment_form(h: &mut HashMap<String, String>, zip: &[u8]) -> Assignme
         compare_fs_solution: checkbox_into(h.get("compare_fs_solution")),
         compare_stdout_solution: checkbox_into(h.get("compare_stdout_solution")),
         custom_script: h.get("custom_script").and_then(|x| Some(fix_newlines(x))),
-        regex: h.get("regex").and_then(|x| Some(x.to_string())),
-        regex_check_mode: h.get("regex_check_mode").into(),
+        custom_script_type: h
+            .get("custom_script_type")
+            .and_then(|s| ScriptType::from_str(s).ok())
+            .unwrap_or(ScriptType::Bash),
         sort_stdout: h.get("sort_stdout").into(),
-    }
+        lint_mode: h.get("lint_mode").into(),
+        lint_fail_severity: h.get("lint_fail_severity").into(),
+        feedback_level: h.get("feedback_level").into(),
+        max_attempts: h.get("max_attempts").and_then(|s| max_attempts(s)),
+        submission_cooldown_secs: h
+            .get("submission_cooldown_secs")
+            .map(|s| cooldown_secs(s))
+            .unwrap_or_default(),
+        required_commands: split_commands(h.get("required_commands").unwrap_or(&String::new())),
+        forbidden_commands: split_commands(h.get("forbidden_commands").unwrap_or(&String::new())),
+        entry_point: h.get("entry_point").and_then(|s| entry_point(s)),
+        regex_rules,
+    })
 }
 
 fn checkbox_into<T: AsRef<str>>(s: Option<T>) -> bool {
@@ -58,7 +83,8 @@ pub async fn insert(data: web::Data<State>, mut payload: Multipart) -> HttpResul
             }
         }
     }
-    let assign = into_assignment_form(&mut text_fields, &zip_file);
+    let assign = into_assignment_form(&mut text_fields, &zip_file)?;
+    assign.validate()?;
     db::insert_assignment(&data.db_pool, &assign).await?;
     Ok(redirect(format!("/exercise/{}", &assign.exercise_id,)))
 }
@@ -71,18 +97,16 @@ pub fn fix_newlines(s: &str) -> String {
     s.replace("\r\n", "\n")
 }
 
+/// Reads all chunks of the field, long values like the regex rules arrive in more than one.
+/// Fields without any chunk are left out like before.
 async fn convert_field(field: &mut Field) -> Option<(String, String)> {
     let content_disposition = &field.content_disposition().unwrap();
-    if let Some(f_name) = content_disposition.get_name() {
-        if let Some(s) = field.next().await {
-            if let Ok(v) = String::from_utf8(s.unwrap_or_default().to_vec()) {
-                return Some((f_name.to_string(), v));
-            } else {
-                return None;
-            }
-        }
+    let f_name = content_disposition.get_name()?.to_string();
+    let mut bytes = field.next().await?.ok()?.to_vec();
+    while let Some(chunk) = field.next().await {
+        bytes.extend_from_slice(&chunk.ok()?);
     }
-    None
+    String::from_utf8(bytes).ok().map(|v| (f_name, v))
 }
 
 pub async fn get_form(data: web::Data<State>) -> HttpResult {
@@ -0,0 +1,216 @@
+//! The regex rules of an assignment. They are edited as a JSON list inside the assignment form.
+use grpc_api::RegexTarget;
+use postgres_types::{FromSql, Type};
+use regex::RegexBuilder;
+use serde::{Deserialize, Deserializer};
+use tokio_postgres::Transaction;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct RegexRule {
+    pub pattern: String,
+    pub target: RegexTarget,
+    #[serde(default)]
+    pub file_name: Option<String>,
+    pub must_match: bool,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    #[serde(default)]
+    pub multi_line: bool,
+    #[serde(default)]
+    pub dot_all: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl RegexRule {
+    /// Compiles the pattern with the flags of the rule like the testing server does,
+    /// so a broken rule is rejected when the assignment is saved and not when a student submits.
+    pub fn validate(&self) -> Result<(), String> {
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_all)
+            .build()
+            .map_err(|e| {
+                format!(
+                    "Das Muster {} ist kein gültiger regulärer Ausdruck: {}",
+                    self.pattern, e
+                )
+            })?;
+        let has_file_name = self
+            .file_name
+            .as_ref()
+            .is_some_and(|name| !name.trim().is_empty());
+        if self.target == RegexTarget::OutputFile && !has_file_name {
+            return Err(format!(
+                "Die Regel mit dem Muster {} prüft eine Ausgabedatei, aber es ist kein Dateiname angegeben.",
+                self.pattern
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// All rules of an assignment. Selected as one ```json_agg``` column, so the assignment is still mapped from a single row.
+#[derive(serde::Serialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct RegexRules(pub Vec<RegexRule>);
+
+impl<'a> FromSql<'a> for RegexRules {
+    fn from_sql(
+        ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        // jsonb starts with a version byte
+        let raw = if *ty == Type::JSONB { &raw[1..] } else { raw };
+        Ok(RegexRules(serde_json::from_slice(raw)?))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::JSON || *ty == Type::JSONB
+    }
+}
+
+/// The form field is the JSON list written by ```form.js```.
+impl<'de> Deserialize<'de> for RegexRules {
+    fn deserialize<D>(deserial: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserial)?;
+        parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+pub fn parse(s: &str) -> Result<RegexRules, serde_json::Error> {
+    if s.trim().is_empty() {
+        return Ok(RegexRules::default());
+    }
+    let rules: Vec<RegexRule> = serde_json::from_str(s)?;
+    Ok(RegexRules(
+        rules
+            .into_iter()
+            .filter(|rule| !rule.pattern.is_empty())
+            .collect(),
+    ))
+}
+
+/// Replaces all rules of the assignment, the order is kept.
+/// Runs inside the transaction which saves the assignment, so it never ends up with only some of its rules.
+pub async fn save(
+    transaction: &Transaction<'_>,
+    uuid: &uuid::Uuid,
+    rules: &RegexRules,
+) -> Result<(), tokio_postgres::Error> {
+    transaction
+        .execute(
+            "DELETE FROM regex_rule WHERE assignment_id = (SELECT id FROM assignment WHERE uuid = $1)",
+            &[uuid],
+        )
+        .await?;
+    let stmt = transaction
+        .prepare(
+            r#"INSERT INTO regex_rule(assignment_id, position, pattern, target, file_name, must_match,
+                                             case_insensitive, multi_line, dot_all, message)
+               VALUES((SELECT id FROM assignment WHERE uuid = $1), $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
+        )
+        .await?;
+    for (position, rule) in rules.0.iter().enumerate() {
+        transaction
+            .execute(
+                &stmt,
+                &[
+                    uuid,
+                    &(position as i32),
+                    &rule.pattern,
+                    &rule.target,
+                    &rule.file_name,
+                    &rule.must_match,
+                    &rule.case_insensitive,
+                    &rule.multi_line,
+                    &rule.dot_all,
+                    &rule.message,
+                ],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, target: RegexTarget) -> RegexRule {
+        RegexRule {
+            pattern: pattern.to_string(),
+            target,
+            file_name: None,
+            must_match: true,
+            case_insensitive: false,
+            multi_line: false,
+            dot_all: false,
+            message: None,
+        }
+    }
+
+    #[test]
+    fn empty_field_has_no_rules() {
+        assert!(parse("").unwrap().0.is_empty());
+        assert!(parse("  \n").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        assert!(parse("[{\"pattern\": \"a\"").is_err());
+        assert!(
+            parse("[{\"pattern\": \"a\", \"target\": \"Nowhere\", \"must_match\": true}]").is_err()
+        );
+    }
+
+    #[test]
+    fn rules_without_pattern_are_dropped() {
+        let rules = parse(
+            r#"[{"pattern": "", "target": "Stdout", "must_match": true},
+                {"pattern": "^done$", "target": "Stderr", "must_match": false}]"#,
+        )
+        .unwrap();
+        assert_eq!(rules.0.len(), 1);
+        let rule = &rules.0[0];
+        assert_eq!(rule.pattern, "^done$");
+        assert_eq!(rule.target, RegexTarget::Stderr);
+        assert!(!rule.must_match);
+        assert!(!rule.case_insensitive && !rule.multi_line && !rule.dot_all);
+        assert_eq!(rule.file_name, None);
+        assert_eq!(rule.message, None);
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected() {
+        assert!(rule("(unclosed", RegexTarget::Stdout).validate().is_err());
+        assert!(rule("a{2,1}", RegexTarget::ScriptContent)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn pattern_is_built_with_the_flags_of_the_rule() {
+        let mut with_flags = rule(r"^echo .*$", RegexTarget::ScriptContent);
+        with_flags.case_insensitive = true;
+        with_flags.multi_line = true;
+        with_flags.dot_all = true;
+        assert!(with_flags.validate().is_ok());
+        // inline flags are checked together with the flags of the rule
+        assert!(rule("(?z)a", RegexTarget::Stdout).validate().is_err());
+    }
+
+    #[test]
+    fn output_file_needs_a_file_name() {
+        let mut output_file = rule("result", RegexTarget::OutputFile);
+        assert!(output_file.validate().is_err());
+        output_file.file_name = Some(" ".to_string());
+        assert!(output_file.validate().is_err());
+        output_file.file_name = Some("result.txt".to_string());
+        assert!(output_file.validate().is_ok());
+    }
+}
@@ -1,5 +1,6 @@
 //! General Database functions.
 use crate::assignments::get::Assignment;
+use crate::assignments::regex_rule;
 use crate::error::HttpError;
 use db_lib::DbError;
 use deadpool_postgres::Pool;
@@ -19,6 +20,9 @@ pub enum ScriptType {
     Bash,
     Awk,
     Sed,
+    C,
+    Java,
+    Rust,
 }
 
 impl FromStr for ScriptType {
@@ -32,6 +36,9 @@ impl FromStr for ScriptType {
             "Bash" => Ok(ScriptType::Bash),
             "Awk" => Ok(ScriptType::Awk),
             "Sed" => Ok(ScriptType::Sed),
+            "C" => Ok(ScriptType::C),
+            "Java" => Ok(ScriptType::Java),
+            "Rust" => Ok(ScriptType::Rust),
             _ => Err(()),
         }
     }
@@ -93,12 +100,16 @@ pub async fn get_exercise_description_for_id(pool: &Pool, id: i32) -> Result<Str
 }
 
 pub async fn insert_assignment(pool: &Pool, assign: &Assignment) -> Result<(), DbError> {
-    let client = pool.get().await?;
-    let stmt = client.prepare(r#"INSERT INTO assignment(assignment_name, script_type, solution, exercise_id, args, description, 
-                                                                    include_files, compare_fs_solution, compare_stdout_solution, custom_script, regex, regex_check_mode)
-                                                  Values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"#).await?;
-    client
-        .execute(
+    let mut client = pool.get().await?;
+    let transaction = client.transaction().await?;
+    let stmt = transaction.prepare(r#"INSERT INTO assignment(assignment_name, script_type, solution, exercise_id, args, description, 
+                                                                    include_files, compare_fs_solution, compare_stdout_solution, custom_script,
+                                                                    lint_mode, lint_fail_severity, required_commands, forbidden_commands, custom_script_type,
+                                                                    entry_point, feedback_level, max_attempts, submission_cooldown_secs)
+                                                  Values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
+                                                  RETURNING uuid"#).await?;
+    let row = transaction
+        .query_one(
             &stmt,
             &[
                 &assign.name,
@@ -111,10 +122,19 @@ pub async fn insert_assignment(pool: &Pool, assign: &Assignment) -> Result<(), D
                 &assign.compare_fs_solution,
                 &assign.compare_stdout_solution,
                 &assign.custom_script,
-                &assign.regex,
-                &assign.regex_check_mode,
+                &assign.lint_mode,
+                &assign.lint_fail_severity,
+                &assign.required_commands,
+                &assign.forbidden_commands,
+                &assign.custom_script_type,
+                &assign.entry_point,
+                &assign.feedback_level,
+                &assign.max_attempts,
+                &assign.submission_cooldown_secs,
             ],
         )
         .await?;
+    regex_rule::save(&transaction, &row.get("uuid"), &assign.regex_rules).await?;
+    transaction.commit().await?;
     Ok(())
 }
@@ -16,6 +16,8 @@ pub enum HttpError {
     WrongPassword,
     #[fail(display = "{} was not found.", _0)]
     NotFound(String),
+    #[fail(display = "{}", _0)]
+    InvalidAssignment(String),
 }
 
 impl<T> From<T> for HttpError
@@ -31,7 +33,9 @@ impl error::ResponseError for HttpError {
     fn status_code(&self) -> StatusCode {
         match self {
             HttpError::NotFound(_) => StatusCode::NOT_FOUND,
-            HttpError::WrongParameter(_) => StatusCode::BAD_REQUEST,
+            HttpError::WrongParameter(_) | HttpError::InvalidAssignment(_) => {
+                StatusCode::BAD_REQUEST
+            }
             HttpError::WrongPassword => StatusCode::UNAUTHORIZED,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -43,7 +47,9 @@ impl error::ResponseError for HttpError {
         c.insert("hide_top_btns", &false);
         let tera = TEMPLATES.borrow();
         let body = match self {
-            HttpError::NotFound(_) | HttpError::WrongParameter(_) => {
+            HttpError::NotFound(_)
+            | HttpError::WrongParameter(_)
+            | HttpError::InvalidAssignment(_) => {
                 c.insert("error_title", "Bad Request");
                 c.insert("error_msg", &self.to_string());
                 tera.render("error.html", &c).unwrap()
@@ -0,0 +1,79 @@
+//! Download of all tested attempts of an assignment or exercise as CSV or JSON for grading in spreadsheets,
+//! the rows are built by [db_lib::export](../../db_lib/export/index.html) just like in the API.
+use crate::assignments::get::parse_path;
+use crate::error::HttpError;
+use crate::handler::HttpResult;
+use crate::State;
+use actix_web::http::header::CONTENT_DISPOSITION;
+use actix_web::{web, HttpResponse};
+use db_lib::export::{ExportFormat, ExportQuery, ExportScope};
+
+/// Exports the attempts of one assignment, path is ```/manage/export/assignment/{uuid}```.
+pub async fn assignment(
+    path: web::Path<uuid::Uuid>,
+    query: web::Query<ExportQuery>,
+    data: web::Data<State>,
+) -> HttpResult {
+    let uuid = path.into_inner();
+    let client = data.db_pool.get().await?;
+    let name: String = client
+        .query_opt(
+            "SELECT assignment_name FROM assignment WHERE uuid = $1",
+            &[&uuid],
+        )
+        .await?
+        .ok_or_else(|| HttpError::NotFound(format!("Aufgabe {}", uuid)))?
+        .get(0);
+    export(&data, ExportScope::Assignment(uuid), &query, &name).await
+}
+
+/// Exports the attempts of all assignments of an exercise, path is ```/manage/export/exercise/{id}```.
+pub async fn exercise(
+    path: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    data: web::Data<State>,
+) -> HttpResult {
+    let id = parse_path(&path.into_inner())?;
+    let client = data.db_pool.get().await?;
+    let name: String = client
+        .query_opt("SELECT description FROM exercise WHERE id = $1", &[&id])
+        .await?
+        .ok_or_else(|| HttpError::NotFound(format!("Praktikum {}", id)))?
+        .get(0);
+    export(&data, ExportScope::Exercise(id), &query, &name).await
+}
+
+/// Only letters, digits, '-' and '_' are kept in the file name.
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}_ergebnisse", name)
+}
+
+async fn export(data: &State, scope: ExportScope, query: &ExportQuery, name: &str) -> HttpResult {
+    let rows = db_lib::export::export(&data.db_pool, scope, query).await?;
+    let name = file_name(name);
+    Ok(match query.format {
+        ExportFormat::Json => HttpResponse::Ok()
+            .header(
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.json\"", name),
+            )
+            .json(rows),
+        ExportFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .header(
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.csv\"", name),
+            )
+            .body(db_lib::export::to_csv(&rows)),
+    })
+}
@@ -15,6 +15,7 @@ use actix_web::{web, App, HttpServer};
 use deadpool_postgres::Pool;
 use failure::ResultExt;
 
+mod api_clients;
 mod assignments;
 mod auth;
 mod auth_middleware;
@@ -22,7 +23,9 @@ mod config;
 mod db;
 mod error;
 mod exercises;
+mod export;
 mod handler;
+mod similarity;
 mod template;
 
 #[derive(Clone)]
@@ -39,6 +42,7 @@ async fn run() -> Result<(), failure::Error> {
         db_pool: db_lib::connect_migrate().await?,
         pwd: config.password,
     };
+    similarity::fail_interrupted(&state.db_pool).await?;
 
     init_logging();
     let host = format!("0.0.0.0:{}", config.port); // default port is 5000
@@ -83,11 +87,34 @@ async fn run() -> Result<(), failure::Error> {
                             .route("/{uuid}", web::post().to(assignments::file::update_files))
                             .route("/{uuid}", web::get().to(assignments::file::download)),
                     )
+                    .service(
+                        web::resource("/similarity/pair/{id}")
+                            .route(web::get().to(similarity::pair)),
+                    )
+                    .service(
+                        web::resource("/similarity/{uuid}")
+                            .route(web::get().to(similarity::page))
+                            .route(web::post().to(similarity::start)),
+                    )
+                    .service(
+                        web::scope("/export")
+                            .route("/assignment/{uuid}", web::get().to(export::assignment))
+                            .route("/exercise/{exercise_id}", web::get().to(export::exercise)),
+                    )
                     .service(
                         web::resource("/exercise_form")
                             .route(web::get().to(exercises::page))
                             .route(web::post().to(exercises::insert)),
                     )
+                    .service(
+                        web::resource("/api_clients")
+                            .route(web::get().to(api_clients::page))
+                            .route(web::post().to(api_clients::insert)),
+                    )
+                    .service(
+                        web::resource("/api_clients/revoke/{id}")
+                            .route(web::post().to(api_clients::revoke)),
+                    )
                     .service(
                         web::scope("/exercise")
                             .route("/rename/{exercise_id}", web::post().to(exercises::rename))
@@ -0,0 +1,310 @@
+//! Similarity analysis across the submissions of an assignment to find copied solutions.
+//! The latest attempt of every student is [tokenized](tokenize/index.html), so comments, whitespace and renamed
+//! variables don't matter, and compared pairwise via [winnowing](winnow/index.html) fingerprints.
+//! An analysis is started on demand and runs in the background, only pairs above ```MIN_SIMILARITY``` are stored.
+//! Zip submissions are skipped because they have no single source code.
+pub mod tokenize;
+pub mod winnow;
+
+use crate::assignments::get::parse_path;
+use crate::db::{rows_into, ScriptType};
+use crate::error::HttpError;
+use crate::handler::{redirect, render_template, HttpResult};
+use crate::template::TEMPLATES;
+use crate::State;
+use actix_web::web;
+use deadpool_postgres::Pool;
+use postgres_types::{FromSql, ToSql};
+use std::collections::BTreeSet;
+use tokio_pg_mapper_derive::PostgresMapper;
+
+/// Pairs with a lower share of common fingerprints are not stored.
+const MIN_SIMILARITY: f32 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, ToSql, FromSql, serde::Serialize)]
+#[postgres(name = "similarity_status")]
+pub enum SimilarityStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, PostgresMapper, serde::Serialize)]
+#[pg_mapper(table = "similarity_run")]
+pub struct SimilarityRun {
+    id: i32,
+    status: SimilarityStatus,
+    submissions: i32,
+    error: Option<String>,
+    started_at: String,
+    finished_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PostgresMapper, serde::Serialize)]
+#[pg_mapper(table = "similarity_pair")]
+pub struct SimilarityPair {
+    id: i32,
+    student_a: String,
+    attempt_a: i32,
+    student_b: String,
+    attempt_b: i32,
+    percent: i32,
+    shared_fingerprints: i32,
+}
+
+/// One line of the side by side view.
+#[derive(Debug, serde::Serialize)]
+struct Line {
+    number: usize,
+    text: String,
+    matched: bool,
+}
+
+/// Marks the runs interrupted by a restart as failed, otherwise no new run could be started.
+pub async fn fail_interrupted(pool: &Pool) -> Result<(), HttpError> {
+    let client = pool.get().await?;
+    let failed = client
+        .execute(
+            r#"UPDATE similarity_run SET status = 'Failed', error = 'Abgebrochen durch Neustart', finished_at = now()
+               WHERE status = 'Running'"#,
+            &[],
+        )
+        .await?;
+    if failed > 0 {
+        log::warn!("Marked {} interrupted similarity runs as failed", failed);
+    }
+    Ok(())
+}
+
+async fn assignment_by_uuid(
+    pool: &Pool,
+    uuid: &uuid::Uuid,
+) -> Result<(i32, String, ScriptType), HttpError> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            "SELECT id, assignment_name, script_type FROM assignment WHERE uuid = $1",
+            &[uuid],
+        )
+        .await?
+        .ok_or_else(|| HttpError::NotFound(format!("Aufgabe {}", uuid)))?;
+    Ok((row.get(0), row.get(1), row.get(2)))
+}
+
+/// Renders the ```similarity.html``` page with the latest run of the assignment and its pairs.
+pub async fn page(path: web::Path<uuid::Uuid>, data: web::Data<State>) -> HttpResult {
+    let uuid = path.into_inner();
+    let (assignment_id, name, _) = assignment_by_uuid(&data.db_pool, &uuid).await?;
+    let client = data.db_pool.get().await?;
+    let run = client
+        .query(
+            r#"SELECT id, status, submissions, error,
+                      to_char(started_at, 'DD.MM.YYYY HH24:MI') AS started_at,
+                      to_char(finished_at, 'DD.MM.YYYY HH24:MI') AS finished_at
+               FROM similarity_run
+               WHERE assignment_id = $1
+               ORDER BY id DESC LIMIT 1"#,
+            &[&assignment_id],
+        )
+        .await?;
+    let run = rows_into::<SimilarityRun>(run).pop();
+    let pairs = match &run {
+        Some(run) => {
+            let rows = client
+                .query(
+                    r#"SELECT p.id, a.student_id AS student_a, a.attempt AS attempt_a,
+                              b.student_id AS student_b, b.attempt AS attempt_b,
+                              round(p.similarity * 100)::INTEGER AS percent, p.shared_fingerprints
+                       FROM similarity_pair p
+                       INNER JOIN submission a ON a.id = p.submission_a
+                       INNER JOIN submission b ON b.id = p.submission_b
+                       WHERE p.run_id = $1
+                       ORDER BY p.similarity DESC, p.shared_fingerprints DESC"#,
+                    &[&run.id],
+                )
+                .await?;
+            rows_into::<SimilarityPair>(rows)
+        }
+        None => vec![],
+    };
+    let mut context = tera::Context::new();
+    context.insert("uuid", &uuid);
+    context.insert("name", &name);
+    context.insert("run", &run);
+    context.insert("pairs", &pairs);
+    context.insert("min_percent", &((MIN_SIMILARITY * 100.0) as i32));
+    render_template(&TEMPLATES, "similarity.html", &context)
+}
+
+/// Starts a new run in the background unless one is still running for the assignment.
+pub async fn start(path: web::Path<uuid::Uuid>, data: web::Data<State>) -> HttpResult {
+    let uuid = path.into_inner();
+    let (assignment_id, name, script_type) = assignment_by_uuid(&data.db_pool, &uuid).await?;
+    let client = data.db_pool.get().await?;
+    let run = client
+        .query_opt(
+            r#"INSERT INTO similarity_run(assignment_id)
+               SELECT $1 WHERE NOT EXISTS
+                   (SELECT 1 FROM similarity_run WHERE assignment_id = $1 AND status = 'Running')
+               RETURNING id"#,
+            &[&assignment_id],
+        )
+        .await?;
+    if let Some(run) = run {
+        let run_id: i32 = run.get(0);
+        log::info!("Started similarity run {} for {}", run_id, name);
+        actix_rt::spawn(run_job(
+            data.db_pool.clone(),
+            run_id,
+            assignment_id,
+            script_type,
+        ));
+    }
+    Ok(redirect(format!("/similarity/{}", uuid)))
+}
+
+async fn run_job(pool: Pool, run_id: i32, assignment_id: i32, script_type: ScriptType) {
+    if let Err(e) = analyse(&pool, run_id, assignment_id, script_type).await {
+        log::error!("Similarity run {} failed: {}", run_id, e);
+        let result = match pool.get().await {
+            Ok(client) => client
+                .execute(
+                    r#"UPDATE similarity_run SET status = 'Failed', error = $2, finished_at = now()
+                       WHERE id = $1"#,
+                    &[&run_id, &e.to_string()],
+                )
+                .await
+                .map(|_| ())
+                .map_err(HttpError::from),
+            Err(e) => Err(HttpError::from(e)),
+        };
+        if let Err(e) = result {
+            log::error!("Marking similarity run {} as failed: {}", run_id, e);
+        }
+    }
+}
+
+/// Compares the latest attempt of each student with all others and stores the suspicious pairs.
+async fn analyse(
+    pool: &Pool,
+    run_id: i32,
+    assignment_id: i32,
+    script_type: ScriptType,
+) -> Result<(), HttpError> {
+    let mut client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT DISTINCT ON (student_id) id, source_code
+               FROM submission
+               WHERE assignment_id = $1 AND source_code <> ''
+               ORDER BY student_id, attempt DESC"#,
+            &[&assignment_id],
+        )
+        .await?;
+    let submissions: Vec<(i32, String)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    let count = submissions.len() as i32;
+    let pairs = web::block(move || Ok::<_, ()>(compare(&script_type, &submissions))).await?;
+    let transaction = client.transaction().await?;
+    let stmt = transaction
+        .prepare(
+            r#"INSERT INTO similarity_pair(run_id, submission_a, submission_b, similarity, shared_fingerprints)
+               VALUES($1, $2, $3, $4, $5)"#,
+        )
+        .await?;
+    for (a, b, similarity, shared) in &pairs {
+        transaction
+            .execute(&stmt, &[&run_id, a, b, similarity, &(*shared as i32)])
+            .await?;
+    }
+    transaction
+        .execute(
+            r#"UPDATE similarity_run SET status = 'Done', submissions = $2, finished_at = now()
+               WHERE id = $1"#,
+            &[&run_id, &count],
+        )
+        .await?;
+    transaction.commit().await?;
+    log::info!(
+        "Similarity run {} compared {} submissions, {} suspicious pairs",
+        run_id,
+        count,
+        pairs.len()
+    );
+    Ok(())
+}
+
+/// Returns each pair of submissions above ```MIN_SIMILARITY``` with its similarity and number of shared fingerprints.
+fn compare(script_type: &ScriptType, submissions: &[(i32, String)]) -> Vec<(i32, i32, f32, usize)> {
+    let fingerprints: Vec<_> = submissions
+        .iter()
+        .map(|(id, source)| {
+            let tokens = tokenize::tokenize(script_type, source);
+            (*id, winnow::fingerprints(&tokens))
+        })
+        .collect();
+    let mut pairs = vec![];
+    for (n, (id_a, a)) in fingerprints.iter().enumerate() {
+        for (id_b, b) in &fingerprints[n + 1..] {
+            let (similarity, shared) = winnow::similarity(a, b);
+            if similarity >= MIN_SIMILARITY {
+                pairs.push((*id_a, *id_b, similarity, shared));
+            }
+        }
+    }
+    pairs
+}
+
+fn lines(source: &str, matched: &BTreeSet<usize>) -> Vec<Line> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(n, text)| Line {
+            number: n + 1,
+            text: text.to_string(),
+            matched: matched.contains(&(n + 1)),
+        })
+        .collect()
+}
+
+/// Renders the ```similarity_pair.html``` page, both scripts side by side with the matching lines highlighted.
+pub async fn pair(path: web::Path<String>, data: web::Data<State>) -> HttpResult {
+    let id = parse_path(&path.into_inner())?;
+    let client = data.db_pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"SELECT asg.uuid, asg.assignment_name, asg.script_type,
+                      a.student_id, a.attempt, a.source_code,
+                      b.student_id, b.attempt, b.source_code,
+                      round(p.similarity * 100)::INTEGER
+               FROM similarity_pair p
+               INNER JOIN submission a ON a.id = p.submission_a
+               INNER JOIN submission b ON b.id = p.submission_b
+               INNER JOIN assignment asg ON asg.id = a.assignment_id
+               WHERE p.id = $1"#,
+            &[&id],
+        )
+        .await?
+        .ok_or_else(|| HttpError::NotFound(format!("Paar {}", id)))?;
+    let uuid: uuid::Uuid = row.get(0);
+    let name: String = row.get(1);
+    let script_type: ScriptType = row.get(2);
+    let source_a: String = row.get(5);
+    let source_b: String = row.get(8);
+    let tokens_a = tokenize::tokenize(&script_type, &source_a);
+    let tokens_b = tokenize::tokenize(&script_type, &source_b);
+    let fingerprints_a = winnow::fingerprints(&tokens_a);
+    let fingerprints_b = winnow::fingerprints(&tokens_b);
+    let matched_a = winnow::matched_lines(&tokens_a, &fingerprints_a, &fingerprints_b);
+    let matched_b = winnow::matched_lines(&tokens_b, &fingerprints_b, &fingerprints_a);
+    let mut context = tera::Context::new();
+    context.insert("uuid", &uuid);
+    context.insert("name", &name);
+    context.insert("percent", &row.get::<_, i32>(9));
+    context.insert("student_a", &row.get::<_, String>(3));
+    context.insert("attempt_a", &row.get::<_, i32>(4));
+    context.insert("lines_a", &lines(&source_a, &matched_a));
+    context.insert("student_b", &row.get::<_, String>(6));
+    context.insert("attempt_b", &row.get::<_, i32>(7));
+    context.insert("lines_b", &lines(&source_b, &matched_b));
+    render_template(&TEMPLATES, "similarity_pair.html", &context)
+}
@@ -0,0 +1,397 @@
+//! A small lexer per language which normalizes a script into tokens. Comments and whitespace are dropped,
+//! names of variables and functions become ```ID```, string literals ```STR``` and numbers ```NUM```.
+//! So renaming variables or rewording comments does not change the tokens.
+use crate::db::ScriptType;
+
+/// One normalized token and the line it starts in, counting from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+}
+
+/// How identifiers are treated.
+#[derive(Clone, Copy, PartialEq)]
+enum Names {
+    /// Every word which is not a keyword is a name.
+    Words,
+    /// Only words after the sigil are variable names, other words are commands and are kept.
+    Sigil(char),
+    /// Everything is kept, e.g. sed has no names.
+    Keep,
+}
+
+/// The syntax of a language as far as the lexer needs it.
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+    names: Names,
+    case_insensitive: bool,
+}
+
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "in", "function", "return", "local", "export", "select",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "break", "class", "continue", "def", "del", "elif", "else", "except",
+    "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not",
+    "or", "pass", "raise", "return", "try", "while", "with", "yield", "None", "True", "False",
+    "print", "len", "range", "open", "input", "int", "str",
+];
+const C_KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "int", "long", "register", "return", "short",
+    "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void",
+    "volatile", "while", "include", "define", "main", "printf", "scanf", "malloc", "free",
+];
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "extends",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "int",
+    "interface",
+    "long",
+    "new",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "throws",
+    "try",
+    "void",
+    "while",
+    "null",
+    "true",
+    "false",
+    "String",
+    "System",
+    "out",
+    "println",
+    "main",
+];
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static",
+    "struct", "trait", "true", "false", "type", "use", "where", "while", "println", "main",
+    "String", "Vec", "Some", "None", "Ok", "Err",
+];
+const AWK_KEYWORDS: &[&str] = &[
+    "BEGIN", "END", "if", "else", "while", "for", "do", "break", "continue", "next", "exit",
+    "function", "return", "in", "delete", "print", "printf", "NR", "NF", "FS", "OFS", "RS",
+];
+const POWERSHELL_KEYWORDS: &[&str] = &[
+    "if", "else", "elseif", "switch", "foreach", "for", "while", "do", "until", "break",
+    "continue", "return", "function", "param", "begin", "process", "end", "try", "catch",
+    "finally", "throw", "in",
+];
+const BATCH_KEYWORDS: &[&str] = &[
+    "if", "else", "for", "in", "do", "goto", "call", "set", "echo", "exit", "not", "exist",
+    "defined", "setlocal", "endlocal",
+];
+
+fn syntax(script_type: &ScriptType) -> Syntax {
+    match script_type {
+        ScriptType::Shell | ScriptType::Bash => Syntax {
+            line_comments: &["#"],
+            block_comments: &[],
+            quotes: &['"', '\'', '`'],
+            keywords: SHELL_KEYWORDS,
+            names: Names::Sigil('$'),
+            case_insensitive: false,
+        },
+        ScriptType::Python3 => Syntax {
+            line_comments: &["#"],
+            block_comments: &[],
+            quotes: &['"', '\''],
+            keywords: PYTHON_KEYWORDS,
+            names: Names::Words,
+            case_insensitive: false,
+        },
+        ScriptType::C => Syntax {
+            line_comments: &["//"],
+            block_comments: &[("/*", "*/")],
+            quotes: &['"', '\''],
+            keywords: C_KEYWORDS,
+            names: Names::Words,
+            case_insensitive: false,
+        },
+        ScriptType::Java => Syntax {
+            line_comments: &["//"],
+            block_comments: &[("/*", "*/")],
+            quotes: &['"', '\''],
+            keywords: JAVA_KEYWORDS,
+            names: Names::Words,
+            case_insensitive: false,
+        },
+        ScriptType::Rust => Syntax {
+            line_comments: &["//"],
+            block_comments: &[("/*", "*/")],
+            quotes: &['"'],
+            keywords: RUST_KEYWORDS,
+            names: Names::Words,
+            case_insensitive: false,
+        },
+        ScriptType::Awk => Syntax {
+            line_comments: &["#"],
+            block_comments: &[],
+            quotes: &['"'],
+            keywords: AWK_KEYWORDS,
+            names: Names::Words,
+            case_insensitive: false,
+        },
+        ScriptType::Sed => Syntax {
+            line_comments: &["#"],
+            block_comments: &[],
+            quotes: &[],
+            keywords: &[],
+            names: Names::Keep,
+            case_insensitive: false,
+        },
+        ScriptType::PowerShell => Syntax {
+            line_comments: &["#"],
+            block_comments: &[("<#", "#>")],
+            quotes: &['"', '\''],
+            keywords: POWERSHELL_KEYWORDS,
+            names: Names::Sigil('$'),
+            case_insensitive: true,
+        },
+        ScriptType::Batch => Syntax {
+            line_comments: &["::", "rem ", "REM ", "@rem ", "@REM "],
+            block_comments: &[],
+            quotes: &['"'],
+            keywords: BATCH_KEYWORDS,
+            names: Names::Sigil('%'),
+            case_insensitive: true,
+        },
+    }
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Splits the source into normalized tokens.
+pub fn tokenize(script_type: &ScriptType, source: &str) -> Vec<Token> {
+    let syntax = syntax(script_type);
+    let chars: Vec<char> = source.chars().collect();
+    let starts_with = |i: usize, pattern: &str| {
+        pattern
+            .chars()
+            .enumerate()
+            .all(|(n, p)| chars.get(i + n) == Some(&p))
+    };
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // a '#' inside a word like $# is no comment
+        let word_before = i > 0 && !chars[i - 1].is_whitespace() && chars[i - 1] != ';';
+        if let Some(comment) = syntax
+            .line_comments
+            .iter()
+            .find(|comment| starts_with(i, comment) && !(word_before && comment.starts_with('#')))
+        {
+            i += comment.len();
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if let Some((_, end)) = syntax
+            .block_comments
+            .iter()
+            .find(|(start, _)| starts_with(i, start))
+        {
+            i += 2;
+            while i < chars.len() && !starts_with(i, end) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += end.len();
+            continue;
+        }
+        let start_line = line;
+        if syntax.quotes.contains(&c) {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&'\n') {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token {
+                text: "STR".into(),
+                line: start_line,
+            });
+            continue;
+        }
+        if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token {
+                text: "NUM".into(),
+                line: start_line,
+            });
+            continue;
+        }
+        if is_name_start(c) {
+            let start = i;
+            while i < chars.len() && is_name_char(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let word = if syntax.case_insensitive {
+                word.to_lowercase()
+            } else {
+                word
+            };
+            let keyword = syntax
+                .keywords
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(&word) && (syntax.case_insensitive || *k == word));
+            let after_sigil = match syntax.names {
+                Names::Sigil(sigil) => {
+                    start > 0 && (chars[start - 1] == sigil || chars[start - 1] == '{')
+                }
+                _ => false,
+            };
+            // shell variables are assigned without the sigil: name=value
+            let assigned = chars.get(i) == Some(&'=') && chars.get(i + 1) != Some(&'=');
+            let text = match syntax.names {
+                _ if keyword => word,
+                Names::Words => "ID".into(),
+                Names::Sigil(_) if after_sigil || assigned => "ID".into(),
+                Names::Sigil(_) | Names::Keep => word,
+            };
+            tokens.push(Token {
+                text,
+                line: start_line,
+            });
+            continue;
+        }
+        tokens.push(Token {
+            text: c.to_string(),
+            line: start_line,
+        });
+        i += 1;
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(script_type: ScriptType, source: &str) -> Vec<String> {
+        tokenize(&script_type, source)
+            .into_iter()
+            .map(|token| token.text)
+            .collect()
+    }
+
+    #[test]
+    fn shell_keeps_commands_and_normalizes_variables() {
+        assert_eq!(
+            texts(
+                ScriptType::Bash,
+                "count=1; echo \"$count\" | sort # sort it"
+            ),
+            vec!["ID", "=", "NUM", ";", "echo", "STR", "|", "sort"]
+        );
+        assert_eq!(
+            texts(ScriptType::Bash, "for f in $files; do cat ${f}; done"),
+            vec!["for", "f", "in", "$", "ID", ";", "do", "cat", "$", "{", "ID", "}", ";", "done"]
+        );
+    }
+
+    #[test]
+    fn hash_inside_a_word_is_no_comment() {
+        assert_eq!(texts(ScriptType::Bash, "echo $#"), vec!["echo", "$", "#"]);
+    }
+
+    #[test]
+    fn renaming_and_comments_do_not_matter() {
+        let a = "def add(a, b):\n    # sum\n    return a + b\n";
+        let b = "def plus(x, y):\n    return x + y  # the sum\n";
+        assert_eq!(texts(ScriptType::Python3, a), texts(ScriptType::Python3, b));
+        assert_eq!(
+            texts(ScriptType::C, "int n = 2; /* two */ printf(\"%d\", n);"),
+            texts(
+                ScriptType::C,
+                "int count = 3; // three\nprintf(\"%i\", count);"
+            )
+        );
+    }
+
+    #[test]
+    fn powershell_is_case_insensitive() {
+        assert_eq!(
+            texts(ScriptType::PowerShell, "Write-Host $Name <# comment #>"),
+            texts(ScriptType::PowerShell, "write-host $other")
+        );
+    }
+
+    #[test]
+    fn batch_comments() {
+        assert_eq!(
+            texts(ScriptType::Batch, "REM hello\n:: world\necho %name%"),
+            vec!["echo", "%", "ID", "%"]
+        );
+    }
+
+    #[test]
+    fn lines_count_from_one() {
+        let tokens = tokenize(&ScriptType::C, "/* a\n b */ int\n\"x\ny\" z");
+        let lines: Vec<usize> = tokens.iter().map(|token| token.line).collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+    }
+}
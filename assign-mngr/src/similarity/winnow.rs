@@ -0,0 +1,146 @@
+//! Fingerprinting via winnowing like [MOSS](https://theory.stanford.edu/~aiken/publications/papers/sigmod03.pdf).
+//! Every ```K``` consecutive tokens are hashed and from each window of ```WINDOW``` hashes the smallest one is kept.
+//! So every match of at least ```K + WINDOW - 1``` tokens is found.
+use super::tokenize::Token;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Length of the hashed token sequences.
+const K: usize = 5;
+/// Number of hashes in each window.
+const WINDOW: usize = 4;
+
+/// A selected hash and the index of its first token.
+#[derive(Debug, Clone, Copy)]
+pub struct Fingerprint {
+    pub hash: u64,
+    pub start: usize,
+}
+
+fn hash_kgram(tokens: &[Token]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for token in tokens {
+        token.text.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Selects the fingerprints of the tokens, scripts shorter than ```K``` tokens have none.
+pub fn fingerprints(tokens: &[Token]) -> Vec<Fingerprint> {
+    if tokens.len() < K {
+        return vec![];
+    }
+    let hashes: Vec<u64> = tokens.windows(K).map(hash_kgram).collect();
+    let window = WINDOW.min(hashes.len());
+    let mut selected: Vec<Fingerprint> = vec![];
+    for (offset, hashes) in hashes.windows(window).enumerate() {
+        // the rightmost minimum, so a repeated minimum is only selected once
+        let (pos, hash) = hashes
+            .iter()
+            .enumerate()
+            .fold((0, u64::MAX), |min, (pos, hash)| {
+                if *hash <= min.1 {
+                    (pos, *hash)
+                } else {
+                    min
+                }
+            });
+        let start = offset + pos;
+        if selected.last().map(|last| last.start) != Some(start) {
+            selected.push(Fingerprint { hash, start });
+        }
+    }
+    selected
+}
+
+/// Returns the share of common fingerprints relative to the smaller script and their count.
+pub fn similarity(a: &[Fingerprint], b: &[Fingerprint]) -> (f32, usize) {
+    let a: HashSet<u64> = a.iter().map(|f| f.hash).collect();
+    let b: HashSet<u64> = b.iter().map(|f| f.hash).collect();
+    let smaller = a.len().min(b.len());
+    if smaller == 0 {
+        return (0.0, 0);
+    }
+    let shared = a.intersection(&b).count();
+    (shared as f32 / smaller as f32, shared)
+}
+
+/// The lines of ```tokens``` which are covered by a fingerprint also found in ```other```.
+pub fn matched_lines(
+    tokens: &[Token],
+    fingerprints: &[Fingerprint],
+    other: &[Fingerprint],
+) -> BTreeSet<usize> {
+    let other: HashSet<u64> = other.iter().map(|f| f.hash).collect();
+    fingerprints
+        .iter()
+        .filter(|f| other.contains(&f.hash))
+        .flat_map(|f| tokens[f.start..f.start + K].iter().map(|t| t.line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One token per word, each line of the text is a line of the script.
+    fn tokens(text: &str) -> Vec<Token> {
+        text.lines()
+            .enumerate()
+            .flat_map(|(n, line)| {
+                line.split_whitespace().map(move |word| Token {
+                    text: word.into(),
+                    line: n + 1,
+                })
+            })
+            .collect()
+    }
+
+    fn starts(fingerprints: &[Fingerprint]) -> Vec<usize> {
+        fingerprints.iter().map(|f| f.start).collect()
+    }
+
+    #[test]
+    fn shorter_than_k_has_no_fingerprints() {
+        assert!(fingerprints(&tokens("a b c d")).is_empty());
+        assert!(fingerprints(&[]).is_empty());
+        assert_eq!(fingerprints(&tokens("a b c d e")).len(), 1);
+        assert_eq!(
+            similarity(&[], &fingerprints(&tokens("a b c d e"))),
+            (0.0, 0)
+        );
+    }
+
+    #[test]
+    fn equal_minimum_takes_the_rightmost_once() {
+        // all k-grams are equal, so every window has the same minimum
+        let same = tokens(&"a ".repeat(K + WINDOW - 1));
+        assert_eq!(starts(&fingerprints(&same)), vec![WINDOW - 1]);
+        let longer = tokens(&"a ".repeat(K + WINDOW + 2));
+        let selected = starts(&fingerprints(&longer));
+        assert!(selected.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(selected.last(), Some(&(longer.len() - K)));
+    }
+
+    #[test]
+    fn identical_and_disjoint_scripts() {
+        let a = fingerprints(&tokens("if ID = NUM then echo STR fi done"));
+        let b = fingerprints(&tokens("while read ID do sort uniq cat ID done"));
+        assert_eq!(similarity(&a, &a), (1.0, a.len()));
+        assert_eq!(similarity(&a, &b).1, 0);
+    }
+
+    #[test]
+    fn shared_sequence_is_found_and_its_lines_are_matched() {
+        let shared = "for ID in ID do\necho ID | sort\ndone";
+        let a = tokens(&format!("cat ID\n{}", shared));
+        let b = tokens(&format!("{}\nuniq -c ID\nwc", shared));
+        let (fa, fb) = (fingerprints(&a), fingerprints(&b));
+        let (share, count) = similarity(&fa, &fb);
+        assert!(count > 0 && share > 0.0 && share <= 1.0);
+        let lines = matched_lines(&a, &fa, &fb);
+        assert!(!lines.is_empty());
+        assert!(lines.iter().all(|line| (2..=4).contains(line)));
+    }
+}
@@ -1,18 +1,71 @@
-function onchangeRegexRadio(target) {
-    const form = document.querySelector("#RegexInputForm");
-    const input = form.querySelector("#RegexInput");
-    if (target.value === "UnknownRegex"){
-        form.hidden = true;
-        input.required = false;
-        if (input.name){
-            input.removeAttribute("name");
-        }
-    }else {
-        form.hidden = false;
-        input.required = true;
-        input.setAttribute("name", "regex");
-    }
+const regexTargets = [
+    ["Stdout", "Konsolen Ausgabe"],
+    ["Stderr", "Fehlerausgabe"],
+    ["ScriptContent", "Skriptinhalt"],
+    ["OutputFile", "Ausgabedatei"],
+];
+
+function addRegexRule(rule = {multi_line: true, must_match: true}) {
+    const row = document.createElement("div");
+    row.className = "regex-rule border rounded p-2 mb-2";
+    row.innerHTML = `
+        <div class="form-row">
+            <div class="col-5"><input type="text" spellcheck="false" class="form-control mono-font rule-pattern" placeholder="Regex (Perl-style)" required></div>
+            <div class="col-2"><select class="form-control rule-polarity">
+                <option value="must">muss passen</option>
+                <option value="must_not">darf nicht passen</option>
+            </select></div>
+            <div class="col-2"><select class="form-control rule-target" onchange="onchangeRegexTarget(this)">
+                ${regexTargets.map(([value, label]) => `<option value="${value}">${label}</option>`).join("")}
+            </select></div>
+            <div class="col-2"><input type="text" spellcheck="false" class="form-control mono-font rule-file" placeholder="Dateiname"></div>
+            <div class="col-1"><button type="button" class="btn btn-outline-danger w-100" onclick="this.closest('.regex-rule').remove()">&times;</button></div>
+        </div>
+        <div class="form-row mt-2 align-items-center">
+            <div class="col-5"><input type="text" class="form-control rule-message" placeholder="Meldung bei Fehlschlag (optional)"></div>
+            <div class="col-7">
+                <label class="ml-2 mb-0"><input type="checkbox" class="rule-i"> i (Groß-/Kleinschreibung ignorieren)</label>
+                <label class="ml-2 mb-0"><input type="checkbox" class="rule-m"> m (zeilenweise)</label>
+                <label class="ml-2 mb-0"><input type="checkbox" class="rule-s"> s (. passt auf Zeilenumbruch)</label>
+            </div>
+        </div>`;
+    row.querySelector(".rule-pattern").value = rule.pattern || "";
+    row.querySelector(".rule-polarity").value = rule.must_match === false ? "must_not" : "must";
+    row.querySelector(".rule-target").value = rule.target || "Stdout";
+    row.querySelector(".rule-file").value = rule.file_name || "";
+    row.querySelector(".rule-message").value = rule.message || "";
+    row.querySelector(".rule-i").checked = !!rule.case_insensitive;
+    row.querySelector(".rule-m").checked = !!rule.multi_line;
+    row.querySelector(".rule-s").checked = !!rule.dot_all;
+    document.querySelector("#RegexRules").appendChild(row);
+    onchangeRegexTarget(row.querySelector(".rule-target"));
+}
+
+function onchangeRegexTarget(select) {
+    const file = select.closest(".regex-rule").querySelector(".rule-file");
+    file.hidden = select.value !== "OutputFile";
+    file.required = !file.hidden;
 }
 
-document.querySelector(".radio-regex>input[type='radio']:checked").click();
+function regexRulesJson() {
+    const rules = [...document.querySelectorAll("#RegexRules .regex-rule")].map((row) => ({
+        pattern: row.querySelector(".rule-pattern").value,
+        target: row.querySelector(".rule-target").value,
+        file_name: row.querySelector(".rule-file").value || null,
+        must_match: row.querySelector(".rule-polarity").value === "must",
+        case_insensitive: row.querySelector(".rule-i").checked,
+        multi_line: row.querySelector(".rule-m").checked,
+        dot_all: row.querySelector(".rule-s").checked,
+        message: row.querySelector(".rule-message").value || null,
+    }));
+    return JSON.stringify(rules);
+}
+
+document.querySelector("#AssignmentForm").addEventListener("formdata", (e) => {
+    e.formData.append("regex_rules", regexRulesJson());
+});
 
+const hiddenRegexRules = document.querySelector("#hiddenRegexRules");
+if (hiddenRegexRules) {
+    JSON.parse(hiddenRegexRules.textContent).forEach((rule) => addRegexRule(rule));
+}
@@ -30,7 +30,7 @@ document.body.onload = (e) => {
 			window.customScriptEditor = createEditor(
 				"#customScriptEditor",
 				customScriptText,
-				"shell"
+				scriptToLanguage(document.querySelector("#CustomScriptType").value)
 			);
 			document
 				.querySelector("#AssignmentForm")
@@ -90,11 +90,24 @@ function scriptToLanguage(lang) {
 			break;
 		case "Batch":
 			return "bat";
+		case "C":
+			return "c";
+		case "Java":
+			return "java";
+		case "Rust":
+			return "rust";
 		default:
 			return "shell";
 	}
 }
 
+function customScriptLanguageChanged(select) {
+	if (window.customScriptEditor) {
+		const model = window.customScriptEditor.getModel();
+		monaco.editor.setModelLanguage(model, scriptToLanguage(select.value));
+	}
+}
+
 function changeEditorLanguage(lang) {
 	if (window.soluitionEditor) {
 		const model = window.soluitionEditor.getModel();
@@ -0,0 +1,33 @@
+CREATE TYPE submission_status AS ENUM(
+    'Queued',
+    'Running',
+    'Done'
+);
+
+CREATE TABLE submission(
+    id SERIAL PRIMARY KEY,
+    ilias_id text NOT NULL,
+    assignment_id INTEGER REFERENCES assignment(id) ON DELETE CASCADE NOT NULL,
+    source_code text NOT NULL,
+    status submission_status NOT NULL DEFAULT 'Queued',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    started_at timestamptz,
+    finished_at timestamptz,
+    -- set when the result was picked up, the ilias id can be used again afterwards
+    acknowledged_at timestamptz
+);
+
+CREATE UNIQUE INDEX submission_open_ilias_id ON submission(ilias_id) WHERE acknowledged_at IS NULL;
+CREATE INDEX submission_status_idx ON submission(status) WHERE status <> 'Done';
+
+CREATE TABLE result(
+    id SERIAL PRIMARY KEY,
+    submission_id INTEGER REFERENCES submission(id) ON DELETE CASCADE NOT NULL UNIQUE,
+    passed boolean NOT NULL,
+    valid boolean NOT NULL,
+    compilation_failed boolean NOT NULL,
+    message text,
+    -- the AssignmentResult as returned by the API
+    full_result jsonb NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,15 @@
+ALTER TABLE submission
+    ADD COLUMN callback_url text;
+
+-- every attempt to POST a result to the callback url of a submission
+CREATE TABLE webhook_delivery(
+    id SERIAL PRIMARY KEY,
+    submission_id INTEGER REFERENCES submission(id) ON DELETE CASCADE NOT NULL,
+    attempt INTEGER NOT NULL,
+    status_code INTEGER,
+    error text,
+    delivered boolean NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX webhook_delivery_submission_idx ON webhook_delivery(submission_id);
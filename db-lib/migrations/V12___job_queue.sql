@@ -0,0 +1,13 @@
+-- the submission table is the job queue of the API
+ALTER TABLE submission
+    ADD COLUMN platform text NOT NULL DEFAULT 'linux',
+    ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN next_run_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN last_error text;
+
+UPDATE submission SET platform = 'windows'
+    FROM assignment
+    WHERE submission.assignment_id = assignment.id AND assignment.script_type IN ('PowerShell', 'Batch');
+
+DROP INDEX submission_status_idx;
+CREATE INDEX submission_queue_idx ON submission(platform, next_run_at) WHERE status = 'Queued';
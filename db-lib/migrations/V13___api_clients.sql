@@ -0,0 +1,17 @@
+CREATE TYPE api_scope AS ENUM(
+    'Submit',
+    'ReadResults',
+    'ListAssignments',
+    'Admin'
+);
+
+CREATE TABLE api_client(
+    id SERIAL PRIMARY KEY,
+    name text NOT NULL UNIQUE,
+    -- sha256 of the bearer token, the token itself is only shown once after creating the client
+    token_hash bytea NOT NULL UNIQUE,
+    token_prefix text NOT NULL,
+    scopes api_scope[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    revoked_at timestamptz
+);
@@ -0,0 +1,18 @@
+-- every submission is one attempt of a student on an assignment, the student id defaults to the ilias id
+ALTER TABLE submission
+    ADD COLUMN student_id text,
+    ADD COLUMN attempt INTEGER;
+
+UPDATE submission SET student_id = ilias_id, attempt = numbered.attempt
+    FROM (SELECT id, row_number() OVER (PARTITION BY ilias_id, assignment_id ORDER BY created_at, id) AS attempt
+          FROM submission) AS numbered
+    WHERE submission.id = numbered.id;
+
+ALTER TABLE submission
+    ALTER COLUMN student_id SET NOT NULL,
+    ALTER COLUMN attempt SET NOT NULL,
+    ADD CONSTRAINT submission_attempt_unique UNIQUE (student_id, assignment_id, attempt);
+
+-- an ilias id can be submitted again, the result endpoint returns its latest attempt
+DROP INDEX submission_open_ilias_id;
+CREATE INDEX submission_ilias_id_idx ON submission(ilias_id);
@@ -0,0 +1,5 @@
+-- the file run for zip submissions, relative to the root of the archive
+ALTER TABLE assignment ADD COLUMN entry_point text;
+
+-- a zip archive with multiple files instead of the single source_code
+ALTER TABLE submission ADD COLUMN source_zip bytea;
@@ -0,0 +1,30 @@
+CREATE TYPE similarity_status AS ENUM(
+    'Running',
+    'Done',
+    'Failed'
+);
+
+-- one on demand similarity analysis over the latest attempts of an assignment
+CREATE TABLE similarity_run(
+    id SERIAL PRIMARY KEY,
+    assignment_id INTEGER REFERENCES assignment(id) ON DELETE CASCADE NOT NULL,
+    status similarity_status NOT NULL DEFAULT 'Running',
+    submissions INTEGER NOT NULL DEFAULT 0,
+    error text,
+    started_at timestamptz NOT NULL DEFAULT now(),
+    finished_at timestamptz
+);
+
+CREATE INDEX similarity_run_assignment_idx ON similarity_run(assignment_id);
+
+-- only pairs above the threshold are stored
+CREATE TABLE similarity_pair(
+    id SERIAL PRIMARY KEY,
+    run_id INTEGER REFERENCES similarity_run(id) ON DELETE CASCADE NOT NULL,
+    submission_a INTEGER REFERENCES submission(id) ON DELETE CASCADE NOT NULL,
+    submission_b INTEGER REFERENCES submission(id) ON DELETE CASCADE NOT NULL,
+    similarity real NOT NULL,
+    shared_fingerprints INTEGER NOT NULL
+);
+
+CREATE INDEX similarity_pair_run_idx ON similarity_pair(run_id, similarity DESC);
@@ -0,0 +1,34 @@
+-- a learning platform (LMS) registered with the LTI 1.3 tool, e.g. Moodle
+CREATE TABLE lti_platform(
+    id SERIAL PRIMARY KEY,
+    name text NOT NULL,
+    issuer text NOT NULL,
+    client_id text NOT NULL,
+    -- empty allows every deployment of the client
+    deployment_ids text[] NOT NULL DEFAULT '{}',
+    auth_login_url text NOT NULL,
+    auth_token_url text NOT NULL,
+    jwks_url text NOT NULL,
+    UNIQUE (issuer, client_id)
+);
+
+-- the state and nonce of an OIDC login, used once by the following launch
+CREATE TABLE lti_login(
+    state text PRIMARY KEY,
+    nonce text NOT NULL,
+    platform_id INTEGER REFERENCES lti_platform(id) ON DELETE CASCADE NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- a verified launch, the submissions and the score passback refer to it
+CREATE TABLE lti_launch(
+    id uuid PRIMARY KEY,
+    platform_id INTEGER REFERENCES lti_platform(id) ON DELETE CASCADE NOT NULL,
+    deployment_id text NOT NULL,
+    user_id text NOT NULL,
+    assignment_id INTEGER REFERENCES assignment(id) ON DELETE CASCADE,
+    line_item_url text,
+    deep_link_return_url text,
+    deep_link_data text,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+-- a submission cancelled via DELETE /api/submission/{iliasId} before it was tested
+ALTER TYPE submission_status ADD VALUE 'Cancelled';
@@ -0,0 +1,12 @@
+CREATE TYPE feedback_level AS ENUM(
+    'Full',
+    'DiffOnly',
+    'PassFailPerCheck',
+    'PassFailOnly'
+);
+
+ALTER TABLE assignment
+    ADD COLUMN feedback_level feedback_level not null default 'Full';
+
+-- the unredacted AssignmentResult for tutors, only set if the feedback level hid something
+ALTER TABLE result ADD COLUMN detail_result jsonb;
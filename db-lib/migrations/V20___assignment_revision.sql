@@ -0,0 +1,2 @@
+-- counts the changes of an assignment, the signed results name the revision they were tested against
+ALTER TABLE assignment ADD COLUMN revision INTEGER NOT NULL DEFAULT 1;
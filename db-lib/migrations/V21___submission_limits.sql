@@ -0,0 +1,4 @@
+-- limits of the submissions per student, no max_attempts means unlimited
+ALTER TABLE assignment
+    ADD COLUMN max_attempts INTEGER CHECK (max_attempts > 0),
+    ADD COLUMN submission_cooldown_secs INTEGER NOT NULL DEFAULT 0 CHECK (submission_cooldown_secs >= 0);
@@ -0,0 +1,3 @@
+ALTER TYPE script_type ADD VALUE 'C';
+ALTER TYPE script_type ADD VALUE 'Java';
+ALTER TYPE script_type ADD VALUE 'Rust';
@@ -0,0 +1,16 @@
+CREATE TYPE lint_mode AS ENUM(
+    'NoLint',
+    'Feedback',
+    'FailOnSeverity'
+);
+
+CREATE TYPE lint_severity AS ENUM(
+    'Style',
+    'Info',
+    'Warning',
+    'Error'
+);
+
+ALTER TABLE assignment
+    ADD COLUMN lint_mode lint_mode not null default 'NoLint',
+    ADD COLUMN lint_fail_severity lint_severity not null default 'Error';
@@ -0,0 +1,3 @@
+ALTER TABLE assignment
+    ADD COLUMN required_commands text[] not null default '{}',
+    ADD COLUMN forbidden_commands text[] not null default '{}';
@@ -0,0 +1,32 @@
+CREATE TYPE regex_target AS ENUM(
+    'Stdout',
+    'Stderr',
+    'ScriptContent',
+    'OutputFile'
+);
+
+CREATE TABLE regex_rule(
+    id SERIAL PRIMARY KEY,
+    assignment_id INTEGER REFERENCES assignment(id) ON DELETE CASCADE NOT NULL,
+    position INTEGER not null default 0,
+    pattern text not null,
+    target regex_target not null default 'Stdout',
+    file_name text, -- only for OutputFile
+    must_match boolean not null default true,
+    case_insensitive boolean not null default false,
+    multi_line boolean not null default false,
+    dot_all boolean not null default false,
+    message text
+);
+
+-- the single regex was always checked line by line
+INSERT INTO regex_rule(assignment_id, pattern, target, multi_line)
+SELECT id, regex, regex_check_mode::text::regex_target, true
+FROM assignment
+WHERE regex_check_mode <> 'UnknownRegex' AND regex IS NOT NULL AND regex <> '';
+
+ALTER TABLE assignment
+    DROP COLUMN regex,
+    DROP COLUMN regex_check_mode;
+
+DROP TYPE regex_mode;
@@ -0,0 +1,5 @@
+ALTER TABLE assignment
+    ADD COLUMN custom_script_type script_type not null default 'Bash';
+
+-- custom scripts of Windows assignments always ran with PowerShell
+UPDATE assignment SET custom_script_type = 'PowerShell' WHERE script_type IN ('PowerShell', 'Batch');
@@ -0,0 +1,350 @@
+//! Export of the tested submissions of an assignment or a whole exercise as CSV or JSON for grading in spreadsheets.
+//! Used by the REST [API](../../api) and the [Assignment Manager](../../assign-mngr).
+use crate::DbError;
+use chrono::{DateTime, NaiveDate, Utc};
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tokio_postgres::types::Json;
+use uuid::Uuid;
+
+/// What is exported.
+#[derive(Debug, Clone, Copy)]
+pub enum ExportScope {
+    Assignment(Uuid),
+    Exercise(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    #[default]
+    Json,
+}
+
+/// The query parameters of the export, the dates are inclusive.
+/// Example: ```?format=csv&from=2020-10-01&to=2021-01-31&latest=true```
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default, deserialize_with = "de_date")]
+    pub from: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "de_date")]
+    pub to: Option<NaiveDate>,
+    /// Only the latest attempt of each student in the date range.
+    #[serde(default, deserialize_with = "de_flag")]
+    pub latest: bool,
+}
+
+/// Empty date inputs of HTML forms are sent as empty string.
+fn de_date<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let date = String::deserialize(deserializer)?;
+    if date.trim().is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Checkboxes are sent as ```on```.
+fn de_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let flag = String::deserialize(deserializer)?;
+    Ok(flag == "true" || flag == "on" || flag == "1")
+}
+
+/// The outcome of one checker.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckOutcome {
+    pub name: String,
+    pub passed: bool,
+    pub message: String,
+    /// Only reported by the custom checker.
+    pub score: Option<f32>,
+}
+
+impl CheckOutcome {
+    /// A check counts fully if it passed, the score of the custom checker from 0 to 1 gives partial credit.
+    fn credit(&self) -> f32 {
+        match self.score {
+            Some(score) => score.clamp(0.0, 1.0),
+            None if self.passed => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+/// One tested attempt of a student.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRow {
+    pub student_id: String,
+    pub assignment: String,
+    pub attempt: i32,
+    pub submitted_at: DateTime<Utc>,
+    pub passed: bool,
+    /// The share of passed checks from 0 to 100 with the score of the custom checker,
+    /// without checks it's all or nothing.
+    pub score: f32,
+    pub message: Option<String>,
+    pub checks: Vec<CheckOutcome>,
+}
+
+impl ExportRow {
+    fn new(
+        student_id: String,
+        assignment: String,
+        attempt: i32,
+        submitted_at: DateTime<Utc>,
+        result: Value,
+    ) -> Self {
+        let passed = result["passed"].as_bool().unwrap_or_default();
+        let checks: Vec<CheckOutcome> = result["checks"]
+            .as_array()
+            .map(|checks| {
+                checks
+                    .iter()
+                    .map(|check| CheckOutcome {
+                        name: check["name"].as_str().unwrap_or_default().to_string(),
+                        passed: check["passed"].as_bool().unwrap_or_default(),
+                        message: check["message"].as_str().unwrap_or_default().to_string(),
+                        score: check["score"].as_f64().map(|score| score as f32),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let score = if checks.is_empty() {
+            if passed {
+                100.0
+            } else {
+                0.0
+            }
+        } else {
+            100.0 * checks.iter().map(CheckOutcome::credit).sum::<f32>() / checks.len() as f32
+        };
+        ExportRow {
+            student_id,
+            assignment,
+            attempt,
+            submitted_at,
+            passed,
+            score,
+            message: result["message"].as_str().map(String::from),
+            checks,
+        }
+    }
+}
+
+/// All tested attempts of the assignment or exercise, ordered by assignment, student and attempt.
+pub async fn export(
+    pool: &Pool,
+    scope: ExportScope,
+    query: &ExportQuery,
+) -> Result<Vec<ExportRow>, DbError> {
+    let (assignment, exercise) = match scope {
+        ExportScope::Assignment(uuid) => (Some(uuid), None),
+        ExportScope::Exercise(id) => (None, Some(id)),
+    };
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            r#"SELECT student_id, assignment_name, attempt, created_at, full_result
+               FROM (SELECT s.student_id, a.assignment_name, s.attempt, s.created_at, r.full_result,
+                            row_number() OVER (PARTITION BY s.student_id, s.assignment_id ORDER BY s.attempt DESC) AS newest
+                     FROM submission s
+                     INNER JOIN assignment a ON a.id = s.assignment_id
+                     INNER JOIN result r ON r.submission_id = s.id
+                     WHERE s.status = 'Done'
+                     AND ($1::uuid IS NULL OR a.uuid = $1)
+                     AND ($2::integer IS NULL OR a.exercise_id = $2)
+                     AND ($3::date IS NULL OR s.created_at >= $3::date)
+                     AND ($4::date IS NULL OR s.created_at < $4::date + 1)) AS attempts
+               WHERE NOT $5 OR newest = 1
+               ORDER BY assignment_name, student_id, attempt"#,
+            &[
+                &assignment,
+                &exercise,
+                &query.from,
+                &query.to,
+                &query.latest,
+            ],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let Json(result): Json<Value> = row.get("full_result");
+            ExportRow::new(
+                row.get("student_id"),
+                row.get("assignment_name"),
+                row.get("attempt"),
+                row.get("created_at"),
+                result,
+            )
+        })
+        .collect())
+}
+
+/// Quotes the field if needed. Fields which spreadsheets would evaluate as formula are prefixed with ```'```,
+/// e.g. a message ```=HYPERLINK(...)``` printed by a submission.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// One line per attempt and two columns per checker, the checkers of all rows are merged by name.
+pub fn to_csv(rows: &[ExportRow]) -> String {
+    let mut check_names: Vec<&str> = vec![];
+    for check in rows.iter().flat_map(|row| row.checks.iter()) {
+        if !check_names.contains(&check.name.as_str()) {
+            check_names.push(&check.name);
+        }
+    }
+    let mut header: Vec<String> = vec![
+        "student_id",
+        "assignment",
+        "attempt",
+        "timestamp",
+        "passed",
+        "score",
+        "message",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    for name in &check_names {
+        header.push(format!("{} passed", name));
+        header.push(format!("{} message", name));
+    }
+    let mut csv = String::new();
+    for line in std::iter::once(header).chain(rows.iter().map(|row| {
+        let mut line = vec![
+            row.student_id.clone(),
+            row.assignment.clone(),
+            row.attempt.to_string(),
+            row.submitted_at.to_rfc3339(),
+            row.passed.to_string(),
+            format!("{:.1}", row.score),
+            row.message.clone().unwrap_or_default(),
+        ];
+        for name in &check_names {
+            match row.checks.iter().find(|check| check.name == *name) {
+                Some(check) => {
+                    line.push(check.passed.to_string());
+                    line.push(check.message.clone());
+                }
+                None => {
+                    line.push(String::new());
+                    line.push(String::new());
+                }
+            }
+        }
+        line
+    })) {
+        let fields: Vec<String> = line.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(result: Value) -> ExportRow {
+        ExportRow::new(
+            "s1".into(),
+            "Sort".into(),
+            1,
+            "2020-07-01T12:00:00Z".parse().unwrap(),
+            result,
+        )
+    }
+
+    #[test]
+    fn fields_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn formulas_are_not_evaluated() {
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("\tx"), "'\tx");
+        assert_eq!(csv_field("\rx"), "\"'\rx\"");
+        assert_eq!(
+            csv_field("=HYPERLINK(\"http://evil\",\"x\")"),
+            "\"'=HYPERLINK(\"\"http://evil\"\",\"\"x\"\")\""
+        );
+        assert_eq!(csv_field("1=1"), "1=1");
+    }
+
+    #[test]
+    fn messages_of_the_checks_are_escaped() {
+        let csv = to_csv(&[row(json!({
+            "passed": false,
+            "message": "=cmd|' /C calc'!A0",
+            "checks": [{"name": "stdout", "passed": false, "message": "-wrong"}],
+        }))]);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(
+            lines[0],
+            "student_id,assignment,attempt,timestamp,passed,score,message,stdout passed,stdout message"
+        );
+        assert_eq!(
+            lines[1],
+            "s1,Sort,1,2020-07-01T12:00:00+00:00,false,0.0,'=cmd|' /C calc'!A0,false,'-wrong"
+        );
+    }
+
+    #[test]
+    fn score_is_the_share_of_passed_checks() {
+        let check = |passed: bool| json!({"name": "check", "passed": passed, "message": ""});
+        assert_eq!(row(json!({"passed": true})).score, 100.0);
+        assert_eq!(row(json!({"passed": false})).score, 0.0);
+        let checks = json!([check(true), check(true), check(true), check(false)]);
+        assert_eq!(row(json!({"passed": false, "checks": checks})).score, 75.0);
+    }
+
+    #[test]
+    fn score_of_the_custom_checker_gives_partial_credit() {
+        let result = json!({
+            "passed": false,
+            "checks": [
+                {"name": "stdout", "passed": true, "message": ""},
+                {"name": "custom", "passed": false, "message": "", "score": 0.5},
+            ],
+        });
+        let partial = row(result);
+        assert_eq!(partial.checks[1].score, Some(0.5));
+        assert_eq!(partial.score, 75.0);
+        let out_of_range = json!({
+            "passed": true,
+            "checks": [{"name": "custom", "passed": true, "message": "", "score": 7.0}],
+        });
+        assert_eq!(row(out_of_range).score, 100.0);
+    }
+}
@@ -4,6 +4,9 @@ use deadpool_postgres::{Manager, Pool};
 use serde::export::Formatter;
 use std::fmt;
 use std::fmt::Display;
+
+pub mod export;
+
 mod embedded {
     use refinery::embed_migrations;
     embed_migrations!("migrations");
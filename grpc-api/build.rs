@@ -6,12 +6,12 @@ fn main() {
             r#"#[postgres(name = "script_type")]"#,
         )
         .type_attribute(
-            "deep_project.RegexMode",
+            "deep_project.RegexTarget",
             r#"#[derive(postgres_types::FromSql, postgres_types::ToSql)]"#,
         )
         .type_attribute(
-            "deep_project.RegexMode",
-            r#"#[postgres(name = "regex_mode")]"#,
+            "deep_project.RegexTarget",
+            r#"#[postgres(name = "regex_target")]"#,
         )
         .type_attribute(
             "deep_project.SortStdoutBy",
@@ -21,11 +21,49 @@ fn main() {
             "deep_project.SortStdoutBy",
             r#"#[postgres(name = "sort_stdout_by")]"#,
         )
+        .type_attribute(
+            "deep_project.LintMode",
+            r#"#[derive(postgres_types::FromSql, postgres_types::ToSql)]"#,
+        )
+        .type_attribute(
+            "deep_project.LintMode",
+            r#"#[postgres(name = "lint_mode")]"#,
+        )
+        .type_attribute(
+            "deep_project.Severity",
+            r#"#[derive(postgres_types::FromSql, postgres_types::ToSql)]"#,
+        )
+        .type_attribute(
+            "deep_project.Severity",
+            r#"#[postgres(name = "lint_severity")]"#,
+        )
+        .type_attribute(
+            "deep_project.FeedbackLevel",
+            r#"#[derive(postgres_types::FromSql, postgres_types::ToSql)]"#,
+        )
+        .type_attribute(
+            "deep_project.FeedbackLevel",
+            r#"#[postgres(name = "feedback_level")]"#,
+        )
         .type_attribute(
             "deep_project.OptionalString",
             r#"#[derive(postgres_types::FromSql, postgres_types::ToSql)]"#,
         )
         .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        // the results are documented in the OpenAPI description of the api
+        .type_attribute(
+            "deep_project.AssignmentResult",
+            "#[derive(schemars::JsonSchema)]",
+        )
+        .type_attribute(
+            "deep_project.CheckResult",
+            "#[derive(schemars::JsonSchema)]",
+        )
+        .type_attribute("deep_project.Diagnostic", "#[derive(schemars::JsonSchema)]")
+        .type_attribute(
+            "deep_project.Attestation",
+            "#[derive(schemars::JsonSchema)]",
+        )
         .compile(&["proto/deep_project.proto"], &["proto"])
         .unwrap()
     //.type_attribute("deep_project.Assignment", "#[serde(rename_all = \"camelCase\")]")
@@ -8,13 +8,15 @@ pub type AssignmentId = uuid::Uuid;
 impl Script {
     pub fn command_line(&self) -> Vec<&str> {
         match self {
-            Script::PowerShell => (vec!["pwsh".into()]), // maybe ln -s pwsh -> powershell.exe
-            Script::Batch => (vec!["cmd.exe".into(), "/C".into()]), // only works inside wsl
-            Script::Python3 => (vec!["python3".into()]),
-            Script::Awk => (vec!["awk".into()]),
-            Script::Sed => (vec!["sed".into()]),
-            Script::Bash => (vec!["bash".into()]),
-            Script::Shell => (vec!["sh".into()]),
+            Script::PowerShell => vec!["pwsh"], // maybe ln -s pwsh -> powershell.exe
+            Script::Batch => vec!["cmd.exe", "/C"], // only works inside wsl
+            Script::Python3 => vec!["python3"],
+            Script::Awk => vec!["awk"],
+            Script::Sed => vec!["sed"],
+            Script::Bash => vec!["bash"],
+            Script::Shell => vec!["sh"],
+            Script::C | Script::Rust => vec![], // the compiled program is run directly
+            Script::Java => vec!["java"],
         }
     }
 }
@@ -23,13 +25,15 @@ impl Script {
 impl Script {
     pub fn command_line(&self) -> Vec<&str> {
         match self {
-            Script::PowerShell => (vec!["powershell.exe".into()]),
-            Script::Batch => (vec!["cmd.exe".into(), "/C".into()]),
-            Script::Python3 => (vec!["python3".into()]),
-            Script::Awk => (vec!["awk".into(), "-f".into()]),
-            Script::Sed => (vec!["sed".into(), "-f".into()]),
-            Script::Bash => (vec!["bash".into()]), // bash -c are forwarded to the WSL process without modification.
-            Script::Shell => (vec!["sh".into()]),
+            Script::PowerShell => vec!["powershell.exe"],
+            Script::Batch => vec!["cmd.exe", "/C"],
+            Script::Python3 => vec!["python3"],
+            Script::Awk => vec!["awk", "-f"],
+            Script::Sed => vec!["sed", "-f"],
+            Script::Bash => vec!["bash"], // bash -c are forwarded to the WSL process without modification.
+            Script::Shell => vec!["sh"],
+            Script::C | Script::Rust => vec![],
+            Script::Java => vec!["java"],
         }
     }
 }
@@ -43,17 +47,21 @@ impl From<i32> for Script {
             3 => Script::Shell,
             5 => Script::Awk,
             6 => Script::Sed,
+            7 => Script::C,
+            8 => Script::Java,
+            9 => Script::Rust,
             4 | _ => Script::Bash,
         }
     }
 }
 
-impl From<i32> for RegexMode {
+impl From<i32> for RegexTarget {
     fn from(n: i32) -> Self {
         match n {
-            1 => RegexMode::Stdout,
-            2 => RegexMode::ScriptContent,
-            _ => RegexMode::UnknownRegex,
+            1 => RegexTarget::Stderr,
+            2 => RegexTarget::ScriptContent,
+            3 => RegexTarget::OutputFile,
+            _ => RegexTarget::Stdout,
         }
     }
 }
@@ -68,6 +76,38 @@ impl From<i32> for SortStdoutBy {
     }
 }
 
+impl From<i32> for LintMode {
+    fn from(n: i32) -> Self {
+        match n {
+            1 => LintMode::Feedback,
+            2 => LintMode::FailOnSeverity,
+            _ => LintMode::NoLint,
+        }
+    }
+}
+
+impl From<i32> for Severity {
+    fn from(n: i32) -> Self {
+        match n {
+            0 => Severity::Style,
+            1 => Severity::Info,
+            2 => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl From<i32> for FeedbackLevel {
+    fn from(n: i32) -> Self {
+        match n {
+            1 => FeedbackLevel::DiffOnly,
+            2 => FeedbackLevel::PassFailPerCheck,
+            3 => FeedbackLevel::PassFailOnly,
+            _ => FeedbackLevel::Full,
+        }
+    }
+}
+
 impl Script {
     pub fn file_extension(&self) -> &'static str {
         match self {
@@ -75,6 +115,43 @@ impl Script {
             Script::PowerShell => ".ps1",
             Script::Python3 => ".py",
             Script::Shell | Script::Bash | Script::Sed | Script::Awk => ".sh",
+            Script::C => ".c",
+            Script::Java => ".java",
+            Script::Rust => ".rs",
+        }
+    }
+    /// Languages with a separate build step inside the container before running the tests.
+    pub fn is_compiled(&self) -> bool {
+        matches!(self, Script::C | Script::Java | Script::Rust)
+    }
+    /// The file name of the source inside the build dir. ```javac``` requires the file to be named after the public class.
+    pub fn source_file_name(&self) -> &'static str {
+        match self {
+            Script::C => "main.c",
+            Script::Java => "Main.java",
+            Script::Rust => "main.rs",
+            Script::PowerShell => "script.ps1",
+            Script::Batch => "script.bat",
+            Script::Python3 => "script.py",
+            Script::Shell | Script::Bash | Script::Sed | Script::Awk => "script.sh",
+        }
+    }
+    /// The program produced by the build step, the main class for Java.
+    pub fn executable_name(&self) -> &'static str {
+        match self {
+            Script::Java => "Main",
+            _ => "main",
+        }
+    }
+    /// The compiler invocation which is run inside the build dir.
+    pub fn compile_command(&self) -> Option<Vec<&str>> {
+        match self {
+            Script::C => Some(vec![
+                "gcc", "-std=c11", "-Wall", "-O2", "-o", "main", "main.c", "-lm",
+            ]),
+            Script::Java => Some(vec!["javac", "-encoding", "UTF-8", "Main.java"]),
+            Script::Rust => Some(vec!["rustc", "-O", "-o", "main", "main.rs"]),
+            _ => None,
         }
     }
     pub fn target_os(&self) -> TargetOs {
@@ -102,19 +179,33 @@ impl From<&tokio_postgres::row::Row> for Assignment {
             compare_fs_solution: r.get("compare_fs_solution"),
             compare_stdout_solution: r.get("compare_stdout_solution"),
             custom_script: r.get::<_, Option<String>>("custom_script"),
-            regex: r.get::<_, Option<String>>("regex"),
-            regex_mode: r.get::<_, RegexMode>("regex_check_mode") as i32,
             sort_stdout: r.get::<_, SortStdoutBy>("sort_stdout") as i32,
+            lint_mode: r.get::<_, LintMode>("lint_mode") as i32,
+            lint_fail_severity: r.get::<_, Severity>("lint_fail_severity") as i32,
+            required_commands: r.get("required_commands"),
+            forbidden_commands: r.get("forbidden_commands"),
+            custom_script_type: r.get::<_, Script>("custom_script_type") as i32,
+            entry_point: r
+                .get::<_, Option<String>>("entry_point")
+                .unwrap_or_default(),
+            feedback_level: r.get::<_, FeedbackLevel>("feedback_level") as i32,
+            // stored in their own table, see ```From<&Row> for RegexRule```
+            regex_rules: vec![],
         }
     }
 }
 
-impl From<Option<&String>> for RegexMode {
-    fn from(str: Option<&String>) -> Self {
-        match str {
-            Some(s) if s == "Stdout" => RegexMode::Stdout,
-            Some(s) if s == "ScriptContent" => RegexMode::ScriptContent,
-            _ => RegexMode::UnknownRegex,
+impl From<&tokio_postgres::row::Row> for RegexRule {
+    fn from(r: &Row) -> Self {
+        RegexRule {
+            pattern: r.get("pattern"),
+            target: r.get::<_, RegexTarget>("target") as i32,
+            file_name: r.get::<_, Option<String>>("file_name").unwrap_or_default(),
+            must_match: r.get("must_match"),
+            case_insensitive: r.get("case_insensitive"),
+            multi_line: r.get("multi_line"),
+            dot_all: r.get("dot_all"),
+            message: r.get::<_, Option<String>>("message").unwrap_or_default(),
         }
     }
 }
@@ -129,6 +220,38 @@ impl From<Option<&String>> for SortStdoutBy {
     }
 }
 
+impl From<Option<&String>> for LintMode {
+    fn from(str: Option<&String>) -> Self {
+        match str {
+            Some(s) if s == "Feedback" => LintMode::Feedback,
+            Some(s) if s == "FailOnSeverity" => LintMode::FailOnSeverity,
+            _ => LintMode::NoLint,
+        }
+    }
+}
+
+impl From<Option<&String>> for Severity {
+    fn from(str: Option<&String>) -> Self {
+        match str {
+            Some(s) if s == "Style" => Severity::Style,
+            Some(s) if s == "Info" => Severity::Info,
+            Some(s) if s == "Warning" => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl From<Option<&String>> for FeedbackLevel {
+    fn from(str: Option<&String>) -> Self {
+        match str {
+            Some(s) if s == "DiffOnly" => FeedbackLevel::DiffOnly,
+            Some(s) if s == "PassFailPerCheck" => FeedbackLevel::PassFailPerCheck,
+            Some(s) if s == "PassFailOnly" => FeedbackLevel::PassFailOnly,
+            _ => FeedbackLevel::Full,
+        }
+    }
+}
+
 impl From<String> for SortStdoutBy {
     fn from(str: String) -> Self {
         match str {
@@ -136,3 +259,60 @@ impl From<String> for SortStdoutBy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPTS: [Script; 10] = [
+        Script::PowerShell,
+        Script::Batch,
+        Script::Python3,
+        Script::Shell,
+        Script::Bash,
+        Script::Awk,
+        Script::Sed,
+        Script::C,
+        Script::Java,
+        Script::Rust,
+    ];
+
+    #[test]
+    fn compiled_languages_have_a_compile_command() {
+        for script in SCRIPTS.iter() {
+            assert_eq!(
+                script.is_compiled(),
+                script.compile_command().is_some(),
+                "{:?}",
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn compile_command_builds_the_source_into_the_executable() {
+        for script in SCRIPTS.iter().filter(|script| script.is_compiled()) {
+            let command = script.compile_command().unwrap();
+            assert!(command.contains(&script.source_file_name()), "{:?}", script);
+            if *script != Script::Java {
+                let output = command.iter().position(|arg| *arg == "-o").unwrap();
+                assert_eq!(command[output + 1], script.executable_name());
+            }
+        }
+        assert_eq!(
+            Script::Java.compile_command(),
+            Some(vec!["javac", "-encoding", "UTF-8", "Main.java"])
+        );
+        assert_eq!(Script::Java.executable_name(), "Main");
+    }
+
+    #[test]
+    fn compiled_programs_are_run_directly() {
+        assert!(Script::C.command_line().is_empty());
+        assert!(Script::Rust.command_line().is_empty());
+        assert_eq!(Script::Java.command_line(), vec!["java"]);
+        assert_eq!(Script::from(Script::Rust as i32), Script::Rust);
+        assert_eq!(Script::from(Script::Java as i32), Script::Java);
+        assert_eq!(Script::from(Script::C as i32), Script::C);
+    }
+}
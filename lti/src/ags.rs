@@ -0,0 +1,108 @@
+//! Grade passback via the Assignment and Grade Services. The tool gets an access token from the platform
+//! with a JWT signed by its key (OAuth 2 client credentials) and POSTs the score to the line item.
+use crate::db::Platform;
+use crate::error::Error;
+use crate::keys::ToolKey;
+use crate::token::SCORE_SCOPE;
+use actix_web::client::Client;
+use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+const SCORE_CONTENT_TYPE: &str = "application/vnd.ims.lis.v1.score+json";
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+/// A score between 0 and ```maximum```.
+#[derive(Debug)]
+pub struct Score {
+    pub given: f32,
+    pub maximum: f32,
+    pub comment: Option<String>,
+}
+
+async fn access_token(platform: &Platform, key: &ToolKey) -> Result<String, Error> {
+    let now = chrono::Utc::now().timestamp();
+    let assertion = key.sign(&json!({
+        "iss": platform.client_id,
+        "sub": platform.client_id,
+        "aud": platform.auth_token_url,
+        "iat": now,
+        "exp": now + 5 * 60,
+        "jti": uuid::Uuid::new_v4().to_string(),
+    }))?;
+    let http_error = |e: String| Error::Http(platform.auth_token_url.clone(), e);
+    let mut response = Client::default()
+        .post(&platform.auth_token_url)
+        .timeout(Duration::from_secs(10))
+        .send_form(&[
+            ("grant_type", "client_credentials"),
+            (
+                "client_assertion_type",
+                "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+            ),
+            ("client_assertion", assertion.as_str()),
+            ("scope", SCORE_SCOPE),
+        ])
+        .await
+        .map_err(|e| http_error(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(http_error(format!(
+            "unexpected status {}",
+            response.status()
+        )));
+    }
+    let token = response
+        .json::<AccessToken>()
+        .await
+        .map_err(|e| http_error(e.to_string()))?;
+    Ok(token.access_token)
+}
+
+/// The scores endpoint is the line item url with ```/scores``` appended to the path.
+fn scores_url(line_item_url: &str) -> Result<String, Error> {
+    let mut url = url::Url::parse(line_item_url).map_err(|e| Error::General(e.to_string()))?;
+    let path = format!("{}/scores", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+    Ok(url.to_string())
+}
+
+/// Posts the score of the user to the line item.
+pub async fn post_score(
+    platform: &Platform,
+    key: &ToolKey,
+    line_item_url: &str,
+    user_id: &str,
+    score: Score,
+) -> Result<(), Error> {
+    let token = access_token(platform, key).await?;
+    let url = scores_url(line_item_url)?;
+    let body = json!({
+        "userId": user_id,
+        "scoreGiven": score.given,
+        "scoreMaximum": score.maximum,
+        "comment": score.comment,
+        "activityProgress": "Completed",
+        "gradingProgress": "FullyGraded",
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    let response = Client::default()
+        .post(&url)
+        .timeout(Duration::from_secs(10))
+        .header(AUTHORIZATION, format!("Bearer {}", token))
+        .header(CONTENT_TYPE, SCORE_CONTENT_TYPE)
+        .send_body(body.to_string())
+        .await
+        .map_err(|e| Error::Http(url.clone(), e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(Error::Http(
+            url,
+            format!("unexpected status {}", response.status()),
+        ));
+    }
+    Ok(())
+}
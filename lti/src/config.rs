@@ -0,0 +1,57 @@
+//! Configuration of the tool provided by the environment prefixed by ```DEPP_LTI_```.
+use serde::Deserialize;
+
+/// Default port: `7000`
+fn default_port() -> u16 {
+    7000
+}
+
+fn default_api_url() -> String {
+    String::from("http://127.0.0.1:6000")
+}
+
+fn default_key_id() -> String {
+    String::from("depp-lti")
+}
+
+/// Tool config from the environment ```prefix DEPP_LTI_```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// The public URL of the tool as the platforms reach it, e.g. ```https://depp.example.org```.
+    pub tool_url: String,
+    /// The REST API which tests the submissions.
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// Bearer token of an API client with the scopes ```Submit``` and ```ListAssignments```.
+    pub api_token: String,
+    /// The same secret as ```DEPP_API_WEBHOOK_SECRET```, the results are only accepted with a valid signature.
+    pub webhook_secret: String,
+    /// Path to the RSA private key (PKCS#8 PEM) for signing the messages to the platforms.
+    pub private_key: String,
+    /// The ```kid``` of the key in the JWKS.
+    #[serde(default = "default_key_id")]
+    pub key_id: String,
+}
+
+impl Config {
+    /// The redirect uri of the OIDC login which is also the target of all launches.
+    pub fn launch_url(&self) -> String {
+        format!("{}/lti/launch", self.tool_url.trim_end_matches('/'))
+    }
+
+    /// The callback url of the API for the results of a launch.
+    pub fn result_url(&self, launch_id: &uuid::Uuid) -> String {
+        format!(
+            "{}/lti/result/{}",
+            self.tool_url.trim_end_matches('/'),
+            launch_id
+        )
+    }
+}
+
+/// Get config from the environment.
+pub fn get() -> Result<Config, envy::Error> {
+    envy::prefixed("DEPP_LTI_").from_env::<Config>()
+}
@@ -0,0 +1,217 @@
+//! All database queries of the tool.
+use crate::error::Error;
+use deadpool_postgres::Pool;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// A platform registered with the tool.
+#[derive(Debug, Clone)]
+pub struct Platform {
+    pub id: i32,
+    pub issuer: String,
+    pub client_id: String,
+    pub deployment_ids: Vec<String>,
+    pub auth_login_url: String,
+    pub auth_token_url: String,
+    pub jwks_url: String,
+}
+
+impl From<&Row> for Platform {
+    fn from(row: &Row) -> Self {
+        Platform {
+            id: row.get("id"),
+            issuer: row.get("issuer"),
+            client_id: row.get("client_id"),
+            deployment_ids: row.get("deployment_ids"),
+            auth_login_url: row.get("auth_login_url"),
+            auth_token_url: row.get("auth_token_url"),
+            jwks_url: row.get("jwks_url"),
+        }
+    }
+}
+
+const PLATFORM_SELECT: &str =
+    "SELECT id, issuer, client_id, deployment_ids, auth_login_url, auth_token_url, jwks_url FROM lti_platform";
+
+/// A verified launch.
+#[derive(Debug, Clone)]
+pub struct Launch {
+    pub id: Uuid,
+    pub platform: Platform,
+    pub deployment_id: String,
+    pub user_id: String,
+    pub assignment: Option<Uuid>,
+    pub line_item_url: Option<String>,
+    pub deep_link_return_url: Option<String>,
+    pub deep_link_data: Option<String>,
+}
+
+/// An assignment which can be linked.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Assignment {
+    pub uuid: Uuid,
+    pub name: String,
+    pub description: String,
+    pub exercise: String,
+}
+
+/// The platform of the issuer, the client id can be left out if the issuer has only one registration.
+pub async fn platform(
+    pool: &Pool,
+    issuer: &str,
+    client_id: Option<&str>,
+) -> Result<Platform, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            format!(
+                "{} WHERE issuer = $1 AND ($2::text IS NULL OR client_id = $2)",
+                PLATFORM_SELECT
+            )
+            .as_str(),
+            &[&issuer, &client_id],
+        )
+        .await?;
+    match rows.as_slice() {
+        [row] => Ok(Platform::from(row)),
+        _ => Err(Error::UnknownPlatform(
+            issuer.to_string(),
+            client_id.unwrap_or_default().to_string(),
+        )),
+    }
+}
+
+/// Stores the state and nonce of a login, logins older than an hour are removed.
+pub async fn insert_login(
+    pool: &Pool,
+    state: &str,
+    nonce: &str,
+    platform_id: i32,
+) -> Result<(), Error> {
+    let client = pool.get().await?;
+    client
+        .execute(
+            "DELETE FROM lti_login WHERE created_at < now() - interval '1 hour'",
+            &[],
+        )
+        .await?;
+    client
+        .execute(
+            "INSERT INTO lti_login(state, nonce, platform_id) VALUES($1, $2, $3)",
+            &[&state, &nonce, &platform_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Removes the login of the state and returns its nonce and platform, each state can be used once.
+pub async fn take_login(pool: &Pool, state: &str) -> Result<(String, Platform), Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"DELETE FROM lti_login WHERE state = $1 AND created_at > now() - interval '10 minutes'
+               RETURNING nonce, platform_id"#,
+            &[&state],
+        )
+        .await?
+        .ok_or_else(|| Error::BadLaunch("unknown or expired state".into()))?;
+    let platform_id: i32 = row.get("platform_id");
+    let platform = client
+        .query_one(
+            format!("{} WHERE id = $1", PLATFORM_SELECT).as_str(),
+            &[&platform_id],
+        )
+        .await?;
+    Ok((row.get("nonce"), Platform::from(&platform)))
+}
+
+/// Stores a verified launch.
+pub async fn insert_launch(pool: &Pool, launch: &Launch) -> Result<(), Error> {
+    let client = pool.get().await?;
+    client
+        .execute(
+            r#"INSERT INTO lti_launch(id, platform_id, deployment_id, user_id, assignment_id, line_item_url,
+                                      deep_link_return_url, deep_link_data)
+               VALUES($1, $2, $3, $4, (SELECT id FROM assignment WHERE uuid = $5), $6, $7, $8)"#,
+            &[
+                &launch.id,
+                &launch.platform.id,
+                &launch.deployment_id,
+                &launch.user_id,
+                &launch.assignment,
+                &launch.line_item_url,
+                &launch.deep_link_return_url,
+                &launch.deep_link_data,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn launch(pool: &Pool, id: &Uuid) -> Result<Launch, Error> {
+    let client = pool.get().await?;
+    let row = client
+        .query_opt(
+            r#"SELECT l.id AS launch_id, l.deployment_id, l.user_id, a.uuid, l.line_item_url,
+                      l.deep_link_return_url, l.deep_link_data, p.id, p.issuer, p.client_id,
+                      p.deployment_ids, p.auth_login_url, p.auth_token_url, p.jwks_url
+               FROM lti_launch l
+               INNER JOIN lti_platform p ON p.id = l.platform_id
+               LEFT JOIN assignment a ON a.id = l.assignment_id
+               WHERE l.id = $1"#,
+            &[id],
+        )
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Launch {}", id)))?;
+    Ok(Launch {
+        id: row.get("launch_id"),
+        platform: Platform::from(&row),
+        deployment_id: row.get("deployment_id"),
+        user_id: row.get("user_id"),
+        assignment: row.get("uuid"),
+        line_item_url: row.get("line_item_url"),
+        deep_link_return_url: row.get("deep_link_return_url"),
+        deep_link_data: row.get("deep_link_data"),
+    })
+}
+
+const ASSIGNMENT_SELECT: &str = r#"SELECT a.uuid, a.assignment_name, a.description, e.description
+               FROM assignment a INNER JOIN exercise e ON a.exercise_id = e.id
+               WHERE a.active"#;
+
+fn assignment_from_row(row: &Row) -> Assignment {
+    Assignment {
+        uuid: row.get(0),
+        name: row.get(1),
+        description: row.get(2),
+        exercise: row.get(3),
+    }
+}
+
+/// All active assignments for the deep linking selection.
+pub async fn assignments(pool: &Pool) -> Result<Vec<Assignment>, Error> {
+    let client = pool.get().await?;
+    let rows = client
+        .query(
+            format!(
+                "{} ORDER BY e.description, a.assignment_name",
+                ASSIGNMENT_SELECT
+            )
+            .as_str(),
+            &[],
+        )
+        .await?;
+    Ok(rows.iter().map(assignment_from_row).collect())
+}
+
+pub async fn assignment(pool: &Pool, uuid: &Uuid) -> Result<Assignment, Error> {
+    let client = pool.get().await?;
+    client
+        .query_opt(
+            format!("{} AND a.uuid = $1", ASSIGNMENT_SELECT).as_str(),
+            &[uuid],
+        )
+        .await?
+        .map(|row| assignment_from_row(&row))
+        .ok_or_else(|| Error::NotFound(format!("Aufgabe {}", uuid)))
+}
@@ -0,0 +1,75 @@
+//! Error handling using [failure](https://docs.rs/crate/failure) as error library.
+use actix_web::http::StatusCode;
+use actix_web::{error, HttpResponse};
+use db_lib::DbError;
+
+#[derive(Debug, failure::Fail)]
+pub enum Error {
+    #[fail(display = "Database error: {}", _0)]
+    Db(DbError),
+    #[fail(display = "Request to {} failed: {}", _0, _1)]
+    Http(String, String),
+    #[fail(display = "Invalid token: {}", _0)]
+    Jwt(jsonwebtoken::errors::Error),
+    #[fail(display = "Invalid launch: {}", _0)]
+    BadLaunch(String),
+    #[fail(display = "Unknown platform {} with client id {}", _0, _1)]
+    UnknownPlatform(String, String),
+    #[fail(display = "{} was not found.", _0)]
+    NotFound(String),
+    #[fail(display = "Invalid signature")]
+    BadSignature,
+    #[fail(display = "Internal Server error {}", _0)]
+    General(String),
+}
+
+impl From<DbError> for Error {
+    fn from(e: DbError) -> Self {
+        Error::Db(e)
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for Error {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        Error::Db(DbError::Pool(e))
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(e: tokio_postgres::Error) -> Self {
+        Error::Db(DbError::Sql(e))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        Error::Jwt(e)
+    }
+}
+
+impl From<tera::Error> for Error {
+    fn from(e: tera::Error) -> Self {
+        Error::General(e.to_string())
+    }
+}
+
+impl error::ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Jwt(_) | Error::BadLaunch(_) | Error::UnknownPlatform(_, _) => {
+                StatusCode::BAD_REQUEST
+            }
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::BadSignature => StatusCode::UNAUTHORIZED,
+            Error::Http(_, _) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        log::error!("{}", self);
+        HttpResponse::build(self.status_code())
+            .content_type("text/plain; charset=utf-8")
+            .body(self.to_string())
+    }
+}
@@ -0,0 +1,53 @@
+//! The RSA key of the tool. Platforms verify the deep linking responses and the AGS token requests with the
+//! public key which is published as JWKS at ```/lti/jwks```.
+use crate::error::Error;
+use crate::State;
+use actix_web::{web, HttpResponse};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rsa::{PublicKeyParts, RSAPrivateKey};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+pub struct ToolKey {
+    kid: String,
+    encoding: EncodingKey,
+    jwk: Value,
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+impl ToolKey {
+    /// Reads the PKCS#8 PEM file.
+    pub fn load(path: &str, kid: &str) -> Result<Self, failure::Error> {
+        let pem_file = std::fs::read_to_string(path)?;
+        let encoding = EncodingKey::from_rsa_pem(pem_file.as_bytes())?;
+        let key = RSAPrivateKey::from_pkcs8(&pem::parse(&pem_file)?.contents)?;
+        let jwk = json!({
+            "kty": "RSA",
+            "alg": "RS256",
+            "use": "sig",
+            "kid": kid,
+            "n": base64_url(&key.n().to_bytes_be()),
+            "e": base64_url(&key.e().to_bytes_be()),
+        });
+        Ok(ToolKey {
+            kid: kid.to_string(),
+            encoding,
+            jwk,
+        })
+    }
+
+    /// Signs the claims with RS256.
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, Error> {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(self.kid.clone());
+        Ok(jsonwebtoken::encode(&header, claims, &self.encoding)?)
+    }
+}
+
+/// The public key of the tool as JWKS.
+pub async fn jwks(state: web::Data<State>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "keys": [state.key.jwk] }))
+}
@@ -0,0 +1,253 @@
+//! The OIDC login and the launches. A resource link launch shows the linked assignment for submitting,
+//! a deep linking launch lets the teacher pick the assignment which is linked in the course.
+use crate::db::{self, Launch, Platform};
+use crate::error::Error;
+use crate::template::{render_template, TEMPLATES};
+use crate::token::{self, LaunchClaims, SCORE_SCOPE};
+use crate::State;
+use actix_web::{http, web, HttpResponse};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+/// The third party initiated login, sent by the platform as query or form.
+#[derive(Deserialize, Debug)]
+pub struct LoginParams {
+    iss: String,
+    login_hint: String,
+    lti_message_hint: Option<String>,
+    client_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LaunchForm {
+    id_token: String,
+    state: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeepLinkForm {
+    assignment: uuid::Uuid,
+}
+
+fn random_string() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .collect()
+}
+
+pub async fn login_get(
+    params: web::Query<LoginParams>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    login(params.into_inner(), &state).await
+}
+
+pub async fn login_post(
+    params: web::Form<LoginParams>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    login(params.into_inner(), &state).await
+}
+
+/// Stores a new state and nonce and redirects to the authentication endpoint of the platform,
+/// which POSTs the ```id_token``` to ```/lti/launch```.
+async fn login(params: LoginParams, state: &State) -> Result<HttpResponse, Error> {
+    let platform = db::platform(&state.db_pool, &params.iss, params.client_id.as_deref()).await?;
+    let login_state = random_string();
+    let nonce = random_string();
+    db::insert_login(&state.db_pool, &login_state, &nonce, platform.id).await?;
+    let launch_url = state.config.launch_url();
+    let mut query = vec![
+        ("scope", "openid"),
+        ("response_type", "id_token"),
+        ("response_mode", "form_post"),
+        ("prompt", "none"),
+        ("client_id", platform.client_id.as_str()),
+        ("redirect_uri", launch_url.as_str()),
+        ("login_hint", params.login_hint.as_str()),
+        ("state", login_state.as_str()),
+        ("nonce", nonce.as_str()),
+    ];
+    if let Some(hint) = &params.lti_message_hint {
+        query.push(("lti_message_hint", hint.as_str()));
+    }
+    let url = Url::parse_with_params(&platform.auth_login_url, &query)
+        .map_err(|e| Error::General(e.to_string()))?;
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, url.as_str())
+        .finish())
+}
+
+/// Checks that the token was issued for the login and for a deployment configured for the platform.
+/// A platform without deployments accepts no launch, the client id alone doesn't identify the course.
+fn check_launch(claims: &LaunchClaims, nonce: &str, platform: &Platform) -> Result<(), Error> {
+    if claims.nonce != nonce {
+        return Err(Error::BadLaunch("nonce does not match".into()));
+    }
+    if !platform.deployment_ids.contains(&claims.deployment_id) {
+        return Err(Error::BadLaunch(format!(
+            "unknown deployment {}",
+            claims.deployment_id
+        )));
+    }
+    Ok(())
+}
+
+/// Verifies the ```id_token``` and renders the page for the message type.
+pub async fn launch(
+    form: web::Form<LaunchForm>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let (nonce, platform) = db::take_login(&state.db_pool, &form.state).await?;
+    let claims: LaunchClaims =
+        token::verify_id_token(&state.platform_keys, &platform, &form.id_token).await?;
+    check_launch(&claims, &nonce, &platform)?;
+    let mut launch = Launch {
+        id: uuid::Uuid::new_v4(),
+        platform,
+        deployment_id: claims.deployment_id,
+        user_id: claims.sub,
+        assignment: None,
+        line_item_url: None,
+        deep_link_return_url: None,
+        deep_link_data: None,
+    };
+    let mut context = tera::Context::new();
+    context.insert("launch_id", &launch.id);
+    match claims.message_type.as_str() {
+        "LtiResourceLinkRequest" => {
+            let assignment_id = claims
+                .custom
+                .get("assignment_id")
+                .and_then(|id| id.as_str())
+                .and_then(|id| id.parse::<uuid::Uuid>().ok())
+                .ok_or_else(|| Error::BadLaunch("no assignment is linked".into()))?;
+            let assignment = db::assignment(&state.db_pool, &assignment_id).await?;
+            launch.assignment = Some(assignment_id);
+            launch.line_item_url = claims
+                .ags
+                .filter(|ags| ags.scope.iter().any(|scope| scope == SCORE_SCOPE))
+                .and_then(|ags| ags.lineitem);
+            db::insert_launch(&state.db_pool, &launch).await?;
+            log::info!(
+                "Launch of {} by {} from {}",
+                &assignment.name,
+                &launch.user_id,
+                &launch.platform.issuer
+            );
+            context.insert("assignment", &assignment);
+            context.insert("grade_passback", &launch.line_item_url.is_some());
+            render_template(&TEMPLATES, "submit.html", &context)
+        }
+        "LtiDeepLinkingRequest" => {
+            let settings = claims
+                .deep_linking
+                .ok_or_else(|| Error::BadLaunch("deep linking settings are missing".into()))?;
+            launch.deep_link_return_url = Some(settings.deep_link_return_url);
+            launch.deep_link_data = settings.data;
+            db::insert_launch(&state.db_pool, &launch).await?;
+            context.insert("assignments", &db::assignments(&state.db_pool).await?);
+            render_template(&TEMPLATES, "deep_link.html", &context)
+        }
+        other => Err(Error::BadLaunch(format!(
+            "unsupported message type {}",
+            other
+        ))),
+    }
+}
+
+/// Sends the picked assignment back to the platform as resource link with a line item for the grades.
+pub async fn deep_link(
+    path: web::Path<uuid::Uuid>,
+    form: web::Form<DeepLinkForm>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let launch = db::launch(&state.db_pool, &path.into_inner()).await?;
+    let return_url = launch
+        .deep_link_return_url
+        .as_ref()
+        .ok_or_else(|| Error::BadLaunch("not a deep linking launch".into()))?;
+    let assignment = db::assignment(&state.db_pool, &form.assignment).await?;
+    let now = chrono::Utc::now().timestamp();
+    let mut claims = json!({
+        "iss": launch.platform.client_id,
+        "aud": launch.platform.issuer,
+        "iat": now,
+        "exp": now + 5 * 60,
+        "nonce": random_string(),
+        token::MESSAGE_TYPE: "LtiDeepLinkingResponse",
+        token::VERSION: "1.3.0",
+        token::DEPLOYMENT_ID: launch.deployment_id,
+        token::CONTENT_ITEMS: [{
+            "type": "ltiResourceLink",
+            "title": assignment.name,
+            "url": state.config.launch_url(),
+            "custom": { "assignment_id": assignment.uuid.to_string() },
+            "lineItem": { "scoreMaximum": 100, "label": assignment.name },
+        }],
+    });
+    if let Some(data) = &launch.deep_link_data {
+        claims[token::DEEP_LINKING_DATA] = json!(data);
+    }
+    let mut context = tera::Context::new();
+    context.insert("action", return_url);
+    context.insert("jwt", &state.key.sign(&claims)?);
+    render_template(&TEMPLATES, "auto_post.html", &context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn platform(deployment_ids: &[&str]) -> Platform {
+        Platform {
+            id: 1,
+            issuer: "https://lms.example.com".into(),
+            client_id: "tool".into(),
+            deployment_ids: deployment_ids.iter().map(|id| id.to_string()).collect(),
+            auth_login_url: "https://lms.example.com/auth".into(),
+            auth_token_url: "https://lms.example.com/token".into(),
+            jwks_url: "https://lms.example.com/jwks".into(),
+        }
+    }
+
+    fn claims(nonce: &str, deployment_id: &str) -> LaunchClaims {
+        LaunchClaims {
+            sub: "student".into(),
+            nonce: nonce.into(),
+            message_type: "LtiResourceLinkRequest".into(),
+            deployment_id: deployment_id.into(),
+            custom: HashMap::new(),
+            ags: None,
+            deep_linking: None,
+        }
+    }
+
+    #[test]
+    fn launch_of_the_login_is_accepted() {
+        assert!(check_launch(&claims("nonce", "1"), "nonce", &platform(&["1", "2"])).is_ok());
+    }
+
+    #[test]
+    fn other_nonce_is_rejected() {
+        let result = check_launch(&claims("replayed", "1"), "nonce", &platform(&["1"]));
+        assert!(matches!(result, Err(Error::BadLaunch(_))));
+    }
+
+    #[test]
+    fn unknown_deployment_is_rejected() {
+        let result = check_launch(&claims("nonce", "3"), "nonce", &platform(&["1", "2"]));
+        assert!(matches!(result, Err(Error::BadLaunch(_))));
+    }
+
+    #[test]
+    fn platform_without_deployments_is_rejected() {
+        let result = check_launch(&claims("nonce", "1"), "nonce", &platform(&[]));
+        assert!(matches!(result, Err(Error::BadLaunch(_))));
+    }
+}
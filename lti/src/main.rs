@@ -0,0 +1,85 @@
+//! An [LTI 1.3](https://www.imsglobal.org/spec/lti/v1p3/) tool, so the project can be used from Moodle, ILIAS and other learning platforms.
+//! Teachers link an assignment via deep linking, students submit their code after the launch and their result
+//! is passed back as score via the Assignment and Grade Services.
+//! The tool is a client of the REST [API](../api), it doesn't test anything itself.
+#[macro_use]
+extern crate lazy_static;
+
+use crate::keys::ToolKey;
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpServer};
+use deadpool_postgres::Pool;
+use failure::ResultExt;
+use std::sync::Arc;
+
+mod ags;
+mod config;
+mod db;
+mod error;
+mod keys;
+mod launch;
+mod submit;
+mod template;
+mod token;
+
+#[derive(Clone)]
+pub struct State {
+    db_pool: Pool,
+    config: config::Config,
+    key: Arc<ToolKey>,
+    platform_keys: Arc<token::KeyCache>,
+}
+
+/// Real main function. Starting the middleware and global initialization the state.
+async fn run() -> Result<(), failure::Error> {
+    init_logging();
+    let config = config::get()?;
+    let key = ToolKey::load(&config.private_key, &config.key_id).context(format!(
+        "Can't read the private key {}",
+        &config.private_key
+    ))?;
+    let state = State {
+        db_pool: db_lib::connect_migrate().await?,
+        key: Arc::new(key),
+        platform_keys: Arc::new(token::KeyCache::default()),
+        config: config.clone(),
+    };
+    let host = format!("0.0.0.0:{}", config.port);
+    log::info!("Listening on http://{}", host);
+    HttpServer::new(move || {
+        App::new()
+            .wrap(Logger::default())
+            .service(
+                web::scope("/lti")
+                    .route("/jwks", web::get().to(keys::jwks))
+                    .service(
+                        web::resource("/login")
+                            .route(web::get().to(launch::login_get))
+                            .route(web::post().to(launch::login_post)),
+                    )
+                    .route("/launch", web::post().to(launch::launch))
+                    .route("/deep_link/{launch_id}", web::post().to(launch::deep_link))
+                    .route("/submit/{launch_id}", web::post().to(submit::submit))
+                    .route("/result/{launch_id}", web::post().to(submit::result)),
+            )
+            .data(state.clone())
+    })
+    .bind(host)
+    .context(format!("Cant bind port {}", config.port))?
+    .run()
+    .await?;
+    Ok(())
+}
+
+/// Fake main function calls only the ```run``` function.
+fn main() {
+    if let Err(e) = actix_rt::System::new("lti-main").block_on(run()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn init_logging() {
+    std::env::set_var("RUST_LOG", "lti=info,error,actix_web=info,warn");
+    env_logger::init();
+}
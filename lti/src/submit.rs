@@ -0,0 +1,198 @@
+//! Submitting to the REST API and receiving its results. Each submission is sent with ```?wait=``` so fast tests
+//! show their result right away, and with a callback url which the API POSTs the signed result to.
+//! The result is passed back to the platform as score if the launch has a line item.
+use crate::ags::{self, Score};
+use crate::db;
+use crate::error::Error;
+use crate::template::{render_template, TEMPLATES};
+use crate::State;
+use actix_web::client::Client;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, HttpRequest, HttpResponse};
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+
+/// Header of the API containing the HMAC-SHA256 of the body: ```sha256=<hex>```.
+const SIGNATURE_HEADER: &str = "X-Depp-Signature";
+/// Seconds the API waits for the result before answering.
+const WAIT_SECS: u64 = 20;
+
+#[derive(Deserialize, Debug)]
+pub struct SubmitForm {
+    source_code: String,
+}
+
+/// The parts of the result the tool uses.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TestResult {
+    passed: bool,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    checks: Vec<CheckResult>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct CheckResult {
+    name: String,
+    passed: bool,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SubmissionCreated {
+    attempt: i32,
+    result: Option<TestResult>,
+}
+
+impl TestResult {
+    /// The share of passed checks, without checks it's all or nothing.
+    fn score(&self) -> Score {
+        let given = if self.checks.is_empty() {
+            if self.passed {
+                100.0
+            } else {
+                0.0
+            }
+        } else {
+            let passed = self.checks.iter().filter(|check| check.passed).count();
+            100.0 * passed as f32 / self.checks.len() as f32
+        };
+        Score {
+            given,
+            maximum: 100.0,
+            comment: self.message.clone(),
+        }
+    }
+}
+
+/// Sends the code to the API and renders the result or a note that it's still being tested.
+pub async fn submit(
+    path: web::Path<uuid::Uuid>,
+    form: web::Form<SubmitForm>,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    let launch = db::launch(&state.db_pool, &path.into_inner()).await?;
+    let assignment_id = launch
+        .assignment
+        .ok_or_else(|| Error::BadLaunch("no assignment is linked".into()))?;
+    let url = format!(
+        "{}/api/submission?wait={}",
+        state.config.api_url.trim_end_matches('/'),
+        WAIT_SECS
+    );
+    let body = json!({
+        "iliasId": format!("lti-{}", uuid::Uuid::new_v4()),
+        "studentId": format!("lti:{}:{}", launch.platform.id, &launch.user_id),
+        "assignmentId": assignment_id,
+        "sourceCode": base64::encode(&form.source_code),
+        "callbackUrl": state.config.result_url(&launch.id),
+    });
+    let http_error = |e: String| Error::Http(url.clone(), e);
+    let mut response = Client::default()
+        .post(&url)
+        .timeout(Duration::from_secs(WAIT_SECS + 10))
+        .header(AUTHORIZATION, format!("Bearer {}", &state.config.api_token))
+        .send_json(&body)
+        .await
+        .map_err(|e| http_error(e.to_string()))?;
+    if !response.status().is_success() {
+        let message = response.body().await.unwrap_or_default();
+        return Err(http_error(format!(
+            "{} {}",
+            response.status(),
+            String::from_utf8_lossy(&message)
+        )));
+    }
+    let created = response
+        .json::<SubmissionCreated>()
+        .await
+        .map_err(|e| http_error(e.to_string()))?;
+    let mut context = tera::Context::new();
+    context.insert("launch_id", &launch.id);
+    context.insert("attempt", &created.attempt);
+    context.insert("result", &created.result);
+    context.insert("grade_passback", &launch.line_item_url.is_some());
+    render_template(&TEMPLATES, "result.html", &context)
+}
+
+fn verify_signature(secret: &str, req: &HttpRequest, body: &[u8]) -> Result<(), Error> {
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("sha256="))
+        .and_then(|value| hex::decode(&value["sha256=".len()..]).ok())
+        .ok_or(Error::BadSignature)?;
+    let mut mac =
+        Hmac::<sha2::Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.verify(&signature).map_err(|_| Error::BadSignature)
+}
+
+/// The callback of the API, passes the result back to the platform.
+pub async fn result(
+    req: HttpRequest,
+    path: web::Path<uuid::Uuid>,
+    body: web::Bytes,
+    state: web::Data<State>,
+) -> Result<HttpResponse, Error> {
+    verify_signature(&state.config.webhook_secret, &req, &body)?;
+    let result: TestResult =
+        serde_json::from_slice(&body).map_err(|e| Error::General(e.to_string()))?;
+    let launch = db::launch(&state.db_pool, &path.into_inner()).await?;
+    if let Some(line_item_url) = &launch.line_item_url {
+        let score = result.score();
+        log::info!(
+            "Passing score {} of {} back to {}",
+            score.given,
+            &launch.user_id,
+            &launch.platform.issuer
+        );
+        ags::post_score(
+            &launch.platform,
+            &state.key,
+            line_item_url,
+            &launch.user_id,
+            score,
+        )
+        .await?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    // RFC 4231 test case 2, the same signature as in the tests of the API webhook
+    const BODY: &[u8] = b"what do ya want for nothing?";
+    const SIGNATURE: &str =
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+    fn verify(signature: Option<&str>, body: &[u8]) -> bool {
+        let mut req = TestRequest::default();
+        if let Some(signature) = signature {
+            req = req.header(SIGNATURE_HEADER, signature);
+        }
+        verify_signature("Jefe", &req.to_http_request(), body).is_ok()
+    }
+
+    #[test]
+    fn signature_of_the_api_is_accepted() {
+        assert!(verify(Some(SIGNATURE), BODY));
+    }
+
+    #[test]
+    fn bad_signatures_are_rejected() {
+        assert!(!verify(None, BODY));
+        assert!(!verify(Some(SIGNATURE), b"what do ya want for something?"));
+        assert!(!verify(Some(&SIGNATURE["sha256=".len()..]), BODY));
+        assert!(!verify(Some("sha256=not hex"), BODY));
+    }
+}
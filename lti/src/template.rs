@@ -0,0 +1,30 @@
+//! Global static instance of the template engine [tera](https://github.com/Keats/tera).
+use crate::error::Error;
+use actix_web::HttpResponse;
+use tera::Tera;
+
+lazy_static! {
+    /// All templates lazy loaded.
+    pub static ref TEMPLATES: Tera = {
+        let mut tera = match Tera::new("templates/*.html") {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!("Parsing error(s): {}", e);
+                ::std::process::exit(1);
+            }
+        };
+        tera.autoescape_on(vec!["html"]);
+        tera
+    };
+}
+
+pub fn render_template(
+    tera: &Tera,
+    temp_name: &'static str,
+    context: &tera::Context,
+) -> Result<HttpResponse, Error> {
+    let body = tera.render(temp_name, context)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(body))
+}
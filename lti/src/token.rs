@@ -0,0 +1,318 @@
+//! Verifying the ```id_token``` of a launch with the public keys of the platform.
+use crate::db::Platform;
+use crate::error::Error;
+use actix_web::client::Client;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const MESSAGE_TYPE: &str = "https://purl.imsglobal.org/spec/lti/claim/message_type";
+pub const VERSION: &str = "https://purl.imsglobal.org/spec/lti/claim/version";
+pub const DEPLOYMENT_ID: &str = "https://purl.imsglobal.org/spec/lti/claim/deployment_id";
+pub const CONTENT_ITEMS: &str = "https://purl.imsglobal.org/spec/lti-dl/claim/content_items";
+pub const DEEP_LINKING_DATA: &str = "https://purl.imsglobal.org/spec/lti-dl/claim/data";
+/// The scope needed for posting scores.
+pub const SCORE_SCOPE: &str = "https://purl.imsglobal.org/spec/lti-ags/scope/score";
+
+/// The claims of a launch the tool uses, all others are ignored.
+#[derive(Debug, Deserialize)]
+pub struct LaunchClaims {
+    pub sub: String,
+    pub nonce: String,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/message_type")]
+    pub message_type: String,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/deployment_id")]
+    pub deployment_id: String,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/custom", default)]
+    pub custom: HashMap<String, Value>,
+    #[serde(
+        rename = "https://purl.imsglobal.org/spec/lti-ags/claim/endpoint",
+        default
+    )]
+    pub ags: Option<AgsEndpoint>,
+    #[serde(
+        rename = "https://purl.imsglobal.org/spec/lti-dl/claim/deep_linking_settings",
+        default
+    )]
+    pub deep_linking: Option<DeepLinkingSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgsEndpoint {
+    pub lineitem: Option<String>,
+    #[serde(default)]
+    pub scope: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeepLinkingSettings {
+    pub deep_link_return_url: String,
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/// Only RSA keys are used, the others have no ```n``` and ```e```.
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+async fn fetch_jwks(url: &str) -> Result<Jwks, Error> {
+    let http_error = |e: String| Error::Http(url.to_string(), e);
+    Client::default()
+        .get(url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| http_error(e.to_string()))?
+        .json::<Jwks>()
+        .await
+        .map_err(|e| http_error(e.to_string()))
+}
+
+/// The ```n``` and ```e``` of the RSA key with the key id, any RSA key if the token has no key id.
+fn find_key(keys: &[Jwk], kid: Option<&String>) -> Option<(String, String)> {
+    keys.iter()
+        .filter(|key| kid.is_none() || key.kid.as_ref() == kid)
+        .find_map(|key| Some((key.n.clone()?, key.e.clone()?)))
+}
+
+/// A JWKS is fetched at most once in this interval, so tokens with made up key ids can't flood the platform.
+const REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct CachedJwks {
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+}
+
+/// The keys of the platforms by JWKS URL. The JWKS is only fetched again if a token is signed with
+/// an unknown key id, so rotated keys work without a restart.
+#[derive(Debug, Default)]
+pub struct KeyCache {
+    keys: Mutex<HashMap<String, CachedJwks>>,
+}
+
+impl KeyCache {
+    async fn key(&self, jwks_url: &str, kid: Option<&String>) -> Result<(String, String), Error> {
+        if let Some(key) = self.cached_key(jwks_url, kid)? {
+            return Ok(key);
+        }
+        let jwks = fetch_jwks(jwks_url).await?;
+        let key = find_key(&jwks.keys, kid);
+        self.keys
+            .lock()
+            .unwrap()
+            .entry(jwks_url.to_string())
+            .or_default()
+            .keys = jwks.keys;
+        key.ok_or_else(no_matching_key)
+    }
+
+    /// The cached key or ```None``` if the JWKS has to be fetched, which is noted as its last fetch.
+    /// Fails if the key is unknown and the JWKS was fetched within the ```REFETCH_INTERVAL```.
+    fn cached_key(
+        &self,
+        jwks_url: &str,
+        kid: Option<&String>,
+    ) -> Result<Option<(String, String)>, Error> {
+        let mut keys = self.keys.lock().unwrap();
+        let cached = keys.entry(jwks_url.to_string()).or_default();
+        if let Some(key) = find_key(&cached.keys, kid) {
+            return Ok(Some(key));
+        }
+        if let Some(fetched_at) = cached.fetched_at {
+            if fetched_at.elapsed() < REFETCH_INTERVAL {
+                return Err(no_matching_key());
+            }
+        }
+        cached.fetched_at = Some(Instant::now());
+        Ok(None)
+    }
+}
+
+fn no_matching_key() -> Error {
+    Error::BadLaunch("no matching key in the JWKS of the platform".into())
+}
+
+/// Checks the signature, issuer, audience and expiry of the token with the key of the platform.
+fn decode_claims(
+    platform: &Platform,
+    id_token: &str,
+    key: &DecodingKey,
+) -> Result<LaunchClaims, Error> {
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_audience(&[&platform.client_id]);
+    validation.iss = Some(platform.issuer.clone());
+    let data = jsonwebtoken::decode::<LaunchClaims>(id_token, key, &validation)?;
+    Ok(data.claims)
+}
+
+/// Checks the signature, issuer, audience and expiry of the token.
+pub async fn verify_id_token(
+    keys: &KeyCache,
+    platform: &Platform,
+    id_token: &str,
+) -> Result<LaunchClaims, Error> {
+    let header = jsonwebtoken::decode_header(id_token)?;
+    let (n, e) = keys.key(&platform.jwks_url, header.kid.as_ref()).await?;
+    decode_claims(
+        platform,
+        id_token,
+        &DecodingKey::from_rsa_components(&n, &e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::errors::ErrorKind;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    const PRIVATE_KEY: &str = include_str!("../testdata/platform_key.pem");
+    const PUBLIC_KEY: &str = include_str!("../testdata/platform_key.pub.pem");
+
+    fn platform() -> Platform {
+        Platform {
+            id: 1,
+            issuer: "https://lms.example.com".into(),
+            client_id: "tool".into(),
+            deployment_ids: vec![],
+            auth_login_url: "https://lms.example.com/auth".into(),
+            auth_token_url: "https://lms.example.com/token".into(),
+            jwks_url: "https://lms.example.com/jwks".into(),
+        }
+    }
+
+    fn claims() -> Value {
+        let now = chrono::Utc::now().timestamp();
+        json!({
+            "iss": "https://lms.example.com",
+            "aud": "tool",
+            "sub": "student",
+            "iat": now,
+            "exp": now + 60,
+            "nonce": "nonce",
+            MESSAGE_TYPE: "LtiResourceLinkRequest",
+            DEPLOYMENT_ID: "1",
+        })
+    }
+
+    fn sign(claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("platform".into());
+        let key = EncodingKey::from_rsa_pem(PRIVATE_KEY.as_bytes()).unwrap();
+        jsonwebtoken::encode(&header, claims, &key).unwrap()
+    }
+
+    fn decode(id_token: &str) -> Result<LaunchClaims, Error> {
+        let key = DecodingKey::from_rsa_pem(PUBLIC_KEY.as_bytes()).unwrap();
+        decode_claims(&platform(), id_token, &key)
+    }
+
+    /// ```ErrorKind``` has no ```PartialEq```, so the kinds are compared by name.
+    fn assert_rejected(id_token: &str, kind: ErrorKind) {
+        match decode(id_token) {
+            Err(Error::Jwt(e)) => assert_eq!(format!("{:?}", e.kind()), format!("{:?}", kind)),
+            other => panic!("expected {:?}, got {:?}", kind, other),
+        }
+    }
+
+    #[test]
+    fn valid_token_is_accepted() {
+        let claims = decode(&sign(&claims())).unwrap();
+        assert_eq!(claims.sub, "student");
+        assert_eq!(claims.nonce, "nonce");
+        assert_eq!(claims.deployment_id, "1");
+    }
+
+    #[test]
+    fn wrong_audience_is_rejected() {
+        let mut claims = claims();
+        claims["aud"] = json!("other tool");
+        assert_rejected(&sign(&claims), ErrorKind::InvalidAudience);
+    }
+
+    #[test]
+    fn wrong_issuer_is_rejected() {
+        let mut claims = claims();
+        claims["iss"] = json!("https://other.example.com");
+        assert_rejected(&sign(&claims), ErrorKind::InvalidIssuer);
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let mut claims = claims();
+        let now = chrono::Utc::now().timestamp();
+        claims["iat"] = json!(now - 600);
+        claims["exp"] = json!(now - 300);
+        assert_rejected(&sign(&claims), ErrorKind::ExpiredSignature);
+    }
+
+    #[test]
+    fn tampered_token_is_rejected() {
+        let id_token = sign(&claims());
+        let mut parts: Vec<&str> = id_token.split('.').collect();
+        let mut claims = claims();
+        claims["sub"] = json!("teacher");
+        let payload = base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD);
+        parts[1] = &payload;
+        assert_rejected(&parts.join("."), ErrorKind::InvalidSignature);
+    }
+
+    #[test]
+    fn keys_are_found_by_key_id() {
+        let key = |kid: &str, n: Option<&str>| Jwk {
+            kid: Some(kid.into()),
+            n: n.map(String::from),
+            e: n.map(|_| "AQAB".into()),
+        };
+        let keys = vec![
+            key("ec", None),
+            key("old", Some("1")),
+            key("new", Some("2")),
+        ];
+        let found = |kid: &str| find_key(&keys, Some(&kid.to_string())).map(|(n, _)| n);
+        assert_eq!(found("new"), Some("2".into()));
+        assert_eq!(found("old"), Some("1".into()));
+        assert_eq!(found("ec"), None);
+        assert_eq!(found("unknown"), None);
+        assert_eq!(find_key(&keys, None), Some(("1".into(), "AQAB".into())));
+    }
+
+    #[test]
+    fn jwks_is_fetched_at_most_once_a_minute() {
+        let cache = KeyCache::default();
+        let url = "https://lms.example.com/jwks";
+        let kid = Some("new".to_string());
+        assert!(cache.cached_key(url, kid.as_ref()).unwrap().is_none());
+        // the fetch brought no key with the id
+        assert!(cache.cached_key(url, kid.as_ref()).is_err());
+        cache.keys.lock().unwrap().get_mut(url).unwrap().keys = vec![Jwk {
+            kid: kid.clone(),
+            n: Some("2".into()),
+            e: Some("AQAB".into()),
+        }];
+        assert_eq!(
+            cache.cached_key(url, kid.as_ref()).unwrap(),
+            Some(("2".into(), "AQAB".into()))
+        );
+        // other platforms have their own interval
+        let other = "https://other.example.com/jwks";
+        assert!(cache.cached_key(other, kid.as_ref()).unwrap().is_none());
+        cache.keys.lock().unwrap().get_mut(url).unwrap().fetched_at =
+            Some(Instant::now() - REFETCH_INTERVAL);
+        let rotated = Some("rotated".to_string());
+        assert!(cache.cached_key(url, rotated.as_ref()).unwrap().is_none());
+    }
+}
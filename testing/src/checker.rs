@@ -1,21 +1,33 @@
 //! Checks the script meets certain criterions.
-use crate::docker_api::ScriptOutput;
+use crate::docker_api::{DockerWrap, ScriptOutput};
 use crate::error::Error;
-use crate::error::IOError;
-use crate::fs_util;
+use crate::error::{IOError, SystemError};
+use crate::fs_util::SourceFile;
+use crate::{fs_util, linter, tokenizer};
 use async_trait::async_trait;
 use futures::pin_mut;
 use futures::StreamExt;
 use grpc_api::Script;
-use grpc_api::{RegexMode, SortStdoutBy};
+use grpc_api::{Diagnostic, LintMode, RegexRule, RegexTarget, Severity, SortStdoutBy};
 use log::info;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tokio::fs;
 
 /// Checks if the script is correct and if not each function has to return an error.
 #[async_trait]
 pub trait Checker: Sync + Send {
     async fn check(&self) -> Result<(), Error>;
+    /// Reported with the result of each check.
+    fn name(&self) -> String;
+    /// Structured findings reported alongside the result. Only the linters have some.
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+    /// Optional score of the check. Only the custom checker reports one.
+    fn score(&self) -> Option<f32> {
+        None
+    }
 }
 
 /// Check if the script has created certain files/folders.
@@ -36,63 +48,117 @@ impl FilesChecker {
         (a.is_file() && b.is_file()) || (a.is_dir() && b.is_dir())
     }
 }
-/// Check script or stdout for certain pattern.
+/// Check one regex rule against stdout, stderr, the script or an output file.
+/// Depending on the rule the pattern must match or must not match.
 #[derive(Debug)]
 pub struct RegexChecker {
-    regex: Option<Result<regex::Regex, regex::Error>>,
-    /// Check stdout or the script content
-    mode: RegexMode,
-    /// Stdout to check
-    stdout: String,
-    /// The content of the script to check
-    script_content: String,
+    /// Position of the rule in the assignment, starting with 1
+    number: usize,
+    rule: RegexRule,
+    tested_out: ScriptOutput,
+    /// The scripts to check, all files of a zip submission with the script type
+    scripts: Vec<SourceFile>,
+    /// The output files are relative to this dir
+    working_dir: PathBuf,
 }
 /// initialize struct
 impl RegexChecker {
-    pub fn boxed<T: AsRef<str>>(
-        regex: Option<T>,
-        mode: RegexMode,
-        stdout: T,
-        script_content: &String,
+    pub fn boxed(
+        number: usize,
+        rule: &RegexRule,
+        tested_out: &ScriptOutput,
+        scripts: &[SourceFile],
+        working_dir: &Path,
     ) -> Box<dyn Checker> {
         Box::new(RegexChecker {
-            regex: regex.and_then(|reg_str| {
-                Some(
-                    regex::RegexBuilder::new(reg_str.as_ref())
-                        .multi_line(true)
-                        .build(),
-                )
-            }),
-            mode,
-            stdout: stdout.as_ref().to_string(),
-            script_content: script_content.into(),
+            number,
+            rule: rule.clone(),
+            tested_out: tested_out.clone(),
+            scripts: scripts.to_vec(),
+            working_dir: working_dir.to_path_buf(),
         })
     }
+
+    fn target_name(&self) -> String {
+        match RegexTarget::from(self.rule.target) {
+            RegexTarget::Stdout => "stdout".into(),
+            RegexTarget::Stderr => "stderr".into(),
+            RegexTarget::ScriptContent => "script".into(),
+            RegexTarget::OutputFile => format!("file '{}'", self.rule.file_name),
+        }
+    }
+
+    /// The message written by the teacher or a generic one.
+    fn failure(&self) -> Error {
+        if !self.rule.message.is_empty() {
+            Error::RegexRule(self.rule.message.clone())
+        } else if self.rule.must_match {
+            Error::NoRegexMatch(self.target_name(), self.rule.pattern.clone())
+        } else {
+            Error::ForbiddenRegexMatch(self.target_name(), self.rule.pattern.clone())
+        }
+    }
+
+    /// Only plain relative paths are allowed, the file has to stay inside the working dir.
+    fn output_file(&self) -> Result<PathBuf, Error> {
+        let path = Path::new(&self.rule.file_name);
+        if self.rule.file_name.is_empty()
+            || path
+                .components()
+                .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err(Error::InvalidRegex(format!(
+                "invalid output file name '{}'",
+                self.rule.file_name
+            )));
+        }
+        Ok(self.working_dir.join(path))
+    }
 }
 
 #[async_trait]
 impl Checker for RegexChecker {
     async fn check(&self) -> Result<(), Error> {
-        log::info!("checking with regex");
-        if let Some(regex) = self.regex.clone() {
-            let regex = regex.map_err(|er| Error::InvalidRegex(er.to_string()))?;
-            let res = match self.mode {
-                RegexMode::Stdout => (regex.is_match(&self.stdout), self.stdout.clone()),
-                RegexMode::ScriptContent => (
-                    regex.is_match(&self.script_content),
-                    self.script_content.clone(),
-                ),
-                _ => (true, "".into()),
-            };
-            return if res.0 {
-                Ok(())
-            } else {
-                Err(Error::NoRegexMatch(res.1, regex.clone()))
-            };
-        } else {
+        log::info!("checking regex rule {}", self.number);
+        let regex = regex::RegexBuilder::new(&self.rule.pattern)
+            .case_insensitive(self.rule.case_insensitive)
+            .multi_line(self.rule.multi_line)
+            .dot_matches_new_line(self.rule.dot_all)
+            .build()
+            .map_err(|er| Error::InvalidRegex(er.to_string()))?;
+        let text = match RegexTarget::from(self.rule.target) {
+            RegexTarget::Stdout => self.tested_out.stdout.clone(),
+            RegexTarget::Stderr => self.tested_out.stderr.clone(),
+            // a required pattern has to be in one of the scripts, a forbidden one in none of them
+            RegexTarget::ScriptContent => {
+                let found = self
+                    .scripts
+                    .iter()
+                    .find(|script| regex.is_match(&script.content));
+                return match found {
+                    Some(_) if self.rule.must_match => Ok(()),
+                    Some(script) => Err(script.locate(self.failure())),
+                    None if self.rule.must_match => Err(self.failure()),
+                    None => Ok(()),
+                };
+            }
+            RegexTarget::OutputFile => match fs::read_to_string(self.output_file()?).await {
+                Ok(content) => content,
+                // a missing file can't contain a forbidden pattern
+                Err(_) if !self.rule.must_match => return Ok(()),
+                Err(_) => return Err(self.failure()),
+            },
+        };
+        if regex.is_match(&text) == self.rule.must_match {
             Ok(())
+        } else {
+            Err(self.failure())
         }
     }
+
+    fn name(&self) -> String {
+        format!("regex #{}", self.number)
+    }
 }
 /// Check stdout with the expected output form the solution.
 #[derive(Debug)]
@@ -117,13 +183,16 @@ impl Checker for StdoutChecker {
         if self.expected == self.tested {
             Ok(())
         } else {
-            Err(Error::WrongOutput(format!(
-                "Expected STDOUT:({}) Result STDOUT:({})",
-                self.expected.trim(),
-                self.tested.trim()
-            )))
+            Err(Error::WrongOutput(
+                self.expected.trim().to_string(),
+                self.tested.trim().to_string(),
+            ))
         }
     }
+
+    fn name(&self) -> String {
+        "stdout".into()
+    }
 }
 
 #[async_trait]
@@ -166,6 +235,10 @@ impl Checker for FilesChecker {
 
         Ok(())
     }
+
+    fn name(&self) -> String {
+        "files".into()
+    }
 }
 
 async fn print_dir_content(msg: &str, root: &Path) -> Result<(), Error> {
@@ -181,28 +254,76 @@ async fn print_dir_content(msg: &str, root: &Path) -> Result<(), Error> {
     }
     Ok(())
 }
-/// Run the custom script to check if the script is right.
-/// $1=Stdout from tested script, $2=script content.
+/// Everything the custom checker gets to see, written to ```context.json```.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CustomCheckContext {
+    pub script_type: Script,
+    pub script_content: String,
+    /// All scripts of a zip submission, the entry point first
+    pub script_files: Vec<SourceFile>,
+    pub args: Vec<String>,
+    pub tested: ScriptOutput,
+    pub solution: ScriptOutput,
+    pub solution_content: String,
+    /// Paths of the working dirs inside the container
+    pub testing_dir: String,
+    pub solution_dir: String,
+}
+
+/// The JSON the custom checker writes to stdout.
+#[derive(Debug, serde::Deserialize)]
+struct Verdict {
+    passed: bool,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    score: Option<f32>,
+}
+
+impl Verdict {
+    /// Either the whole stdout or the last line is the verdict, so the checker can print some debug output first.
+    fn parse(stdout: &str) -> Option<Verdict> {
+        serde_json::from_str(stdout.trim()).ok().or_else(|| {
+            stdout
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .and_then(|line| serde_json::from_str(line.trim()).ok())
+        })
+    }
+}
+
+/// Runs the custom checker written by the teacher in its own container.
+/// It gets ```context.json```, the tested and the solution working dir as arguments and answers with a JSON verdict.
+/// Without a verdict the exit code and stderr decide like before.
 #[derive(Debug)]
 pub struct CustomScriptChecker {
+    docker: DockerWrap,
+    script_type: Script,
     custom_script_content: String,
-    tested_script_content: String,
-    tested_out: ScriptOutput,
-    working_dir: PathBuf,
+    context: CustomCheckContext,
+    testing_dir: PathBuf,
+    solution_dir: PathBuf,
+    score: Mutex<Option<f32>>,
 }
 /// initialize struct
 impl CustomScriptChecker {
     pub fn boxed(
+        docker: &DockerWrap,
+        script_type: Script,
         c_script_content: &str,
-        tested_script_content: &str,
-        tested_out: &ScriptOutput,
-        cur_dir: &Path,
+        context: CustomCheckContext,
+        testing_dir: &Path,
+        solution_dir: &Path,
     ) -> Box<dyn Checker> {
         Box::new(CustomScriptChecker {
+            docker: docker.clone(),
+            script_type,
             custom_script_content: String::from(c_script_content),
-            tested_script_content: String::from(tested_script_content),
-            tested_out: tested_out.clone(),
-            working_dir: cur_dir.to_path_buf(),
+            context,
+            testing_dir: testing_dir.to_path_buf(),
+            solution_dir: solution_dir.to_path_buf(),
+            score: Mutex::new(None),
         })
     }
 }
@@ -210,38 +331,58 @@ impl CustomScriptChecker {
 #[async_trait]
 impl Checker for CustomScriptChecker {
     async fn check(&self) -> Result<(), Error> {
-        log::info!("running Custom script");
-        let script_type = if cfg!(target_family = "unix") {
-            Script::Bash
-        } else {
-            Script::PowerShell
-        };
-        let file_custom_script =
-            fs_util::new_tmp_script_file(script_type, &self.custom_script_content)
-                .map_err(|e| IOError::CreateFile(e))?
-                .into_temp_path();
-
-        let prog = if cfg!(target_family = "unix") {
-            "bash"
+        log::info!("running Custom script as {:?}", self.script_type);
+        let checker_dir = fs_util::new_build_dir(self.script_type, &self.custom_script_content)
+            .await
+            .map_err(IOError::CreateFile)?;
+        let program_name = if self.script_type.is_compiled() {
+            self.docker
+                .compile_in_container(&self.script_type, checker_dir.path())
+                .await
+                .map_err(|e| SystemError::CustomChecker(e.to_string()))?;
+            self.script_type.executable_name()
         } else {
-            "powershell.exe"
+            self.script_type.source_file_name()
         };
-        use tokio::process::Command;
-        let outpout = Command::new(prog)
-            .arg(&file_custom_script)
-            .args(&[&self.tested_out.stdout, &self.tested_script_content])
-            .current_dir(&self.working_dir)
-            .output()
+        let context = serde_json::to_vec(&self.context).unwrap_or_default();
+        fs::write(checker_dir.path().join("context.json"), context)
             .await
-            .map_err(|e| IOError::FailedRunCustomScript(e))?;
-        if outpout.stderr.is_empty() && outpout.status.success() {
-            Ok(())
-        } else {
-            Err(Error::CustomScript(
-                String::from_utf8(outpout.stderr).unwrap_or_default(),
-            ))
+            .map_err(IOError::CreateFile)?;
+
+        let output = self
+            .docker
+            .custom_check_in_container(
+                &self.script_type,
+                checker_dir.path(),
+                program_name,
+                &self.testing_dir,
+                &self.solution_dir,
+            )
+            .await?;
+        log::info!("Custom script output: {}", output);
+        match Verdict::parse(&output.stdout) {
+            Some(verdict) => {
+                *self.score.lock().unwrap() = verdict.score;
+                if verdict.passed {
+                    Ok(())
+                } else {
+                    Err(Error::CustomCheckFailed(verdict.message.unwrap_or_else(
+                        || "The custom checker rejected the submission.".into(),
+                    )))
+                }
+            }
+            None if output.status_code == 0 && output.stderr.trim().is_empty() => Ok(()),
+            None => Err(Error::CustomScript(output.stderr.trim().to_string())),
         }
     }
+
+    fn name(&self) -> String {
+        "custom script".into()
+    }
+
+    fn score(&self) -> Option<f32> {
+        *self.score.lock().unwrap()
+    }
 }
 /// Check if the stdout ist sorted asc or desc.
 #[derive(Debug)]
@@ -278,6 +419,147 @@ impl Checker for SortedChecker {
             Err(Error::NoSorted(self.content.clone()))
         }
     }
+
+    fn name(&self) -> String {
+        "sorted stdout".into()
+    }
+}
+
+/// Runs a linter inside the container against the tested script.
+/// Depending on the mode the findings are only feedback or fail the test at the given severity.
+#[derive(Debug)]
+pub struct LintChecker {
+    docker: DockerWrap,
+    script_type: Script,
+    scripts: Vec<SourceFile>,
+    mode: LintMode,
+    fail_severity: Severity,
+    /// The parsed findings after the check ran.
+    diagnostics: Mutex<Vec<Diagnostic>>,
+}
+/// initialize struct
+impl LintChecker {
+    pub fn boxed(
+        docker: &DockerWrap,
+        script_type: Script,
+        scripts: &[SourceFile],
+        mode: LintMode,
+        fail_severity: Severity,
+    ) -> Box<dyn Checker> {
+        Box::new(LintChecker {
+            docker: docker.clone(),
+            script_type,
+            scripts: scripts.to_vec(),
+            mode,
+            fail_severity,
+            diagnostics: Mutex::new(vec![]),
+        })
+    }
+}
+
+#[async_trait]
+impl Checker for LintChecker {
+    async fn check(&self) -> Result<(), Error> {
+        log::info!("Running linter for {:?}", self.script_type);
+        let mut found = Vec::new();
+        for script in &self.scripts {
+            let script_path = fs_util::new_tmp_script_file(self.script_type, &script.content)
+                .map_err(IOError::CreateFile)?
+                .into_temp_path();
+            let output = self
+                .docker
+                .lint_in_container(&self.script_type, &script_path)
+                .await?;
+            found.extend(
+                linter::parse(&self.script_type, &output.stdout)?
+                    .into_iter()
+                    .map(|diagnostic| Diagnostic {
+                        message: script.prefix(&diagnostic.message),
+                        ..diagnostic
+                    }),
+            );
+        }
+        let over_threshold = found
+            .iter()
+            .filter(|d| d.severity >= self.fail_severity as i32)
+            .count();
+        *self.diagnostics.lock().unwrap() = found;
+        if self.mode == LintMode::FailOnSeverity && over_threshold > 0 {
+            Err(Error::LintFindings(over_threshold, self.fail_severity))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn name(&self) -> String {
+        "linter".into()
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.lock().unwrap().clone()
+    }
+}
+
+/// Tokenizes the script and checks the invoked commands against the required and forbidden lists.
+/// Unlike a regex on the script content, comments and string literals are ignored.
+/// A required command may be invoked in any of the scripts.
+#[derive(Debug)]
+pub struct CommandChecker {
+    script_type: Script,
+    scripts: Vec<SourceFile>,
+    required: Vec<String>,
+    forbidden: Vec<String>,
+}
+/// initialize struct
+impl CommandChecker {
+    pub fn boxed(
+        script_type: Script,
+        scripts: &[SourceFile],
+        required: &[String],
+        forbidden: &[String],
+    ) -> Box<dyn Checker> {
+        Box::new(CommandChecker {
+            script_type,
+            scripts: scripts.to_vec(),
+            required: required.to_vec(),
+            forbidden: forbidden.to_vec(),
+        })
+    }
+}
+
+#[async_trait]
+impl Checker for CommandChecker {
+    async fn check(&self) -> Result<(), Error> {
+        log::info!("Checking invoked commands");
+        let is =
+            |invoked: &str, name: &str| tokenizer::same_command(&self.script_type, invoked, name);
+        let mut commands = Vec::new();
+        for script in &self.scripts {
+            let invoked = tokenizer::invoked_commands(&self.script_type, &script.content)
+                .map_err(|e| script.locate(e))?;
+            if let Some((command, name)) = invoked.iter().find_map(|command| {
+                self.forbidden
+                    .iter()
+                    .find(|name| is(&command.name, name))
+                    .map(|name| (command, name))
+            }) {
+                return Err(script.locate(Error::ForbiddenCommand(name.clone(), command.line)));
+            }
+            commands.extend(invoked);
+        }
+        if let Some(name) = self
+            .required
+            .iter()
+            .find(|name| !commands.iter().any(|command| is(&command.name, name)))
+        {
+            return Err(Error::MissingCommand(name.clone()));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "commands".into()
+    }
 }
 
 pub fn trim_lines(s: &str) -> String {
@@ -290,3 +572,80 @@ pub fn trim_lines(s: &str) -> String {
         .collect::<Vec<String>>();
     ret.join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripts() -> Vec<SourceFile> {
+        vec![
+            SourceFile {
+                path: "main.sh".into(),
+                content: "source lib/a.sh\necho done\n".into(),
+            },
+            SourceFile {
+                path: "lib/a.sh".into(),
+                content: "# helper\ngrep foo bar.txt\n".into(),
+            },
+        ]
+    }
+
+    fn regex_rule(pattern: &str, must_match: bool) -> RegexRule {
+        RegexRule {
+            pattern: pattern.into(),
+            target: RegexTarget::ScriptContent as i32,
+            must_match,
+            ..RegexRule::default()
+        }
+    }
+
+    async fn check_regex(rule: RegexRule) -> Result<(), Error> {
+        let output = ScriptOutput {
+            stdout: String::new(),
+            stderr: String::new(),
+            status_code: 0,
+        };
+        RegexChecker::boxed(1, &rule, &output, &scripts(), Path::new("."))
+            .check()
+            .await
+    }
+
+    #[tokio::test]
+    async fn commands_of_all_scripts_are_checked() {
+        let check = |required: &[&str], forbidden: &[&str]| {
+            let to_vec = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+            CommandChecker::boxed(
+                Script::Bash,
+                &scripts(),
+                &to_vec(required),
+                &to_vec(forbidden),
+            )
+        };
+        check(&["echo", "grep"], &[]).check().await.unwrap();
+        match check(&[], &["grep"]).check().await {
+            Err(Error::InFile(path, e)) => {
+                assert_eq!(path, "lib/a.sh");
+                assert!(matches!(*e, Error::ForbiddenCommand(_, 2)));
+            }
+            other => panic!("expected a forbidden command in lib/a.sh, got {:?}", other),
+        }
+        assert!(matches!(
+            check(&["awk"], &[]).check().await,
+            Err(Error::MissingCommand(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn regex_rules_see_all_scripts() {
+        check_regex(regex_rule("grep", true)).await.unwrap();
+        check_regex(regex_rule("awk", false)).await.unwrap();
+        assert!(matches!(
+            check_regex(regex_rule("awk", true)).await,
+            Err(Error::NoRegexMatch(_, _))
+        ));
+        match check_regex(regex_rule("helper", false)).await {
+            Err(e @ Error::InFile(_, _)) => assert!(e.to_string().starts_with("lib/a.sh: ")),
+            other => panic!("expected a match in lib/a.sh, got {:?}", other),
+        }
+    }
+}
@@ -33,6 +33,22 @@ fn default_timout_secs() -> u64 {
         180
     }
 }
+/// Default timeout for the build step of compiled languages in sec: `60sec`
+fn default_compile_timout_secs() -> u64 {
+    60
+}
+/// Default max number of files in a zip submission: `100`
+fn default_max_zip_files() -> usize {
+    100
+}
+/// Default max unpacked size of a zip submission: `10 MiB`
+fn default_max_zip_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+/// Default time the running tests get to finish on shutdown in sec, the same as the test timeout.
+fn default_shutdown_grace_secs() -> u64 {
+    default_timout_secs()
+}
 /// The config for setting up the server
 #[derive(serde::Deserialize, Debug)]
 pub struct ServerConfig {
@@ -48,6 +64,18 @@ pub struct ServerConfig {
     #[serde(default = "default_timout_secs")]
     /// The timeout in seconds for each test.
     pub timeout: u64,
+    #[serde(default = "default_compile_timout_secs")]
+    /// The timeout in seconds for compiling C, Java or Rust before each test.
+    pub compile_timeout: u64,
+    #[serde(default = "default_max_zip_files")]
+    /// Max number of files and dirs in a zip submission.
+    pub max_zip_files: usize,
+    #[serde(default = "default_max_zip_bytes")]
+    /// Max size in bytes of all unpacked files of a zip submission.
+    pub max_zip_bytes: u64,
+    #[serde(default = "default_shutdown_grace_secs")]
+    /// The time in seconds the running tests get to finish on shutdown before they are aborted.
+    pub shutdown_grace: u64,
 }
 /// Get config from the environment.
 pub fn get_config() -> Result<ServerConfig, envy::Error> {
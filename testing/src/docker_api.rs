@@ -8,10 +8,13 @@ use bollard::errors::ErrorKind;
 use bollard::service::{ContainerCreateResponse, HostConfig, Mount};
 
 use crate::checker::trim_lines;
+use crate::linter;
 use futures::StreamExt;
 use grpc_api::{Script, TargetOs};
+use std::collections::HashSet;
 use std::fmt::Write;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::time::timeout;
 
@@ -45,6 +48,20 @@ impl From<bollard::errors::Error> for Error {
     }
 }
 
+/// Either the time limit was reached or something else went wrong while running the container.
+enum ContainerError {
+    Timeout(tokio::time::Elapsed),
+    Other(Error),
+}
+
+/// A compiler running out of time is a failed build of the submission, not a timeout of the test.
+fn compile_error(e: ContainerError, time_limit: Duration) -> Error {
+    match e {
+        ContainerError::Timeout(_) => Error::CompileTimeout(time_limit.into()),
+        ContainerError::Other(e) => e,
+    }
+}
+
 #[derive(Debug)]
 enum MountPermission {
     Readonly,
@@ -80,6 +97,32 @@ pub fn docker_mount_points(script: &Script) -> (&'static str, &'static str) {
     }
 }
 
+/// Mount points of the custom checker: its own dir, the tested and the solution working dir.
+pub fn custom_checker_mount_points() -> (&'static str, &'static str, &'static str) {
+    if cfg!(target_family = "windows") {
+        (r"C:\checker\", r"C:\testing\", r"C:\solution\")
+    } else {
+        ("/checker/", "/testing/", "/solution/")
+    }
+}
+
+/// The command running the script or the compiled program inside ```inner_script_dir```.
+fn run_command<'a>(
+    script: &'a Script,
+    inner_script_dir: &'a str,
+    prog: &'a str,
+    name: &'a str,
+) -> Vec<&'a str> {
+    match script {
+        Script::Java => vec!["java", "-cp", inner_script_dir, name],
+        _ => {
+            let mut cmd = script.command_line();
+            cmd.push(prog);
+            cmd
+        }
+    }
+}
+
 fn create_mount_point<'a>(source: String, target: String, permission: MountPermission) -> Mount {
     Mount {
         target: Some(target),
@@ -114,27 +157,116 @@ pub fn create_host_config<'a>(
         ..Default::default()
     })
 }
+/// Host config with only one mounted dir. Used for the build step and the linters.
+fn create_single_mount_host_config(
+    mount: &MountContext,
+    permission: MountPermission,
+    memory_mb: u64,
+) -> Option<HostConfig> {
+    let mount_point = create_mount_point(
+        mount.source_dir.to_string(),
+        mount.target_dir.to_string(),
+        permission,
+    );
+    Some(HostConfig {
+        mounts: Some(vec![mount_point]),
+        memory: Some(to_mb(memory_mb)),
+        ..Default::default()
+    })
+}
 /// Util function into mega
 const fn to_mb(n: u64) -> i64 {
     (n * 1000000) as i64
 }
 
+/// Kills and removes the container when dropped. If the RPC call is cancelled, the test future is dropped
+/// with it, so the container is removed in the background and the permit of the test is free immediately.
+struct ContainerGuard {
+    docker: bollard::Docker,
+    containers: Containers,
+    id: Option<String>,
+}
+
+impl ContainerGuard {
+    fn id(&self) -> &str {
+        self.id.as_deref().unwrap_or_default()
+    }
+
+    /// Removes the container now and waits for it.
+    async fn remove(mut self) -> Result<(), bollard::errors::Error> {
+        match self.id.take() {
+            Some(id) => force_remove(&self.docker, &self.containers, &id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ContainerGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            log::info!("Test was cancelled, removing container {}", &id);
+            let docker = self.docker.clone();
+            let containers = self.containers.clone();
+            tokio::spawn(async move {
+                if let Err(e) = force_remove(&docker, &containers, &id).await {
+                    log::error!("Removing container {} failed: {}", &id, e);
+                }
+            });
+        }
+    }
+}
+
+/// The ids of all containers which are not removed yet.
+type Containers = Arc<Mutex<HashSet<String>>>;
+
+/// Kills and removes the container, it's forgotten once it's gone.
+async fn force_remove(
+    docker: &bollard::Docker,
+    containers: &Containers,
+    id: &str,
+) -> Result<(), bollard::errors::Error> {
+    let removed = docker
+        .remove_container(
+            id,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+    match removed {
+        Ok(()) => {}
+        // already removed in the background
+        Err(e) => match e.kind() {
+            ErrorKind::DockerResponseNotFoundError { .. } => {}
+            _ => return Err(e),
+        },
+    }
+    containers.lock().unwrap().remove(id);
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub struct DockerWrap {
     docker: bollard::Docker,
+    containers: Containers,
     image_name: String,
     timeout: Duration,
+    compile_timeout: Duration,
 }
 
 impl DockerWrap {
-    pub fn new(image_name: String, timout: u64) -> DockerWrap {
+    pub fn new(image_name: String, timout: u64, compile_timeout: u64) -> DockerWrap {
         DockerWrap {
             docker: bollard::Docker::connect_with_local_defaults()
                 .expect("Can't connect to docker api. Is the docker daemon running?"),
+            containers: Containers::default(),
             image_name,
             timeout: Duration::from_secs(timout),
+            compile_timeout: Duration::from_secs(compile_timeout),
         }
     }
+    /// Runs the script or for compiled languages the program inside ```script_path```'s parent dir.
     pub async fn test_in_container(
         &self,
         script: &Script,
@@ -153,32 +285,145 @@ impl DockerWrap {
         };
         let host_config = create_host_config(&out_dir_mount, &script_dir_mount);
         let script_name = script_path.file_name().unwrap().to_str().unwrap();
-        let mut cmd = script.command_line();
         let prog = format!("{}{}", inner_script_dir, script_name);
-        cmd.push(prog.as_str());
+        let mut cmd = run_command(script, inner_script_dir, &prog, script_name);
         cmd.extend(args_from_conf.iter().map(|x| x.as_str()));
-        let container = self
-            .create_container(cmd, host_config, inner_working_dir)
-            .await?;
-        log::info!("Container created");
-        let output = timeout(self.timeout, self.start_and_log_container(&container.id))
+        let output = self
+            .run_container(cmd, host_config, inner_working_dir, self.timeout)
             .await
-            .map_err(|e| {
-                let err = Error::Timeout(e, self.timeout.into());
-                log::info!("{}", &err);
-                err
+            .map_err(|e| match e {
+                ContainerError::Timeout(e) => Error::Timeout(e, self.timeout.into()),
+                ContainerError::Other(e) => e,
             })?;
-        self.docker
-            .remove_container(
-                &container.id,
-                Some(RemoveContainerOptions {
-                    force: true,
-                    ..Default::default()
-                }),
+        Ok(output)
+    }
+    /// Runs the build step of a compiled language inside the container. The build dir is mounted writable so the
+    /// program ends up next to the source.
+    pub async fn compile_in_container(
+        &self,
+        script: &Script,
+        build_dir: &Path,
+    ) -> Result<ScriptOutput, Error> {
+        let (_, inner_script_dir) = docker_mount_points(script);
+        let build_mount = MountContext {
+            source_dir: build_dir.to_str().unwrap(),
+            target_dir: inner_script_dir,
+        };
+        let cmd = script.compile_command().unwrap_or_default();
+        let output = self
+            .run_container(
+                cmd,
+                // compilers like javac and rustc need more memory
+                create_single_mount_host_config(&build_mount, MountPermission::Write, 512),
+                inner_script_dir,
+                self.compile_timeout,
             )
-            .await?;
+            .await
+            .map_err(|e| compile_error(e, self.compile_timeout))?;
+        log::info!("Compiler Output: {}", output);
+        output.compile_success()
+    }
+    /// Runs the linter for the script type inside the container, the script dir is mounted read only.
+    /// The linters exit with a non zero code if they found something, so only the output matters.
+    pub async fn lint_in_container(
+        &self,
+        script: &Script,
+        script_path: &Path,
+    ) -> Result<ScriptOutput, Error> {
+        let (_, inner_script_dir) = docker_mount_points(script);
+        let script_mount = MountContext {
+            source_dir: script_path.parent().unwrap().to_str().unwrap(),
+            target_dir: inner_script_dir,
+        };
+        let script_name = script_path.file_name().unwrap().to_str().unwrap();
+        let prog = format!("{}{}", inner_script_dir, script_name);
+        let cmd = linter::command(script, &prog).unwrap_or_default();
+        self.run_container(
+            cmd.iter().map(|x| x.as_str()).collect(),
+            create_single_mount_host_config(&script_mount, MountPermission::Readonly, 200),
+            inner_script_dir,
+            self.timeout,
+        )
+        .await
+        .map_err(|e| match e {
+            ContainerError::Timeout(e) => Error::Timeout(e, self.timeout.into()),
+            ContainerError::Other(e) => e,
+        })
+    }
+    /// Runs the custom checker inside ```checker_dir``` which also holds ```context.json```.
+    /// Both working dirs are mounted read only, the checker gets the paths as arguments.
+    pub async fn custom_check_in_container(
+        &self,
+        script: &Script,
+        checker_dir: &Path,
+        program_name: &str,
+        testing_dir: &Path,
+        solution_dir: &Path,
+    ) -> Result<ScriptOutput, Error> {
+        let (inner_checker_dir, inner_testing_dir, inner_solution_dir) =
+            custom_checker_mount_points();
+        let mounts = [
+            (checker_dir, inner_checker_dir),
+            (testing_dir, inner_testing_dir),
+            (solution_dir, inner_solution_dir),
+        ]
+        .iter()
+        .map(|(source, target)| {
+            create_mount_point(
+                source.to_str().unwrap().to_string(),
+                target.to_string(),
+                MountPermission::Readonly,
+            )
+        })
+        .collect();
+        let host_config = Some(HostConfig {
+            mounts: Some(mounts),
+            memory: Some(to_mb(200)),
+            ..Default::default()
+        });
+        let prog = format!("{}{}", inner_checker_dir, program_name);
+        let context = format!("{}context.json", inner_checker_dir);
+        let mut cmd = run_command(script, inner_checker_dir, &prog, program_name);
+        cmd.extend(&[context.as_str(), inner_testing_dir, inner_solution_dir]);
+        self.run_container(cmd, host_config, inner_testing_dir, self.timeout)
+            .await
+            .map_err(|e| match e {
+                ContainerError::Timeout(e) => Error::Timeout(e, self.timeout.into()),
+                ContainerError::Other(e) => e,
+            })
+    }
+    /// Creates the container, waits until it's done or the time is up and removes it.
+    /// The container is also removed if the future is dropped before.
+    async fn run_container(
+        &self,
+        cmd: Vec<&str>,
+        host_config: Option<HostConfig>,
+        working_dir: &str,
+        time_limit: Duration,
+    ) -> Result<ScriptOutput, ContainerError> {
+        let id = self
+            .create_container(cmd, host_config, working_dir)
+            .await
+            .map_err(|e| ContainerError::Other(e.into()))?
+            .id;
+        self.containers.lock().unwrap().insert(id.clone());
+        let container = ContainerGuard {
+            docker: self.docker.clone(),
+            containers: self.containers.clone(),
+            id: Some(id),
+        };
+        log::info!("Container created");
+        let output = timeout(time_limit, self.start_and_log_container(container.id())).await;
+        container
+            .remove()
+            .await
+            .map_err(|e| ContainerError::Other(e.into()))?;
         log::info!("Container removed");
-        output
+        let output = output.map_err(|e| {
+            log::info!("Time out reached after {} secs", time_limit.as_secs());
+            ContainerError::Timeout(e)
+        })?;
+        output.map_err(ContainerError::Other)
     }
 
     // TODO set MacAddress, args_escaped on windows?!
@@ -256,6 +501,16 @@ impl DockerWrap {
             status_code,
         })
     }
+    /// Removes the containers which are still left, e.g. of aborted tests on shutdown.
+    pub async fn remove_containers(&self) {
+        let ids: Vec<String> = self.containers.lock().unwrap().iter().cloned().collect();
+        for id in ids {
+            match force_remove(&self.docker, &self.containers, &id).await {
+                Ok(()) => log::info!("Removed container {}", &id),
+                Err(e) => log::error!("Removing container {} failed: {}", &id, e),
+            }
+        }
+    }
     pub async fn pull_image(&self) -> Result<(), DockerError> {
         use bollard::image::CreateImageOptions;
         let options = Some(CreateImageOptions {
@@ -284,7 +539,7 @@ impl DockerWrap {
     }
 }
 /// Script sdtout and stderr and status code.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct ScriptOutput {
     pub stdout: String,
     pub stderr: String,
@@ -292,6 +547,18 @@ pub struct ScriptOutput {
 }
 
 impl ScriptOutput {
+    /// The build step failed if the compiler exits with a non zero code, its output is the message.
+    pub fn compile_success(self) -> Result<ScriptOutput, Error> {
+        if self.status_code == 0 {
+            Ok(self)
+        } else {
+            Err(Error::CompilationFailed(
+                format!("{}\n{}", self.stdout, self.stderr)
+                    .trim()
+                    .to_string(),
+            ))
+        }
+    }
     pub fn status_success(&self) -> Result<(), Error> {
         if self.stderr.is_empty() || self.status_code == 0 {
             Ok(())
@@ -300,3 +567,58 @@ impl ScriptOutput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(stdout: &str, stderr: &str, status_code: i64) -> ScriptOutput {
+        ScriptOutput {
+            stdout: stdout.into(),
+            stderr: stderr.into(),
+            status_code,
+        }
+    }
+
+    #[test]
+    fn successful_build_is_passed_on() {
+        let build = output("", "main.c:3: warning: unused variable", 0).compile_success();
+        assert_eq!(build.unwrap().status_code, 0);
+    }
+
+    #[test]
+    fn compiler_errors_fail_the_build() {
+        match output("", "main.c:3:5: error: expected ';'\n", 1).compile_success() {
+            Err(e @ Error::CompilationFailed(_)) => {
+                assert!(e.is_compilation_failed());
+                assert_eq!(
+                    e.to_string(),
+                    "Compilation failed: main.c:3:5: error: expected ';'"
+                );
+            }
+            other => panic!("expected a failed compilation, got {:?}", other),
+        }
+        match output("error[E0425]: cannot find value", "aborting", 1).compile_success() {
+            Err(Error::CompilationFailed(message)) => {
+                assert_eq!(message, "error[E0425]: cannot find value\naborting")
+            }
+            other => panic!("expected a failed compilation, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn compiler_timeout_fails_the_build() {
+        let elapsed = timeout(Duration::from_millis(1), futures::future::pending::<()>())
+            .await
+            .unwrap_err();
+        let e = compile_error(ContainerError::Timeout(elapsed), Duration::from_secs(60));
+        assert!(matches!(e, Error::CompileTimeout(_)));
+        assert!(e.is_compilation_failed());
+        let e = compile_error(
+            ContainerError::Other(Error::ExitCode("no space left".into())),
+            Duration::from_secs(60),
+        );
+        assert!(matches!(e, Error::ExitCode(_)));
+        assert!(!e.is_compilation_failed());
+    }
+}
@@ -0,0 +1,144 @@
+//! Draining before maintenance and graceful shutdown.
+//! While draining new tests are rejected with ```UNAVAILABLE```, so the API retries them on another testing server.
+//! The running tests are tracked, on shutdown they get a grace period to finish and are aborted afterwards.
+use futures::future::{AbortHandle, Abortable, Aborted};
+use grpc_api::DrainStatus;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often ```idle``` checks if all tests are done.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default)]
+pub struct Drain {
+    draining: AtomicBool,
+    next_id: AtomicU64,
+    /// The running tests.
+    running: Mutex<HashMap<u64, AbortHandle>>,
+}
+
+impl Drain {
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn set_draining(&self, draining: bool) {
+        if self.draining.swap(draining, Ordering::SeqCst) != draining {
+            if draining {
+                log::info!("Draining, {} tests are running", self.running());
+            } else {
+                log::info!("Draining stopped, accepting tests again");
+            }
+        }
+    }
+
+    pub fn running(&self) -> usize {
+        self.running.lock().unwrap().len()
+    }
+
+    pub fn status(&self) -> DrainStatus {
+        DrainStatus {
+            draining: self.is_draining(),
+            running: self.running() as u32,
+        }
+    }
+
+    /// Runs the test until it's done or aborted by ```abort_all```.
+    /// The test also stops running if the returned future is dropped, e.g. when the API closes the connection.
+    pub async fn run<F: Future>(&self, test: F) -> Result<F::Output, Aborted> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (handle, registration) = AbortHandle::new_pair();
+        self.running.lock().unwrap().insert(id, handle);
+        let _running = Running { drain: self, id };
+        Abortable::new(test, registration).await
+    }
+
+    /// Aborts all running tests, their containers are removed when the test futures are dropped.
+    /// Returns the number of aborted tests.
+    pub fn abort_all(&self) -> usize {
+        let running: Vec<_> = self.running.lock().unwrap().drain().collect();
+        for (_, handle) in &running {
+            handle.abort();
+        }
+        running.len()
+    }
+
+    /// Waits until no test is running.
+    pub async fn idle(&self) {
+        while self.running() > 0 {
+            tokio::time::delay_for(IDLE_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Removes the test from the running tests when it's done or dropped.
+struct Running<'a> {
+    drain: &'a Drain,
+    id: u64,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.drain.running.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Resolves on Ctrl-C and on Unix also on ```SIGTERM```, which is sent by ```docker stop```.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => log::error!("Can't listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::error!("Can't listen for Ctrl-C: {}", e);
+        futures::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+
+    #[tokio::test]
+    async fn finished_test_is_removed() {
+        let drain = Drain::default();
+        assert_eq!(drain.run(async { 42 }).await, Ok(42));
+        assert_eq!(drain.running(), 0);
+    }
+
+    #[tokio::test]
+    async fn dropped_test_is_removed() {
+        let drain = Drain::default();
+        let mut test = Box::pin(drain.run(future::pending::<()>()));
+        assert!(futures::poll!(&mut test).is_pending());
+        assert_eq!(drain.running(), 1);
+        drop(test);
+        assert_eq!(drain.running(), 0);
+        drain.idle().await;
+    }
+
+    #[tokio::test]
+    async fn running_tests_are_aborted() {
+        let drain = Drain::default();
+        let mut test = Box::pin(drain.run(future::pending::<()>()));
+        assert!(futures::poll!(&mut test).is_pending());
+        assert_eq!(drain.status().running, 1);
+        assert_eq!(drain.abort_all(), 1);
+        assert_eq!(test.await, Err(Aborted));
+        assert_eq!(drain.running(), 0);
+    }
+}
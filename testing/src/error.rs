@@ -1,5 +1,6 @@
 //! Error handling using [failure](https://docs.rs/crate/failure) as error library.
 use crate::docker_api::DockerError;
+use crate::feedback;
 use std::path::PathBuf;
 use std::{fmt, time};
 use zip::result::ZipError;
@@ -12,8 +13,6 @@ pub enum IOError {
     Copy(std::io::Error),
     #[fail(display = "Can't create temp file. {}", _0)]
     CreateFile(std::io::Error),
-    #[fail(display = "Failed to run the custom scrip. Error: {}", _0)]
-    FailedRunCustomScript(tokio::io::Error),
     #[fail(display = "Zip error: {}", _0)]
     Zip(zip::result::ZipError),
     #[fail(display = "IO error while reading the dir {:?}", _0)]
@@ -27,6 +26,15 @@ pub enum SystemError {
     Docker(DockerError),
     #[fail(display = "Error in sample solution.")]
     BadSampleSolution,
+    #[fail(display = "Linter output could not be parsed: {}", _0)]
+    Linter(String),
+    #[fail(display = "Custom checker could not be built: {}", _0)]
+    CustomChecker(String),
+    #[fail(
+        display = "Required and forbidden commands can't be checked for {:?} scripts.",
+        _0
+    )]
+    NoCommandCheck(grpc_api::Script),
 }
 
 impl From<zip::result::ZipError> for IOError {
@@ -54,11 +62,18 @@ pub enum Error {
     InvalidTest(SystemError),
     #[fail(display = "Time out reached! Script took more than {}.", _1)]
     Timeout(tokio::time::Elapsed, DurationDisplay),
+    #[fail(display = "Compilation failed: {}", _0)]
+    CompilationFailed(String),
+    #[fail(display = "Compilation failed! The compiler took more than {}.", _0)]
+    CompileTimeout(DurationDisplay),
     #[from]
     #[fail(display = "Script produced invalid UTF8.")]
     NoUTF8(std::string::FromUtf8Error),
-    #[fail(display = "Does not contains expected output: {}", _0)]
-    WrongOutput(String),
+    #[fail(
+        display = "Does not contains expected output: Expected STDOUT:({}) Result STDOUT:({})",
+        _0, _1
+    )]
+    WrongOutput(String, String),
     #[fail(display = "Solution dir and tested dir have not the same content")]
     ExpectedDirNotSame,
     #[fail(display = "Script finished with exit code 1 stderr: {}", _0)]
@@ -70,8 +85,12 @@ pub enum Error {
     ExpectedFileNotSame(PathBuf, String, String),
     #[fail(display = "Regex error {}", _0)]
     InvalidRegex(String),
-    #[fail(display = "No Regex match found in '{}' for regex: '{}'", _0, _1)]
-    NoRegexMatch(String, regex::Regex),
+    #[fail(display = "No Regex match found in the {} for regex: '{}'", _0, _1)]
+    NoRegexMatch(String, String),
+    #[fail(display = "The {} must not match the regex: '{}'", _0, _1)]
+    ForbiddenRegexMatch(String, String),
+    #[fail(display = "{}", _0)]
+    RegexRule(String),
     #[fail(display = "Stdout is not sorted. stdout: {:#?}", _0)]
     NoSorted(String),
     #[fail(
@@ -79,6 +98,63 @@ pub enum Error {
         _0
     )]
     CustomScript(String),
+    #[fail(display = "{}", _0)]
+    CustomCheckFailed(String),
+    #[fail(
+        display = "The linter found {} issue(s) with severity {:?} or higher.",
+        _0, _1
+    )]
+    LintFindings(usize, grpc_api::Severity),
+    #[fail(display = "The command '{}' is not allowed (line {}).", _0, _1)]
+    ForbiddenCommand(String, usize),
+    #[fail(display = "The script has to use the command '{}'.", _0)]
+    MissingCommand(String),
+    #[fail(
+        display = "The script nests commands deeper than {} levels and can't be checked.",
+        _0
+    )]
+    TooDeeplyNested(usize),
+    #[fail(display = "The submitted zip archive is invalid: {}", _0)]
+    BadArchive(String),
+    /// A finding in one of the scripts of a zip submission.
+    #[fail(display = "{}: {}", _0, _1)]
+    InFile(String, Box<Error>),
+}
+
+impl Error {
+    /// The build step of a compiled language failed, this is reported as a separate verdict.
+    pub fn is_compilation_failed(&self) -> bool {
+        matches!(self, Error::CompilationFailed(_) | Error::CompileTimeout(_))
+    }
+    /// The message without the expected output of the sample solution or the patterns of the regex rules,
+    /// for assignments whose feedback level hides them.
+    pub fn redacted(&self) -> String {
+        match self {
+            Error::WrongOutput(_, _) => "The output does not match the expected output.".into(),
+            Error::ExpectedFileNotSame(path, _, _) => format!(
+                "This content of this file {:?} does not match with the solution.",
+                path.file_name().unwrap_or_default()
+            ),
+            Error::NoRegexMatch(target, _) => format!("No Regex match found in the {}.", target),
+            Error::ForbiddenRegexMatch(target, _) => {
+                format!("The {} matches a forbidden regex.", target)
+            }
+            Error::CustomScript(_) => "The custom checker rejected the submission.".into(),
+            Error::InFile(path, e) => format!("{}: {}", path, e.redacted()),
+            _ => self.to_string(),
+        }
+    }
+    /// The differing lines of the expected and the actual output, only for the stdout and files checks.
+    pub fn diff(&self) -> Option<String> {
+        match self {
+            Error::WrongOutput(expected, tested)
+            | Error::ExpectedFileNotSame(_, expected, tested) => {
+                Some(feedback::diff(expected, tested))
+            }
+            Error::InFile(_, e) => e.diff(),
+            _ => None,
+        }
+    }
 }
 #[derive(Debug, derive_more::From)]
 pub struct DurationDisplay(time::Duration);
@@ -0,0 +1,238 @@
+//! Applies the feedback level of the assignment when the result is built,
+//! so graded assignments don't leak the expected output of the sample solution to the student.
+//! The unredacted result is returned alongside for tutors.
+use grpc_api::{AssignmentResult, CheckResult, FeedbackLevel, TestReply};
+
+/// Outputs which differ in more lines are not diffed line by line, the diff would be useless anyway.
+/// This keeps the table of the longest common subsequence below 500 × 500 cells.
+const MAX_DIFF_LINES: usize = 1000;
+
+/// Builds the reply from the unredacted ```detail```. ```message``` and ```checks``` are the messages without
+/// expected values of the result and of each check, in the same order as ```detail.checks```.
+pub fn reply(
+    level: FeedbackLevel,
+    detail: AssignmentResult,
+    message: Option<String>,
+    checks: Vec<String>,
+) -> TestReply {
+    let result = match level {
+        FeedbackLevel::Full => {
+            return TestReply {
+                result: Some(detail),
+                detail: None,
+            }
+        }
+        FeedbackLevel::DiffOnly | FeedbackLevel::PassFailPerCheck => AssignmentResult {
+            message,
+            checks: detail
+                .checks
+                .iter()
+                .zip(checks)
+                .map(|(check, message)| CheckResult {
+                    message,
+                    diff: if level == FeedbackLevel::DiffOnly {
+                        check.diff.clone()
+                    } else {
+                        String::new()
+                    },
+                    ..check.clone()
+                })
+                .collect(),
+            ..detail.clone()
+        },
+        FeedbackLevel::PassFailOnly => AssignmentResult {
+            passed: detail.passed,
+            // errors of the testing server are no feedback on the submission
+            message: if detail.valid { None } else { message },
+            valid: detail.valid,
+            compilation_failed: detail.compilation_failed,
+            diagnostics: vec![],
+            checks: vec![],
+            attestation: None,
+        },
+    };
+    TestReply {
+        result: Some(result),
+        detail: Some(detail),
+    }
+}
+
+/// The lines which differ between the expected and the actual output, prefixed with ```-``` or ```+```
+/// and the line number, based on the longest common subsequence of lines.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // the equal lines at the start and at the end are left out of the table
+    let start = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(e, a)| e == a)
+        .count();
+    let end = expected[start..]
+        .iter()
+        .rev()
+        .zip(actual[start..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+    let (expected_all, actual_all) = (expected.len(), actual.len());
+    let expected = &expected[start..expected_all - end];
+    let actual = &actual[start..actual_all - end];
+    if expected.len() + actual.len() > MAX_DIFF_LINES {
+        return format!(
+            "The output differs in too many lines for a diff, expected {} lines, got {} lines.",
+            expected_all, actual_all
+        );
+    }
+    // lcs[i][j] is the length of the longest common subsequence of expected[i..] and actual[j..]
+    let mut lcs = vec![vec![0u32; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("-{}: {}", start + i + 1, expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}: {}", start + j + 1, actual[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grpc_api::Diagnostic;
+
+    fn detail(valid: bool) -> AssignmentResult {
+        AssignmentResult {
+            passed: false,
+            message: Some("Expected 'a b', got 'b a'".into()),
+            valid,
+            compilation_failed: false,
+            diagnostics: vec![Diagnostic {
+                line: 1,
+                message: "quote the variable".into(),
+                ..Default::default()
+            }],
+            checks: vec![CheckResult {
+                name: "stdout".into(),
+                passed: false,
+                message: "Expected 'a b', got 'b a'".into(),
+                score: None,
+                diff: "-1: a b\n+1: b a".into(),
+            }],
+            attestation: None,
+        }
+    }
+
+    fn redacted_reply(level: FeedbackLevel, valid: bool) -> TestReply {
+        reply(
+            level,
+            detail(valid),
+            Some("The output is wrong".into()),
+            vec!["The output is wrong".into()],
+        )
+    }
+
+    #[test]
+    fn full_feedback_is_not_redacted() {
+        let reply = redacted_reply(FeedbackLevel::Full, true);
+        assert_eq!(reply.result, Some(detail(true)));
+        assert_eq!(reply.detail, None);
+    }
+
+    #[test]
+    fn diff_only_removes_the_expected_values() {
+        let reply = redacted_reply(FeedbackLevel::DiffOnly, true);
+        let result = reply.result.unwrap();
+        assert_eq!(result.message.as_deref(), Some("The output is wrong"));
+        assert_eq!(result.diagnostics, detail(true).diagnostics);
+        assert_eq!(result.checks[0].message, "The output is wrong");
+        assert_eq!(result.checks[0].diff, "-1: a b\n+1: b a");
+        assert_eq!(reply.detail, Some(detail(true)));
+    }
+
+    #[test]
+    fn pass_fail_per_check_removes_the_diff() {
+        let reply = redacted_reply(FeedbackLevel::PassFailPerCheck, true);
+        let result = reply.result.unwrap();
+        assert_eq!(result.message.as_deref(), Some("The output is wrong"));
+        assert_eq!(result.checks[0].name, "stdout");
+        assert!(!result.checks[0].passed);
+        assert_eq!(result.checks[0].message, "The output is wrong");
+        assert_eq!(result.checks[0].diff, "");
+        assert_eq!(reply.detail, Some(detail(true)));
+    }
+
+    #[test]
+    fn pass_fail_only_removes_the_checks() {
+        let reply = redacted_reply(FeedbackLevel::PassFailOnly, true);
+        let result = reply.result.unwrap();
+        assert!(!result.passed);
+        assert_eq!(result.message, None);
+        assert!(result.diagnostics.is_empty());
+        assert!(result.checks.is_empty());
+        assert_eq!(reply.detail, Some(detail(true)));
+        // errors of the testing server are still reported
+        let invalid = redacted_reply(FeedbackLevel::PassFailOnly, false);
+        assert_eq!(
+            invalid.result.unwrap().message.as_deref(),
+            Some("The output is wrong")
+        );
+    }
+
+    #[test]
+    fn equal_outputs_have_no_diff() {
+        assert_eq!(diff("", ""), "");
+        assert_eq!(diff("a\nb\n", "a\nb"), "");
+    }
+
+    #[test]
+    fn changed_lines_are_removed_and_added() {
+        assert_eq!(diff("a\nb\nc", "a\nx\nc"), "-2: b\n+2: x");
+        assert_eq!(diff("a\nb", "b\na"), "-1: a\n+2: a");
+    }
+
+    #[test]
+    fn missing_and_additional_lines() {
+        assert_eq!(diff("a\nb\nc", "a\nc"), "-2: b");
+        assert_eq!(diff("a\nc", "a\nb\nc"), "+2: b");
+        assert_eq!(diff("a", ""), "-1: a");
+        assert_eq!(diff("", "a\nb"), "+1: a\n+2: b");
+    }
+
+    #[test]
+    fn line_numbers_count_the_equal_lines() {
+        let expected: String = (1..=5000).map(|i| format!("{}\n", i)).collect();
+        let actual = expected.replace("\n4000\n", "\nfour thousand\n");
+        assert_eq!(
+            diff(&expected, &actual),
+            "-4000: 4000\n+4000: four thousand"
+        );
+    }
+
+    #[test]
+    fn too_many_differing_lines_are_not_diffed() {
+        let expected: String = (0..600).map(|i| format!("{}\n", i)).collect();
+        let actual: String = (0..600).map(|i| format!("line {}\n", i)).collect();
+        assert_eq!(
+            diff(&expected, &actual),
+            "The output differs in too many lines for a diff, expected 600 lines, got 600 lines."
+        );
+        let actual = format!("{}{}", expected, "more\n".repeat(MAX_DIFF_LINES + 1));
+        assert!(diff(&expected, &actual).starts_with("The output differs in too many lines"));
+    }
+}
@@ -2,11 +2,13 @@
 use crate::error::{Error, IOError};
 use async_stream::try_stream;
 use futures::stream::Stream;
+use futures::{pin_mut, StreamExt};
 use grpc_api::{Script, TargetOs};
 use std::io;
+use std::io::Read;
 use std::io::Write;
-use std::path::{Path, PathBuf};
-use tempfile::{Builder, NamedTempFile, TempDir};
+use std::path::{Component, Path, PathBuf};
+use tempfile::{Builder, NamedTempFile, TempDir, TempPath};
 use tokio::fs;
 
 const TEMP_DIR: &str = "/tmp/scripts";
@@ -51,6 +53,198 @@ pub fn new_tmp_script_file(
     Ok(file)
 }
 
+/// The script file for interpreted languages, the unpacked zip submission with its entry point
+/// or the build dir holding the compiled program.
+pub enum Program {
+    Script(TempPath),
+    Archive(TempDir, String),
+    Build(TempDir, Script),
+}
+
+impl Program {
+    /// Path of the script or the compiled program which is run inside the container.
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Program::Script(path) => path.to_path_buf(),
+            Program::Archive(dir, entry_point) => dir.path().join(entry_point),
+            Program::Build(dir, script_type) => dir.path().join(script_type.executable_name()),
+        }
+    }
+}
+
+/// Limits for unpacking zip submissions.
+#[derive(Debug, Clone, Copy)]
+pub struct ZipLimits {
+    pub max_files: usize,
+    pub max_bytes: u64,
+}
+
+/// A script of the submission, the path is relative to the root of a zip submission and empty for a single script.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SourceFile {
+    pub path: String,
+    pub content: String,
+}
+
+impl SourceFile {
+    pub fn single(content: &str) -> Self {
+        SourceFile {
+            path: String::new(),
+            content: content.to_string(),
+        }
+    }
+
+    /// Prefixes the finding with the path, so the student knows which file of the archive it is about.
+    pub fn prefix(&self, message: &str) -> String {
+        if self.path.is_empty() {
+            message.to_string()
+        } else {
+            format!("{}: {}", self.path, message)
+        }
+    }
+
+    /// Like ```prefix``` for the error of a checker.
+    pub fn locate(&self, e: Error) -> Error {
+        if self.path.is_empty() {
+            e
+        } else {
+            Error::InFile(self.path.clone(), Box::new(e))
+        }
+    }
+}
+
+/// Unpacks the zip submission into a new temp dir and returns it with the scripts of the archive,
+/// the entry point first and then every other file with the extension of the script type.
+/// The entry point has to be a file in the root of the archive, so the whole archive is mounted next to it.
+pub async fn extract_submission(
+    zip: &[u8],
+    entry_point: &str,
+    script_type: Script,
+    limits: ZipLimits,
+) -> Result<(TempDir, Vec<SourceFile>), Error> {
+    let mut entry = Path::new(entry_point).components();
+    match (entry.next(), entry.next()) {
+        (Some(Component::Normal(_)), None) => {}
+        _ => {
+            return Err(Error::BadArchive(format!(
+                "the entry point '{}' must be a file name",
+                entry_point
+            )))
+        }
+    }
+    let dir = new_tmp_dir().await.map_err(IOError::CreateFile)?;
+    let c_dir = dir.path();
+    tokio::task::block_in_place(move || unzip_submission(c_dir, zip, limits))?;
+    let entry_path = dir.path().join(entry_point);
+    if !entry_path.is_file() {
+        return Err(Error::BadArchive(format!(
+            "the entry point '{}' is missing",
+            entry_point
+        )));
+    }
+    let mut others = Vec::new();
+    let listing = ls_dir_content(dir.path().to_path_buf());
+    pin_mut!(listing);
+    while let Some(path) = listing.next().await {
+        let path = path?;
+        if path.is_file()
+            && path
+                .to_string_lossy()
+                .ends_with(script_type.file_extension())
+        {
+            let relative = path
+                .strip_prefix(dir.path())
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if relative != entry_point {
+                others.push(relative);
+            }
+        }
+    }
+    others.sort();
+    let mut scripts = vec![read_script(dir.path(), entry_point.to_string(), script_type).await?];
+    for path in others {
+        scripts.push(read_script(dir.path(), path, script_type).await?);
+    }
+    Ok((dir, scripts))
+}
+
+/// Reads a script of the unpacked archive, the line endings of scripts for unix are fixed in place.
+async fn read_script(dir: &Path, path: String, script_type: Script) -> Result<SourceFile, Error> {
+    let full_path = dir.join(&path);
+    let content = fs::read(&full_path).await.map_err(IOError::ReadFile)?;
+    let content = String::from_utf8(content)
+        .map_err(|_| Error::BadArchive(format!("the script '{}' is not UTF-8", path)))?;
+    let content = if script_type.target_os() == TargetOs::Unix && content.contains("\r\n") {
+        let content = content.replace("\r\n", "\n");
+        fs::write(&full_path, &content)
+            .await
+            .map_err(IOError::CreateFile)?;
+        content
+    } else {
+        content
+    };
+    Ok(SourceFile { path, content })
+}
+
+/// Like ```unzip_into_dir``` but the archive comes from a student, so every path has to stay inside ```outdir```
+/// and the number of files and the unpacked size are limited.
+fn unzip_submission(outdir: &Path, zip_buf: &[u8], limits: ZipLimits) -> Result<(), Error> {
+    let bad_archive = |e: zip::result::ZipError| Error::BadArchive(e.to_string());
+    let mut zip = zip::ZipArchive::new(io::Cursor::new(zip_buf)).map_err(bad_archive)?;
+    if zip.len() > limits.max_files {
+        return Err(Error::BadArchive(format!(
+            "more than {} files",
+            limits.max_files
+        )));
+    }
+    let mut remaining = limits.max_bytes;
+    for i in 0..zip.len() {
+        let file = zip.by_index(i).map_err(bad_archive)?;
+        let relative = Path::new(file.name());
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::BadArchive(format!(
+                "the path '{}' leaves the archive",
+                file.name()
+            )));
+        }
+        let outpath = outdir.join(relative);
+        if file.is_dir() {
+            std::fs::create_dir_all(&outpath).map_err(IOError::CreateFile)?;
+            continue;
+        }
+        if let Some(p) = outpath.parent() {
+            std::fs::create_dir_all(p).map_err(IOError::CreateFile)?;
+        }
+        let mut outfile = std::fs::File::create(&outpath).map_err(IOError::CreateFile)?;
+        // the size in the header can't be trusted, so at most one byte more than allowed is read
+        let written =
+            io::copy(&mut file.take(remaining + 1), &mut outfile).map_err(IOError::Copy)?;
+        if written > remaining {
+            return Err(Error::BadArchive(format!(
+                "the unpacked files are larger than {} bytes",
+                limits.max_bytes
+            )));
+        }
+        remaining -= written;
+    }
+    Ok(())
+}
+
+/// Creates a build dir containing the source file for compiled languages. The compiled program is written next to it.
+pub async fn new_build_dir(script_type: Script, content: &str) -> Result<TempDir, std::io::Error> {
+    let dir = new_tmp_dir().await?;
+    let content = content.replace("\r\n", "\n");
+    fs::write(dir.path().join(script_type.source_file_name()), content).await?;
+    Ok(dir)
+}
+
 pub fn ls_dir_content(root: PathBuf) -> impl Stream<Item = Result<PathBuf, IOError>> {
     try_stream! {
         if !root.is_file() {
@@ -91,3 +285,112 @@ fn unzip_into_dir(outdir: &Path, zip_buf: &[u8]) -> Result<(), IOError> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::write::FileOptions;
+
+    const LIMITS: ZipLimits = ZipLimits {
+        max_files: 3,
+        max_bytes: 10,
+    };
+
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, content) in files {
+            if name.ends_with('/') {
+                writer.add_directory(*name, FileOptions::default()).unwrap();
+            } else {
+                writer.start_file(*name, FileOptions::default()).unwrap();
+                writer.write_all(content.as_bytes()).unwrap();
+            }
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn unzip(files: &[(&str, &str)]) -> (TempDir, Result<(), Error>) {
+        let dir = tempfile::tempdir().unwrap();
+        let result = unzip_submission(dir.path(), &zip(files), LIMITS);
+        (dir, result)
+    }
+
+    fn assert_bad_archive(result: Result<(), Error>) {
+        match result {
+            Err(Error::BadArchive(_)) => {}
+            other => panic!("expected a bad archive, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unpacks_files_and_dirs() {
+        let (dir, result) = unzip(&[("main.sh", "main"), ("lib/", ""), ("lib/a.sh", "a")]);
+        result.unwrap();
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("main.sh"), "main");
+        assert_eq!(read("lib/a.sh"), "a");
+    }
+
+    #[test]
+    fn parent_dirs_are_rejected() {
+        let outer = tempfile::tempdir().unwrap();
+        let dir = outer.path().join("submission");
+        std::fs::create_dir(&dir).unwrap();
+        for name in &["../evil.sh", "lib/../../evil.sh"] {
+            let result = unzip_submission(&dir, &zip(&[(name, "evil")]), LIMITS);
+            assert_bad_archive(result);
+            assert!(!outer.path().join("evil.sh").exists());
+        }
+    }
+
+    #[test]
+    fn absolute_paths_are_rejected() {
+        let (_dir, result) = unzip(&[("/tmp/evil.sh", "evil")]);
+        assert_bad_archive(result);
+    }
+
+    #[test]
+    fn too_many_files() {
+        let (_dir, result) = unzip(&[("a", ""), ("b", ""), ("c", "")]);
+        result.unwrap();
+        let (_dir, result) = unzip(&[("a", ""), ("b", ""), ("c", ""), ("d", "")]);
+        assert_bad_archive(result);
+    }
+
+    #[test]
+    fn size_limit_counts_all_files() {
+        let (_dir, result) = unzip(&[("a", "12345"), ("b", "67890")]);
+        result.unwrap();
+        let (_dir, result) = unzip(&[("a", "12345"), ("b", "678901")]);
+        assert_bad_archive(result);
+        let (_dir, result) = unzip(&[("a", "12345678901")]);
+        assert_bad_archive(result);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn lists_the_scripts_entry_point_first() {
+        let zip = zip(&[
+            ("util.sh", "u"),
+            ("main.sh", "m\r\n"),
+            ("lib/", ""),
+            ("lib/a.sh", "a"),
+            ("notes.txt", "n"),
+        ]);
+        let limits = ZipLimits {
+            max_files: 10,
+            max_bytes: 100,
+        };
+        let (_dir, scripts) = extract_submission(&zip, "main.sh", Script::Bash, limits)
+            .await
+            .unwrap();
+        let paths: Vec<_> = scripts.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, vec!["main.sh", "lib/a.sh", "util.sh"]);
+        assert_eq!(scripts[0].content, "m\n");
+    }
+
+    #[test]
+    fn not_a_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_bad_archive(unzip_submission(dir.path(), b"no zip", LIMITS));
+    }
+}
@@ -1,25 +1,41 @@
 //! The RPC testing server.
 use crate::checker::{
-    Checker, CustomScriptChecker, FilesChecker, RegexChecker, SortedChecker, StdoutChecker,
+    Checker, CommandChecker, CustomCheckContext, CustomScriptChecker, FilesChecker, LintChecker,
+    RegexChecker, SortedChecker, StdoutChecker,
 };
-use crate::docker_api::DockerWrap;
+use crate::docker_api::{custom_checker_mount_points, DockerWrap};
+use crate::drain::Drain;
 use crate::error::{Error, IOError, SystemError};
-use crate::{fs_util, sema_wrap};
+use crate::fs_util::{Program, SourceFile, ZipLimits};
+use crate::{feedback, fs_util, linter, sema_wrap};
 use futures::future;
 use grpc_api::test_server::Test;
-use grpc_api::{Assignment, AssignmentMsg, AssignmentResult, RegexMode, Script, SortStdoutBy};
+use grpc_api::{
+    Assignment, AssignmentMsg, AssignmentResult, CheckResult, Diagnostic, DrainRequest,
+    DrainStatus, LintMode, Script, SortStdoutBy, TestReply,
+};
 use log::info;
+use std::sync::Arc;
 use tonic::{Request, Response, Status};
 /// State
 #[derive(Debug, Clone)]
 pub struct Tester {
     docker: sema_wrap::SemWrap<DockerWrap>,
+    zip_limits: ZipLimits,
+    drain: Arc<Drain>,
 }
 
 impl Tester {
-    pub fn new(docker: DockerWrap, max_sema: usize) -> Self {
+    pub fn new(
+        docker: DockerWrap,
+        max_sema: usize,
+        zip_limits: ZipLimits,
+        drain: Arc<Drain>,
+    ) -> Self {
         Tester {
             docker: sema_wrap::SemWrap::new(docker, max_sema),
+            zip_limits,
+            drain,
         }
     }
 }
@@ -28,30 +44,71 @@ impl Test for Tester {
     async fn run_test(
         &self,
         request: Request<AssignmentMsg>,
-    ) -> Result<Response<AssignmentResult>, Status> {
+    ) -> Result<Response<TestReply>, Status> {
+        if self.drain.is_draining() {
+            return Err(Status::unavailable("The testing server is draining"));
+        }
         let req = request.into_inner();
         if let Some(assignment) = req.assignment {
-            let reply = match self.inner_run_test(&assignment, &req.code_to_test).await {
+            let mut report = Report::default();
+            let outcome = self
+                .drain
+                .run(self.inner_run_test(
+                    &assignment,
+                    &req.code_to_test,
+                    &req.source_zip,
+                    &mut report,
+                ))
+                .await
+                .map_err(|_| {
+                    Status::unavailable("The test was aborted, the testing server is shutting down")
+                })?;
+            let (detail, redacted) = match outcome {
                 Err(Error::InvalidTest(e)) => {
                     log::error!("Invalid test error_msg: {}", e);
-                    AssignmentResult {
+                    let result = AssignmentResult {
                         passed: false,
                         message: Some(e.to_string()),
                         valid: false,
-                    }
+                        compilation_failed: false,
+                        diagnostics: report.diagnostics,
+                        checks: report.checks,
+                        // signed by the API
+                        attestation: None,
+                    };
+                    (result, Some(e.to_string()))
+                }
+                Err(e) => {
+                    let result = AssignmentResult {
+                        passed: false,
+                        message: Some(e.to_string()),
+                        valid: true,
+                        compilation_failed: e.is_compilation_failed(),
+                        diagnostics: report.diagnostics,
+                        checks: report.checks,
+                        attestation: None,
+                    };
+                    (result, Some(e.redacted()))
+                }
+                Ok(_) => {
+                    let result = AssignmentResult {
+                        passed: true,
+                        message: None,
+                        valid: true,
+                        compilation_failed: false,
+                        diagnostics: report.diagnostics,
+                        checks: report.checks,
+                        attestation: None,
+                    };
+                    (result, None)
                 }
-                Err(e) => AssignmentResult {
-                    passed: false,
-                    message: Some(e.to_string()),
-                    valid: true,
-                },
-                Ok(_) => AssignmentResult {
-                    passed: true,
-                    message: None,
-                    valid: true,
-                },
             };
-            Ok(Response::new(reply))
+            Ok(Response::new(feedback::reply(
+                assignment.feedback_level.into(),
+                detail,
+                redacted,
+                report.redacted,
+            )))
         } else {
             Err(tonic::Status::new(
                 tonic::Code::InvalidArgument,
@@ -59,13 +116,34 @@ impl Test for Tester {
             ))
         }
     }
+
+    async fn drain(&self, request: Request<DrainRequest>) -> Result<Response<DrainStatus>, Status> {
+        if let Some(draining) = request.into_inner().draining {
+            self.drain.set_draining(draining);
+        }
+        Ok(Response::new(self.drain.status()))
+    }
+}
+
+/// Collected alongside the verdict, filled even if a checker fails.
+#[derive(Debug, Default)]
+struct Report {
+    diagnostics: Vec<Diagnostic>,
+    checks: Vec<CheckResult>,
+    /// The message of each check without expected values, see ```Error::redacted```.
+    redacted: Vec<String>,
 }
 
 impl Tester {
+    /// Runs the test and all checkers. The findings of the linter and the result of each checker are collected
+    /// into the ```report```. For zip submissions the content checkers and the linter get every script of the archive,
+    /// their findings are prefixed with the path of the file.
     async fn inner_run_test(
         &self,
         assignment: &Assignment,
         code_to_test: &str,
+        source_zip: &[u8],
+        report: &mut Report,
     ) -> Result<(), Error> {
         // TODO Fix always into thank you grpc
         let script_type: &Script = &assignment.script_type.into();
@@ -74,40 +152,93 @@ impl Tester {
             &assignment.name, &script_type
         );
         let context_dir = fs_util::extract_files_include(&assignment.include_files).await?;
-        let script_test_path =
-            fs_util::new_tmp_script_file(assignment.script_type.into(), code_to_test)
-                .map_err(|e| IOError::CreateFile(e))?
-                .into_temp_path();
+        let archive = if source_zip.is_empty() {
+            None
+        } else {
+            Some(
+                fs_util::extract_submission(
+                    source_zip,
+                    entry_point(assignment, script_type),
+                    *script_type,
+                    self.zip_limits,
+                )
+                .await?,
+            )
+        };
         let docker_api = self.docker.acquire().await;
+        let (archive_dir, scripts) = match archive {
+            Some((dir, scripts)) => (Some(dir), scripts),
+            None => (None, vec![SourceFile::single(code_to_test)]),
+        };
+        // the entry point of a zip submission is always the first script
+        let code_to_test = scripts[0].content.as_str();
 
-        let test_output = docker_api
-            .test_in_container(
-                &assignment.script_type.into(),
-                &script_test_path,
-                &context_dir.path(),
-                &assignment.args,
-            )
-            .await?;
-        test_output.status_success()?;
+        let lint_mode = assignment.lint_mode.into();
+        let lint_checker = if lint_mode == LintMode::NoLint {
+            None
+        } else if linter::command(script_type, "").is_some() {
+            Some(LintChecker::boxed(
+                &docker_api,
+                *script_type,
+                &scripts,
+                lint_mode,
+                assignment.lint_fail_severity.into(),
+            ))
+        } else {
+            log::warn!("No linter available for {:?}", script_type);
+            None
+        };
+
+        let tested = async {
+            let program_test = match archive_dir {
+                Some(dir) => {
+                    prepare_archive(&docker_api, *script_type, dir, assignment, code_to_test)
+                        .await?
+                }
+                None => prepare_program(&docker_api, *script_type, code_to_test).await?,
+            };
+            let test_output = docker_api
+                .test_in_container(
+                    &assignment.script_type.into(),
+                    &program_test.path(),
+                    context_dir.path(),
+                    &assignment.args,
+                )
+                .await?;
+            test_output.status_success()?;
+            Ok::<_, Error>(test_output)
+        }
+        .await;
+        let test_output = match tested {
+            Ok(test_output) => test_output,
+            Err(e) => {
+                // the findings of the linter help the most if the script does not build or run
+                if let Some(lint_checker) = lint_checker {
+                    run_checkers(&[lint_checker], report).await.ok();
+                }
+                return Err(e);
+            }
+        };
         log::info!("Test Output: {}", test_output);
         // only run solution if one of the 3 options is on
         let mut tests: Vec<Box<dyn Checker>> = Vec::new();
+        // keeps the solution dir alive until all checkers are done
+        let mut _solution_context_dir = None;
         if assignment.compare_fs_solution
             | assignment.compare_stdout_solution
             | assignment.compare_stdout_solution
             | assignment.custom_script.is_some()
         {
-            let script_solution_path =
-                fs_util::new_tmp_script_file(assignment.script_type.into(), &assignment.solution)
-                    .map_err(|e| IOError::CreateFile(e))?
-                    .into_temp_path();
+            let program_solution = prepare_program(&docker_api, *script_type, &assignment.solution)
+                .await
+                .map_err(|_e| SystemError::BadSampleSolution)?;
             let solution_context_dir =
                 fs_util::extract_files_include(&assignment.include_files).await?;
 
             let solution_output = docker_api
                 .test_in_container(
                     &assignment.script_type.into(),
-                    &script_solution_path,
+                    &program_solution.path(),
                     &solution_context_dir.path(),
                     &assignment.args,
                 )
@@ -135,29 +266,151 @@ impl Tester {
             if sort_stdout_by != SortStdoutBy::UnknownSort {
                 tests.push(SortedChecker::boxed(&test_output.stdout, sort_stdout_by))
             }
+
+            if let Some(custom_script) = &assignment.custom_script {
+                let (_, inner_testing_dir, inner_solution_dir) = custom_checker_mount_points();
+                let context = CustomCheckContext {
+                    script_type: *script_type,
+                    script_content: code_to_test.to_string(),
+                    script_files: scripts.clone(),
+                    args: assignment.args.clone(),
+                    tested: test_output.clone(),
+                    solution: solution_output.clone(),
+                    solution_content: assignment.solution.clone(),
+                    testing_dir: inner_testing_dir.to_string(),
+                    solution_dir: inner_solution_dir.to_string(),
+                };
+                tests.push(CustomScriptChecker::boxed(
+                    &docker_api,
+                    assignment.custom_script_type.into(),
+                    custom_script,
+                    context,
+                    context_dir.path(),
+                    solution_context_dir.path(),
+                ))
+            }
+            _solution_context_dir = Some(solution_context_dir);
         }
 
-        let regex_mode = assignment.regex_mode.into();
-        if regex_mode != RegexMode::UnknownRegex {
+        for (i, rule) in assignment.regex_rules.iter().enumerate() {
             tests.push(RegexChecker::boxed(
-                assignment.regex.as_ref(),
-                regex_mode,
-                &test_output.stdout,
-                &code_to_test.to_string(),
+                i + 1,
+                rule,
+                &test_output,
+                &scripts,
+                context_dir.path(),
             ));
         }
-        if let Some(custom_script) = &assignment.custom_script {
-            tests.push(CustomScriptChecker::boxed(
-                &custom_script,
-                code_to_test,
-                &test_output,
-                &context_dir.path(),
-            ))
+        if !assignment.required_commands.is_empty() || !assignment.forbidden_commands.is_empty() {
+            tests.push(CommandChecker::boxed(
+                *script_type,
+                &scripts,
+                &assignment.required_commands,
+                &assignment.forbidden_commands,
+            ));
         }
 
-        let _ = future::try_join_all(tests.iter().map(|item| async move { item.check().await }))
-            .await?;
+        tests.extend(lint_checker);
+
+        run_checkers(&tests, report).await?;
         info!("testing done for assignment: {}", &assignment.name);
         Ok(())
     }
 }
+
+/// Runs the checkers concurrently and adds their diagnostics and results to the report.
+/// Fails with the error of the first failed checker.
+async fn run_checkers(tests: &[Box<dyn Checker>], report: &mut Report) -> Result<(), Error> {
+    let results =
+        future::join_all(tests.iter().map(|item| async move { item.check().await })).await;
+    report
+        .diagnostics
+        .extend(tests.iter().flat_map(|item| item.diagnostics()));
+    report
+        .checks
+        .extend(tests.iter().zip(&results).map(|(item, result)| {
+            CheckResult {
+                name: item.name(),
+                passed: result.is_ok(),
+                score: item.score(),
+                message: result
+                    .as_ref()
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+                diff: result
+                    .as_ref()
+                    .err()
+                    .and_then(|e| e.diff())
+                    .unwrap_or_default(),
+            }
+        }));
+    report.redacted.extend(results.iter().map(|result| {
+        result
+            .as_ref()
+            .err()
+            .map(|e| e.redacted())
+            .unwrap_or_default()
+    }));
+    results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    Ok(())
+}
+
+/// The file run for zip submissions, the source file name of the script type if the assignment declares none.
+fn entry_point<'a>(assignment: &'a Assignment, script_type: &Script) -> &'a str {
+    if assignment.entry_point.is_empty() {
+        script_type.source_file_name()
+    } else {
+        &assignment.entry_point
+    }
+}
+
+/// Runs the entry point of the unpacked zip submission. For compiled languages the entry point is copied to the
+/// source file name of the script type and the program is built inside the unpacked dir.
+async fn prepare_archive(
+    docker_api: &DockerWrap,
+    script_type: Script,
+    dir: tempfile::TempDir,
+    assignment: &Assignment,
+    entry_content: &str,
+) -> Result<Program, Error> {
+    let entry_point = entry_point(assignment, &script_type);
+    if script_type.is_compiled() {
+        if entry_point != script_type.source_file_name() {
+            tokio::fs::write(
+                dir.path().join(script_type.source_file_name()),
+                entry_content.replace("\r\n", "\n"),
+            )
+            .await
+            .map_err(IOError::CreateFile)?;
+        }
+        docker_api
+            .compile_in_container(&script_type, dir.path())
+            .await?;
+        Ok(Program::Build(dir, script_type))
+    } else {
+        Ok(Program::Archive(dir, entry_point.to_string()))
+    }
+}
+
+/// Writes the script into a temp file or for compiled languages builds the program inside the container first.
+async fn prepare_program(
+    docker_api: &DockerWrap,
+    script_type: Script,
+    code: &str,
+) -> Result<Program, Error> {
+    if script_type.is_compiled() {
+        let build_dir = fs_util::new_build_dir(script_type, code)
+            .await
+            .map_err(IOError::CreateFile)?;
+        docker_api
+            .compile_in_container(&script_type, build_dir.path())
+            .await?;
+        Ok(Program::Build(build_dir, script_type))
+    } else {
+        let path = fs_util::new_tmp_script_file(script_type, code)
+            .map_err(IOError::CreateFile)?
+            .into_temp_path();
+        Ok(Program::Script(path))
+    }
+}
@@ -0,0 +1,302 @@
+//! Runs linters like [shellcheck](https://www.shellcheck.net/), [ruff](https://github.com/astral-sh/ruff) and
+//! [PSScriptAnalyzer](https://github.com/PowerShell/PSScriptAnalyzer) and parses their ```JSON``` output into [Diagnostics](../../grpc_api/struct.Diagnostic.html).
+use crate::error::{Error, SystemError};
+use grpc_api::{Diagnostic, Script, Severity};
+use serde::Deserialize;
+
+/// The linter command for the given script type. ```None``` if there is no linter for this script type.
+pub fn command(script: &Script, script_path: &str) -> Option<Vec<String>> {
+    let cmd = match script {
+        Script::Bash | Script::Shell => {
+            let shell = if *script == Script::Bash {
+                "bash"
+            } else {
+                "sh"
+            };
+            vec![
+                "shellcheck".into(),
+                "--format=json1".into(),
+                format!("--shell={}", shell),
+                script_path.into(),
+            ]
+        }
+        Script::Python3 => vec![
+            "ruff".into(),
+            "check".into(),
+            "--output-format=json".into(),
+            "--no-cache".into(),
+            "--exit-zero".into(),
+            script_path.into(),
+        ],
+        Script::PowerShell => {
+            let prog = if cfg!(target_family = "unix") {
+                "pwsh"
+            } else {
+                "powershell.exe"
+            };
+            vec![
+                prog.into(),
+                "-NoProfile".into(),
+                "-Command".into(),
+                format!(
+                    "ConvertTo-Json -Depth 2 -InputObject @(Invoke-ScriptAnalyzer -Path '{}' | \
+                     Select-Object Line, Column, RuleName, Message, @{{n='Severity';e={{[int]$_.Severity}}}})",
+                    script_path
+                ),
+            ]
+        }
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+/// Parses the linter output of the given script type.
+pub fn parse(script: &Script, stdout: &str) -> Result<Vec<Diagnostic>, Error> {
+    let diagnostics = match script {
+        Script::Bash | Script::Shell => parse_shellcheck(stdout),
+        Script::Python3 => parse_ruff(stdout),
+        Script::PowerShell => parse_script_analyzer(stdout),
+        _ => Ok(vec![]),
+    };
+    diagnostics.map_err(|e| Error::InvalidTest(SystemError::Linter(e.to_string())))
+}
+
+#[derive(Deserialize)]
+struct ShellCheckOutput {
+    comments: Vec<ShellCheckComment>,
+}
+
+#[derive(Deserialize)]
+struct ShellCheckComment {
+    line: u32,
+    column: u32,
+    level: String,
+    code: u32,
+    message: String,
+}
+
+fn parse_shellcheck(stdout: &str) -> Result<Vec<Diagnostic>, serde_json::Error> {
+    let output = serde_json::from_str::<ShellCheckOutput>(stdout)?;
+    Ok(output
+        .comments
+        .into_iter()
+        .map(|c| {
+            let severity = match c.level.as_str() {
+                "error" => Severity::Error,
+                "warning" => Severity::Warning,
+                "info" => Severity::Info,
+                _ => Severity::Style,
+            };
+            Diagnostic {
+                line: c.line,
+                column: c.column,
+                severity: severity as i32,
+                rule_id: format!("SC{}", c.code),
+                message: c.message,
+            }
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct RuffMessage {
+    code: Option<String>,
+    message: String,
+    location: RuffLocation,
+}
+
+#[derive(Deserialize)]
+struct RuffLocation {
+    row: u32,
+    column: u32,
+}
+
+/// Ruff has no severity. Syntax errors (```E9```) and undefined names (```F82```) are errors,
+/// the other pyflakes rules are warnings and everything else is style.
+fn ruff_severity(code: &str) -> Severity {
+    if code.starts_with("E9") || code.starts_with("F82") {
+        Severity::Error
+    } else if code.starts_with('F') {
+        Severity::Warning
+    } else {
+        Severity::Style
+    }
+}
+
+fn parse_ruff(stdout: &str) -> Result<Vec<Diagnostic>, serde_json::Error> {
+    let output = serde_json::from_str::<Vec<RuffMessage>>(stdout)?;
+    Ok(output
+        .into_iter()
+        .map(|m| {
+            // newer versions report syntax errors without a code
+            let rule_id = m.code.unwrap_or_else(|| String::from("E999"));
+            Diagnostic {
+                line: m.location.row,
+                column: m.location.column,
+                severity: ruff_severity(&rule_id) as i32,
+                rule_id,
+                message: m.message,
+            }
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ScriptAnalyzerRecord {
+    line: u32,
+    column: u32,
+    rule_name: String,
+    message: String,
+    severity: i32,
+}
+
+fn parse_script_analyzer(stdout: &str) -> Result<Vec<Diagnostic>, serde_json::Error> {
+    let output = serde_json::from_str::<Vec<ScriptAnalyzerRecord>>(stdout)?;
+    Ok(output
+        .into_iter()
+        .map(|r| {
+            // Information = 0, Warning = 1, Error = 2, ParseError = 3
+            let severity = match r.severity {
+                0 => Severity::Info,
+                1 => Severity::Warning,
+                _ => Severity::Error,
+            };
+            Diagnostic {
+                line: r.line,
+                column: r.column,
+                severity: severity as i32,
+                rule_id: r.rule_name,
+                message: r.message,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn severities(diagnostics: &[Diagnostic]) -> Vec<Severity> {
+        diagnostics
+            .iter()
+            .map(|d| Severity::from(d.severity))
+            .collect()
+    }
+
+    #[test]
+    fn shellcheck_json1() {
+        let stdout = r#"{"comments":[
+            {"file":"s.sh","line":3,"endLine":3,"column":6,"endColumn":8,"level":"warning","code":2086,
+             "message":"Double quote to prevent globbing and word splitting.","fix":null},
+            {"file":"s.sh","line":1,"endLine":1,"column":1,"endColumn":1,"level":"error","code":2148,
+             "message":"Tips depend on target shell.","fix":null},
+            {"file":"s.sh","line":5,"endLine":5,"column":1,"endColumn":4,"level":"info","code":2034,
+             "message":"foo appears unused.","fix":null},
+            {"file":"s.sh","line":7,"endLine":7,"column":3,"endColumn":9,"level":"style","code":2006,
+             "message":"Use $(...) notation instead of legacy backticks.","fix":null}
+        ]}"#;
+        let diagnostics = parse_shellcheck(stdout).unwrap();
+        assert_eq!(
+            severities(&diagnostics),
+            vec![
+                Severity::Warning,
+                Severity::Error,
+                Severity::Info,
+                Severity::Style
+            ]
+        );
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].column, 6);
+        assert_eq!(diagnostics[0].rule_id, "SC2086");
+        assert_eq!(
+            diagnostics[0].message,
+            "Double quote to prevent globbing and word splitting."
+        );
+    }
+
+    #[test]
+    fn shellcheck_without_findings() {
+        assert!(parse_shellcheck(r#"{"comments":[]}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn shellcheck_bad_json() {
+        assert!(parse_shellcheck("In s.sh line 1:").is_err());
+    }
+
+    #[test]
+    fn ruff_json() {
+        let stdout = r#"[
+            {"code":"F401","message":"`os` imported but unused","location":{"row":1,"column":8},
+             "end_location":{"row":1,"column":10},"filename":"s.py","fix":null,"noqa_row":1,"url":null},
+            {"code":"F821","message":"Undefined name `x`","location":{"row":3,"column":7},
+             "end_location":{"row":3,"column":8},"filename":"s.py","fix":null,"noqa_row":3,"url":null},
+            {"code":"E501","message":"Line too long (100 > 88)","location":{"row":4,"column":89},
+             "end_location":{"row":4,"column":100},"filename":"s.py","fix":null,"noqa_row":4,"url":null},
+            {"code":null,"message":"SyntaxError: Expected an expression","location":{"row":5,"column":4},
+             "end_location":{"row":5,"column":5},"filename":"s.py","fix":null,"noqa_row":null,"url":null}
+        ]"#;
+        let diagnostics = parse_ruff(stdout).unwrap();
+        assert_eq!(
+            severities(&diagnostics),
+            vec![
+                Severity::Warning,
+                Severity::Error,
+                Severity::Style,
+                Severity::Error
+            ]
+        );
+        assert_eq!(diagnostics[0].line, 1);
+        assert_eq!(diagnostics[0].column, 8);
+        assert_eq!(diagnostics[0].rule_id, "F401");
+        assert_eq!(diagnostics[3].rule_id, "E999");
+    }
+
+    #[test]
+    fn ruff_severity_by_code() {
+        assert_eq!(ruff_severity("E999"), Severity::Error);
+        assert_eq!(ruff_severity("E902"), Severity::Error);
+        assert_eq!(ruff_severity("F822"), Severity::Error);
+        assert_eq!(ruff_severity("F841"), Severity::Warning);
+        assert_eq!(ruff_severity("E711"), Severity::Style);
+        assert_eq!(ruff_severity("W291"), Severity::Style);
+    }
+
+    #[test]
+    fn script_analyzer_json() {
+        let stdout = r#"[
+            {"Line":2,"Column":1,"RuleName":"PSAvoidUsingWriteHost","Message":"Avoid Write-Host","Severity":1},
+            {"Line":4,"Column":5,"RuleName":"PSUseDeclaredVarsMoreThanAssignments","Message":"Unused","Severity":0},
+            {"Line":6,"Column":9,"RuleName":"PSAvoidUsingPlainTextForPassword","Message":"Plain text","Severity":2},
+            {"Line":8,"Column":1,"RuleName":"MissingEndCurlyBrace","Message":"Missing }","Severity":3}
+        ]"#;
+        let diagnostics = parse_script_analyzer(stdout).unwrap();
+        assert_eq!(
+            severities(&diagnostics),
+            vec![
+                Severity::Warning,
+                Severity::Info,
+                Severity::Error,
+                Severity::Error
+            ]
+        );
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[0].column, 1);
+        assert_eq!(diagnostics[0].rule_id, "PSAvoidUsingWriteHost");
+        assert_eq!(diagnostics[0].message, "Avoid Write-Host");
+    }
+
+    #[test]
+    fn script_analyzer_without_findings() {
+        assert!(parse_script_analyzer("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_error_is_invalid_test() {
+        match parse(&Script::Python3, "not json") {
+            Err(Error::InvalidTest(SystemError::Linter(_))) => {}
+            other => panic!("expected a linter error, got {:?}", other.map(|d| d.len())),
+        }
+    }
+}
@@ -4,14 +4,26 @@
 mod checker;
 mod config;
 mod docker_api;
+mod drain;
 mod error;
+mod feedback;
 mod fs_util;
 mod grpc_tester;
+mod linter;
 mod sema_wrap;
+mod tokenizer;
 use crate::docker_api::DockerWrap;
+use crate::drain::Drain;
+use futures::FutureExt;
 use grpc_api::test_server::TestServer;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
 //use tonic::transport::{Identity, Server, ServerTlsConfig};
 use tonic::transport::Server;
+
+/// How long the aborted tests get to send their reply before the server stops.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
 /// Real main function. Starting the RPC Server and global initialization the state.
 fn main() {
     if let Err(e) = tokio::runtime::Runtime::new().unwrap().block_on(run()) {
@@ -25,23 +37,56 @@ async fn run() -> Result<(), failure::Error> {
     env_logger::init();
     let config = config::get_config()?;
     log::info!("Pulling docker the required image. This may take some time...");
-    let docker_api = DockerWrap::new(config.docker_image, config.timeout);
+    let docker_api = DockerWrap::new(config.docker_image, config.timeout, config.compile_timeout);
     docker_api.pull_image().await?;
     log::info!("Pulling image done.");
     log::info!(
         "Limiting test to {} at the same  time.",
         config.max_curr_test
     );
-    let test = grpc_tester::Tester::new(docker_api, config.max_curr_test);
+    let zip_limits = fs_util::ZipLimits {
+        max_files: config.max_zip_files,
+        max_bytes: config.max_zip_bytes,
+    };
+    let drain = Arc::new(Drain::default());
+    let test = grpc_tester::Tester::new(
+        docker_api.clone(),
+        config.max_curr_test,
+        zip_limits,
+        drain.clone(),
+    );
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], config.port));
     /*    let cert = tokio::fs::read(&config.cert_path).await?;
     let key = tokio::fs::read(&config.key_path).await?;
     let identity = Identity::from_pem(cert, key);*/
     log::info!("Tester listening on {}", &addr);
-    Server::builder()
-        /*        .tls_config(ServerTlsConfig::new().identity(identity))*/
-        .add_service(TestServer::new(test))
-        .serve(addr)
-        .await?;
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let mut server = tokio::spawn(
+        Server::builder()
+            /*        .tls_config(ServerTlsConfig::new().identity(identity))*/
+            .add_service(TestServer::new(test))
+            .serve_with_shutdown(addr, stopped.map(|_| ())),
+    );
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        _ = drain::shutdown_signal() => {}
+    }
+    log::info!(
+        "Shutting down, waiting up to {}s for {} running tests",
+        config.shutdown_grace,
+        drain.running()
+    );
+    drain.set_draining(true);
+    let grace = Duration::from_secs(config.shutdown_grace);
+    if timeout(grace, drain.idle()).await.is_err() {
+        log::warn!("Grace period is over, aborted {} tests", drain.abort_all());
+    }
+    let _ = stop.send(());
+    match timeout(STOP_TIMEOUT, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => log::warn!("Not all connections were closed in time"),
+    }
+    docker_api.remove_containers().await;
+    log::info!("Shutdown complete");
     Ok(())
 }
@@ -0,0 +1,1167 @@
+//! Tokenizes Bash/sh and PowerShell scripts to find the commands which are actually invoked.
+//! Comments, string literals, arguments and here documents are ignored and quotes are removed like the shell would do,
+//! so ```'so'rt``` or ```\sort``` are still found as ```sort```.
+use crate::error::{Error, SystemError};
+use grpc_api::Script;
+use std::collections::HashMap;
+
+/// Max nesting of command substitutions, subexpressions, ```eval``` and ```sh -c```.
+/// The lexers recurse for each level, deeper scripts are rejected instead of overflowing the stack.
+pub const MAX_NESTING: usize = 64;
+
+/// A command invoked by the script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub line: usize,
+}
+
+/// All commands the script invokes in order.
+pub fn invoked_commands(script: &Script, content: &str) -> Result<Vec<Command>, Error> {
+    match script {
+        Script::Bash | Script::Shell => ShellLexer::new(content, 1, HashMap::new()).commands(),
+        Script::PowerShell => PowerShellLexer::new(content, 1).commands(),
+        _ => Err(Error::InvalidTest(SystemError::NoCommandCheck(*script))),
+    }
+}
+
+/// Compares the invoked command with a name from the allow/deny list. The path is stripped and
+/// PowerShell is case insensitive.
+pub fn same_command(script: &Script, invoked: &str, name: &str) -> bool {
+    let invoked = invoked.rsplit(['/', '\\']).next().unwrap_or(invoked);
+    match script {
+        Script::PowerShell => {
+            let invoked = invoked.to_lowercase();
+            let invoked = invoked.trim_end_matches(".exe");
+            invoked == name.to_lowercase()
+        }
+        _ => invoked == name,
+    }
+}
+
+/// Reserved words which keep the next word in command position.
+const SHELL_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "!", "{", "}", "time",
+    "esac",
+];
+/// Reserved words which are followed by names or words, but not by a command.
+const SHELL_NO_COMMAND_KEYWORDS: &[&str] = &["for", "select", "function", "in"];
+/// Commands which run their arguments as a command.
+/// ```command -v``` and ```command -V``` only print how the name is resolved, see ```is_lookup```.
+const SHELL_WRAPPERS: &[&str] = &[
+    "command", "builtin", "exec", "nohup", "sudo", "env", "xargs", "nice", "timeout", "stdbuf",
+];
+/// Shells which run the argument of ```-c``` as a script.
+const SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh"];
+
+/// A word after quote removal and expansion of known variables.
+struct Word {
+    value: String,
+    line: usize,
+    /// Contains an expansion which value is not known like ```$1``` or ```$(date)```.
+    dynamic: bool,
+    /// ```NAME=value```
+    assignment: Option<(String, String)>,
+}
+
+/// Where in a ```case``` statement the lexer is.
+#[derive(PartialEq)]
+enum CaseState {
+    Header,
+    Pattern,
+    Body,
+}
+
+struct ShellLexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    commands: Vec<Command>,
+    /// Variables assigned with a literal value, ```c=sort; $c``` is still ```sort```.
+    vars: HashMap<String, String>,
+    /// Here document delimiters waiting for the next newline and if tabs are stripped.
+    heredocs: Vec<(String, bool)>,
+    /// Nesting of backtick command substitutions, inside a backtick ends the word.
+    backticks: usize,
+    /// Nesting of ```parse_list``` calls, see ```MAX_NESTING```.
+    nesting: usize,
+    too_deep: bool,
+}
+
+impl ShellLexer {
+    fn new(content: &str, line: usize, vars: HashMap<String, String>) -> Self {
+        ShellLexer {
+            chars: content.chars().collect(),
+            pos: 0,
+            line,
+            commands: vec![],
+            vars,
+            heredocs: vec![],
+            backticks: 0,
+            nesting: 0,
+            too_deep: false,
+        }
+    }
+
+    fn commands(mut self) -> Result<Vec<Command>, Error> {
+        self.parse_list(None);
+        if self.too_deep {
+            Err(Error::TooDeeplyNested(MAX_NESTING))
+        } else {
+            Ok(self.commands)
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') => {
+                    self.bump();
+                }
+                Some('\\') if self.peek_at(1) == Some('\n') => {
+                    self.bump();
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                return;
+            }
+            self.bump();
+        }
+    }
+
+    /// Skips the bodies of all pending here documents, called right after a newline.
+    fn skip_heredocs(&mut self) {
+        for (delimiter, strip_tabs) in std::mem::take(&mut self.heredocs) {
+            while self.peek().is_some() {
+                let start = self.pos;
+                self.skip_line();
+                let line: String = self.chars[start..self.pos].iter().collect();
+                self.bump();
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line.as_str()
+                };
+                if line.trim_end_matches('\r') == delimiter {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Skips ```((...))``` and ```$((...))``` arithmetic, the lexer is right after the opening parentheses.
+    fn skip_arithmetic(&mut self) {
+        let mut depth = 2;
+        while let Some(c) = self.bump() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Parses a list of commands until the terminator or the end of the script.
+    /// If the script is nested too deep the rest of it is skipped.
+    fn parse_list(&mut self, terminator: Option<char>) {
+        if self.nesting >= MAX_NESTING {
+            self.too_deep = true;
+            self.pos = self.chars.len();
+            return;
+        }
+        self.nesting += 1;
+        let mut command_start = true;
+        // the next word is the target of a redirection
+        let mut skip_word = false;
+        // after command, xargs... the next word which is not an option is a command as well
+        let mut wrapper = false;
+        let mut depth = 0;
+        let mut case_stack: Vec<CaseState> = vec![];
+        // the current command and its arguments for eval and sh -c
+        let mut current: Option<String> = None;
+        let mut args: Vec<Word> = vec![];
+
+        loop {
+            self.skip_blanks();
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break,
+            };
+            // the ```)``` after a case pattern does not end a command substitution
+            let in_pattern = case_stack.last() == Some(&CaseState::Pattern);
+            if Some(c) == terminator && (c != ')' || (depth == 0 && !in_pattern)) {
+                self.bump();
+                break;
+            }
+            match c {
+                '#' => self.skip_line(),
+                '\n' | ';' | '&' | '|' | '(' | ')' => {
+                    self.bump();
+                    let next = self.peek();
+                    if c == ';' && (next == Some(';') || next == Some('&')) {
+                        // end of a case item
+                        self.bump();
+                        if let Some(state) = case_stack.last_mut() {
+                            *state = CaseState::Pattern;
+                        }
+                    } else if c == '(' && next == Some('(') && command_start {
+                        self.bump();
+                        self.skip_arithmetic();
+                    } else if c == '(' {
+                        depth += 1;
+                    } else if c == ')' {
+                        if case_stack.last() == Some(&CaseState::Pattern) {
+                            *case_stack.last_mut().unwrap() = CaseState::Body;
+                        } else if depth > 0 {
+                            depth -= 1;
+                        }
+                    } else if c == '\n' {
+                        self.skip_heredocs();
+                    }
+                    self.finish_command(current.take(), std::mem::take(&mut args));
+                    command_start = true;
+                    skip_word = false;
+                    wrapper = false;
+                }
+                '<' | '>' => {
+                    self.read_redirection();
+                    skip_word = !matches_fd_duplication(self.peek(), self.peek_at(1));
+                    if !skip_word {
+                        self.bump();
+                        self.bump();
+                    }
+                }
+                _ => {
+                    let word = match self.read_word() {
+                        Some(word) => word,
+                        None => continue,
+                    };
+                    if skip_word {
+                        skip_word = false;
+                        continue;
+                    }
+                    if case_stack.last() == Some(&CaseState::Header) {
+                        if word.value == "in" {
+                            *case_stack.last_mut().unwrap() = CaseState::Pattern;
+                        }
+                        continue;
+                    }
+                    if case_stack.last() == Some(&CaseState::Pattern) {
+                        if word.value == "esac" {
+                            case_stack.pop();
+                        }
+                        continue;
+                    }
+                    if !command_start {
+                        args.push(word);
+                        continue;
+                    }
+                    if let Some((name, value)) = &word.assignment {
+                        if word.dynamic {
+                            self.vars.remove(name);
+                        } else {
+                            self.vars.insert(name.clone(), value.clone());
+                        }
+                        continue;
+                    }
+                    let value = word.value.as_str();
+                    if word.dynamic && value.is_empty() {
+                        command_start = false;
+                        continue;
+                    }
+                    if wrapper && (value.starts_with('-') || value.parse::<f64>().is_ok()) {
+                        if is_lookup(current.as_deref(), value) {
+                            wrapper = false;
+                            command_start = false;
+                        }
+                        continue;
+                    }
+                    match value {
+                        "case" => {
+                            case_stack.push(CaseState::Header);
+                        }
+                        "esac" if !case_stack.is_empty() => {
+                            case_stack.pop();
+                        }
+                        "[[" => {
+                            self.skip_until_word("]]");
+                            command_start = false;
+                        }
+                        v if SHELL_KEYWORDS.contains(&v) => {}
+                        v if SHELL_NO_COMMAND_KEYWORDS.contains(&v) => {
+                            command_start = false;
+                        }
+                        _ if self.is_function_definition() => {
+                            command_start = false;
+                        }
+                        v => {
+                            self.commands.push(Command {
+                                name: v.to_string(),
+                                line: word.line,
+                            });
+                            wrapper = SHELL_WRAPPERS.contains(&v);
+                            command_start = wrapper;
+                            current = Some(v.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        self.finish_command(current, args);
+        self.nesting -= 1;
+    }
+
+    /// Runs ```eval``` and ```sh -c``` arguments through the lexer again.
+    fn finish_command(&mut self, command: Option<String>, args: Vec<Word>) {
+        let command = match command {
+            Some(command) => command,
+            None => return,
+        };
+        let name = command.rsplit('/').next().unwrap_or(&command);
+        let script = if name == "eval" {
+            args.iter()
+                .map(|w| w.value.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else if SHELLS.contains(&name) {
+            match args.iter().position(|w| w.value == "-c") {
+                Some(i) => args.get(i + 1).map(|w| w.value.clone()).unwrap_or_default(),
+                None => return,
+            }
+        } else {
+            return;
+        };
+        let line = args.first().map(|w| w.line).unwrap_or(self.line);
+        let mut inner = ShellLexer::new(&script, line, self.vars.clone());
+        inner.nesting = self.nesting;
+        inner.parse_list(None);
+        self.commands.extend(inner.commands);
+        if inner.too_deep {
+            self.too_deep = true;
+            self.pos = self.chars.len();
+        }
+    }
+
+    /// ```name() { ...; }``` defines a function and does not run ```name```.
+    fn is_function_definition(&self) -> bool {
+        let rest = self.chars[self.pos..]
+            .iter()
+            .filter(|c| **c != ' ' && **c != '\t')
+            .take(2)
+            .collect::<String>();
+        rest == "()"
+    }
+
+    fn skip_until_word(&mut self, end: &str) {
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None | Some('\n') => return,
+                Some(c) if is_shell_meta(c) => {
+                    self.bump();
+                }
+                Some(_) => {
+                    if let Some(word) = self.read_word() {
+                        if word.value == end {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Consumes the redirection operator and remembers here documents.
+    fn read_redirection(&mut self) {
+        let first = self.bump();
+        if first == Some('<') && self.peek() == Some('<') {
+            self.bump();
+            if self.peek() == Some('<') {
+                // here string, the next word is data
+                self.bump();
+                return;
+            }
+            let strip_tabs = if self.peek() == Some('-') {
+                self.bump();
+                true
+            } else {
+                false
+            };
+            self.skip_blanks();
+            if let Some(word) = self.read_word() {
+                self.heredocs.push((word.value, strip_tabs));
+            }
+            return;
+        }
+        while let Some('>') | Some('|') = self.peek() {
+            self.bump();
+        }
+    }
+
+    /// Reads one word and removes the quotes. ```None``` if the word only was a file descriptor of a redirection.
+    fn read_word(&mut self) -> Option<Word> {
+        let line = self.line;
+        let mut value = String::new();
+        let mut dynamic = false;
+        let mut quoted = false;
+        let mut assignment_name: Option<String> = None;
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' | '\n' | ';' | '&' | '|' | '<' | '>' | '(' | ')' => break,
+                '`' if self.backticks > 0 => break,
+                '\\' => {
+                    self.bump();
+                    match self.bump() {
+                        Some('\n') | None => {}
+                        Some(escaped) => value.push(escaped),
+                    }
+                }
+                '\'' => {
+                    quoted = true;
+                    self.bump();
+                    while let Some(c) = self.bump() {
+                        if c == '\'' {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                }
+                '"' => {
+                    quoted = true;
+                    self.bump();
+                    self.read_double_quoted(&mut value, &mut dynamic);
+                }
+                '$' => {
+                    self.bump();
+                    self.read_expansion(&mut value, &mut dynamic);
+                }
+                '`' => {
+                    self.bump();
+                    self.parse_backticks();
+                    dynamic = true;
+                }
+                '=' if assignment_name.is_none() && !quoted && is_identifier(&value) => {
+                    self.bump();
+                    assignment_name = Some(std::mem::take(&mut value));
+                }
+                c => {
+                    self.bump();
+                    value.push(c);
+                }
+            }
+        }
+        if !quoted
+            && assignment_name.is_none()
+            && !value.is_empty()
+            && value.chars().all(|c| c.is_ascii_digit())
+            && (self.peek() == Some('<') || self.peek() == Some('>'))
+        {
+            return None;
+        }
+        if value.is_empty() && !quoted && !dynamic && assignment_name.is_none() {
+            // a meta char we do not know, skip it
+            self.bump();
+            return None;
+        }
+        Some(Word {
+            line,
+            dynamic,
+            assignment: assignment_name.map(|name| (name, value.clone())),
+            value,
+        })
+    }
+
+    fn parse_backticks(&mut self) {
+        self.backticks += 1;
+        self.parse_list(Some('`'));
+        self.backticks -= 1;
+    }
+
+    fn read_double_quoted(&mut self, value: &mut String, dynamic: &mut bool) {
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => return,
+                '\\' => match self.peek() {
+                    Some('$') | Some('`') | Some('"') | Some('\\') => {
+                        value.push(self.bump().unwrap());
+                    }
+                    Some('\n') => {
+                        self.bump();
+                    }
+                    _ => value.push('\\'),
+                },
+                '$' => self.read_expansion(value, dynamic),
+                '`' => {
+                    self.parse_backticks();
+                    *dynamic = true;
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// Reads an expansion after ```$```. Command substitutions are parsed for commands as well.
+    fn read_expansion(&mut self, value: &mut String, dynamic: &mut bool) {
+        match self.peek() {
+            Some('(') if self.peek_at(1) == Some('(') => {
+                self.bump();
+                self.bump();
+                self.skip_arithmetic();
+                *dynamic = true;
+            }
+            Some('(') => {
+                self.bump();
+                self.parse_list(Some(')'));
+                *dynamic = true;
+            }
+            Some('{') => {
+                self.bump();
+                let mut name = String::new();
+                while let Some(c) = self.bump() {
+                    if c == '}' {
+                        break;
+                    }
+                    name.push(c);
+                }
+                self.expand_var(&name, value, dynamic);
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        name.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                self.expand_var(&name, value, dynamic);
+            }
+            Some(c) if c.is_ascii_digit() || "@*#?$!-".contains(c) => {
+                self.bump();
+                *dynamic = true;
+            }
+            _ => value.push('$'),
+        }
+    }
+
+    fn expand_var(&self, name: &str, value: &mut String, dynamic: &mut bool) {
+        match self.vars.get(name) {
+            Some(known) => value.push_str(known),
+            None => *dynamic = true,
+        }
+    }
+}
+
+/// ```command -v name``` and ```command -V name``` only look up the name, combined options like ```-pv``` as well.
+fn is_lookup(wrapper: Option<&str>, option: &str) -> bool {
+    wrapper == Some("command")
+        && !option.starts_with("--")
+        && option.starts_with('-')
+        && option.contains(['v', 'V'])
+}
+
+/// ```>&2``` or ```2>&1``` duplicate a file descriptor instead of naming a file.
+fn matches_fd_duplication(c: Option<char>, next: Option<char>) -> bool {
+    c == Some('&')
+        && next
+            .map(|n| n.is_ascii_digit() || n == '-')
+            .unwrap_or(false)
+}
+
+fn is_shell_meta(c: char) -> bool {
+    ";&|<>()".contains(c)
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Keywords which are followed by a command.
+const PS_KEYWORDS: &[&str] = &[
+    "else",
+    "do",
+    "try",
+    "finally",
+    "begin",
+    "process",
+    "end",
+    "return",
+    "throw",
+    "exit",
+    "dynamicparam",
+];
+/// Keywords which are followed by a condition, name or type, but not by a command.
+const PS_NO_COMMAND_KEYWORDS: &[&str] = &[
+    "if", "elseif", "foreach", "for", "while", "until", "switch", "function", "filter", "workflow",
+    "param", "catch", "trap", "class", "enum", "using", "in", "break", "continue", "data",
+];
+
+struct PowerShellLexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    commands: Vec<Command>,
+    /// Nesting of ```parse_list``` calls, see ```MAX_NESTING```.
+    nesting: usize,
+    too_deep: bool,
+}
+
+impl PowerShellLexer {
+    fn new(content: &str, line: usize) -> Self {
+        PowerShellLexer {
+            chars: content.chars().collect(),
+            pos: 0,
+            line,
+            commands: vec![],
+            nesting: 0,
+            too_deep: false,
+        }
+    }
+
+    fn commands(mut self) -> Result<Vec<Command>, Error> {
+        self.parse_list(None);
+        if self.too_deep {
+            Err(Error::TooDeeplyNested(MAX_NESTING))
+        } else {
+            Ok(self.commands)
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') => {
+                    self.bump();
+                }
+                Some('`') if self.peek_at(1) == Some('\n') || self.peek_at(1) == Some('\r') => {
+                    self.bump();
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn skip_until(&mut self, end: &str) {
+        let end = end.chars().collect::<Vec<_>>();
+        while self.peek().is_some() {
+            if self.chars[self.pos..].starts_with(&end) {
+                for _ in 0..end.len() {
+                    self.bump();
+                }
+                return;
+            }
+            self.bump();
+        }
+    }
+
+    /// Parses statements until the terminator or the end of the script.
+    /// If the script is nested too deep the rest of it is skipped.
+    fn parse_list(&mut self, terminator: Option<char>) {
+        if self.nesting >= MAX_NESTING {
+            self.too_deep = true;
+            self.pos = self.chars.len();
+            return;
+        }
+        self.nesting += 1;
+        let mut command_start = true;
+        // after the call operator & even a string is a command
+        let mut call_operator = false;
+        let mut depth = 0;
+        let mut current: Option<Command> = None;
+        let mut args: Vec<String> = vec![];
+
+        loop {
+            self.skip_blanks();
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break,
+            };
+            if Some(c) == terminator && depth == 0 {
+                self.bump();
+                break;
+            }
+            match c {
+                '#' => {
+                    while let Some(c) = self.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.bump();
+                    }
+                }
+                '<' if self.peek_at(1) == Some('#') => self.skip_until("#>"),
+                '\n' | ';' | '|' | '{' | '}' | '(' | ')' => {
+                    self.bump();
+                    match c {
+                        '(' | '{' => depth += 1,
+                        ')' | '}' if depth > 0 => depth -= 1,
+                        _ => {}
+                    }
+                    self.finish_command(current.take(), std::mem::take(&mut args));
+                    command_start = true;
+                    call_operator = false;
+                }
+                '&' if self.peek_at(1) == Some('&') => {
+                    self.bump();
+                    self.bump();
+                    self.finish_command(current.take(), std::mem::take(&mut args));
+                    command_start = true;
+                }
+                '&' if command_start => {
+                    self.bump();
+                    call_operator = true;
+                }
+                '.' if command_start
+                    && (self.peek_at(1) == Some(' ') || self.peek_at(1) == Some('\t')) =>
+                {
+                    // dot sourcing
+                    self.bump();
+                    call_operator = true;
+                }
+                '@' if self.peek_at(1) == Some('"') || self.peek_at(1) == Some('\'') => {
+                    let quote = self.peek_at(1).unwrap();
+                    self.bump();
+                    self.bump();
+                    self.skip_until(&format!("\n{}@", quote));
+                    command_start = false;
+                }
+                '@' if self.peek_at(1) == Some('{') => {
+                    // hashtable literal, the keys are no commands
+                    self.bump();
+                    self.bump();
+                    self.skip_balanced('{', '}');
+                    command_start = false;
+                }
+                '@' => {
+                    self.bump();
+                }
+                '\'' | '"' => {
+                    let line = self.line;
+                    let value = self.read_string();
+                    if call_operator {
+                        self.push_command(&value, line, &mut current);
+                        call_operator = false;
+                        command_start = false;
+                    } else if command_start {
+                        command_start = false;
+                    } else {
+                        args.push(value);
+                    }
+                }
+                '$' => {
+                    self.read_variable();
+                    self.skip_blanks();
+                    if self.is_assignment_operator() {
+                        while let Some(c) = self.peek() {
+                            if c == '=' {
+                                self.bump();
+                                break;
+                            }
+                            self.bump();
+                        }
+                        command_start = true;
+                    } else if call_operator {
+                        // & $command, the command is not known
+                        call_operator = false;
+                        command_start = false;
+                    } else {
+                        command_start = false;
+                    }
+                }
+                '[' => {
+                    // type literal like [int] or [System.IO.File]::ReadAllText()
+                    self.skip_balanced('[', ']');
+                }
+                _ => {
+                    let line = self.line;
+                    let word = self.read_bare_word();
+                    if word.is_empty() {
+                        self.bump();
+                        continue;
+                    }
+                    if !command_start && !call_operator {
+                        args.push(word);
+                        continue;
+                    }
+                    let lower = word.to_lowercase();
+                    if call_operator {
+                        self.push_command(&word, line, &mut current);
+                        call_operator = false;
+                        command_start = false;
+                    } else if PS_KEYWORDS.contains(&lower.as_str()) {
+                    } else if PS_NO_COMMAND_KEYWORDS.contains(&lower.as_str()) {
+                        command_start = false;
+                    } else if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                        // number or operator
+                        command_start = false;
+                    } else {
+                        self.push_command(&word, line, &mut current);
+                        command_start = false;
+                    }
+                }
+            }
+        }
+        self.finish_command(current, args);
+        self.nesting -= 1;
+    }
+
+    fn push_command(&mut self, name: &str, line: usize, current: &mut Option<Command>) {
+        let command = Command {
+            name: name.to_string(),
+            line,
+        };
+        self.commands.push(command.clone());
+        *current = Some(command);
+    }
+
+    /// Runs the argument of ```Invoke-Expression``` through the lexer again.
+    fn finish_command(&mut self, command: Option<Command>, args: Vec<String>) {
+        if let Some(command) = command {
+            let name = command.name.to_lowercase();
+            if name == "invoke-expression" || name == "iex" {
+                let mut inner = PowerShellLexer::new(&args.join(" "), command.line);
+                inner.nesting = self.nesting;
+                inner.parse_list(None);
+                self.commands.extend(inner.commands);
+                if inner.too_deep {
+                    self.too_deep = true;
+                    self.pos = self.chars.len();
+                }
+            }
+        }
+    }
+
+    fn is_assignment_operator(&self) -> bool {
+        match (self.peek(), self.peek_at(1)) {
+            (Some('='), next) => next != Some('='),
+            (Some(c), Some('=')) => "+-*/%".contains(c),
+            _ => false,
+        }
+    }
+
+    fn skip_balanced(&mut self, open: char, close: char) {
+        let mut depth = 1;
+        while let Some(c) = self.bump() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Reads ```$name```, ```${name}``` or the subexpression ```$(...)``` which can contain commands.
+    fn read_variable(&mut self) {
+        self.bump();
+        match self.peek() {
+            Some('(') => {
+                self.bump();
+                self.parse_list(Some(')'));
+            }
+            Some('{') => {
+                self.bump();
+                self.skip_balanced('{', '}');
+            }
+            _ => {
+                while let Some(c) = self.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == ':' || c == '?' || c == '^' {
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads a string literal and returns its value. Subexpressions in double quoted strings are parsed.
+    fn read_string(&mut self) -> String {
+        let quote = self.bump().unwrap_or('"');
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            if c == quote {
+                self.bump();
+                // a doubled quote is an escaped quote
+                if self.peek() == Some(quote) {
+                    self.bump();
+                    value.push(quote);
+                    continue;
+                }
+                break;
+            }
+            if quote == '"' && c == '`' {
+                self.bump();
+                if let Some(escaped) = self.bump() {
+                    value.push(escaped);
+                }
+            } else if quote == '"' && c == '$' && self.peek_at(1) == Some('(') {
+                self.bump();
+                self.bump();
+                self.parse_list(Some(')'));
+            } else {
+                self.bump();
+                value.push(c);
+            }
+        }
+        value
+    }
+
+    fn read_bare_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "|;&(){}'\"#,=".contains(c) {
+                break;
+            }
+            if c == '`' {
+                self.bump();
+                if let Some(escaped) = self.bump() {
+                    word.push(escaped);
+                }
+                continue;
+            }
+            self.bump();
+            word.push(c);
+        }
+        word
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(script: Script, content: &str) -> Vec<String> {
+        invoked_commands(&script, content)
+            .unwrap()
+            .into_iter()
+            .map(|command| command.name)
+            .collect()
+    }
+
+    fn bash(content: &str) -> Vec<String> {
+        names(Script::Bash, content)
+    }
+
+    fn ps(content: &str) -> Vec<String> {
+        names(Script::PowerShell, content)
+    }
+
+    #[test]
+    fn commands_and_lines() {
+        let commands = invoked_commands(&Script::Bash, "ls -l | sort\n\nuniq -c").unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                Command {
+                    name: "ls".into(),
+                    line: 1
+                },
+                Command {
+                    name: "sort".into(),
+                    line: 1
+                },
+                Command {
+                    name: "uniq".into(),
+                    line: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        assert_eq!(bash("# sort the lines\necho hi # | sort"), vec!["echo"]);
+        assert_eq!(
+            ps("# Sort-Object\n<# sort\nuniq #>\nGet-Content a"),
+            vec!["Get-Content"]
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(bash("echo 'sort | uniq' \"sort; uniq\""), vec!["echo"]);
+        assert_eq!(
+            bash("'so'rt a; \"un\"iq; \\wc -l"),
+            vec!["sort", "uniq", "wc"]
+        );
+        assert_eq!(ps("Write-Output 'sort' \"uniq\""), vec!["Write-Output"]);
+    }
+
+    #[test]
+    fn command_substitutions() {
+        assert_eq!(bash("echo $(sort a | uniq)"), vec!["echo", "sort", "uniq"]);
+        assert_eq!(
+            bash("echo \"$(sort a)\" `uniq b`"),
+            vec!["echo", "sort", "uniq"]
+        );
+        assert_eq!(bash("echo $((1 + 2))"), vec!["echo"]);
+        assert_eq!(
+            ps("Write-Output \"$(Sort-Object)\""),
+            vec!["Write-Output", "Sort-Object"]
+        );
+    }
+
+    #[test]
+    fn case_statements() {
+        assert_eq!(
+            bash("case $1 in\n  a|b) sort;;\n  *) uniq ;;\nesac"),
+            vec!["sort", "uniq"]
+        );
+        assert_eq!(
+            bash("echo $(case x in a) sort;; esac)"),
+            vec!["echo", "sort"]
+        );
+        assert_eq!(
+            bash("x=$(case $1 in a) sort a;; b) uniq;; esac); wc -l"),
+            vec!["sort", "uniq", "wc"]
+        );
+    }
+
+    #[test]
+    fn heredocs() {
+        let script = "cat <<EOF\nsort\nEOF\nuniq\ncat <<-'END'\n\tsort\n\tEND\n";
+        assert_eq!(bash(script), vec!["cat", "uniq", "cat"]);
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(bash("c=sort; $c a"), vec!["sort"]);
+        assert_eq!(bash("$1 a"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn eval_and_shells() {
+        assert_eq!(bash("eval 'sort a'"), vec!["eval", "sort"]);
+        assert_eq!(bash("sh -c 'sort a | uniq'"), vec!["sh", "sort", "uniq"]);
+        assert_eq!(bash("/bin/bash -c \"sort a\""), vec!["/bin/bash", "sort"]);
+        assert_eq!(bash("bash script.sh sort"), vec!["bash"]);
+    }
+
+    #[test]
+    fn invoke_expression() {
+        assert_eq!(ps("iex 'sort'"), vec!["iex", "sort"]);
+        assert_eq!(
+            ps("Invoke-Expression \"Sort-Object\""),
+            vec!["Invoke-Expression", "Sort-Object"]
+        );
+        assert_eq!(ps("& 'uniq' a; . ./lib.ps1"), vec!["uniq", "./lib.ps1"]);
+    }
+
+    #[test]
+    fn wrappers() {
+        assert_eq!(bash("xargs -n 1 sort"), vec!["xargs", "sort"]);
+        assert_eq!(bash("nice -n 5 sort"), vec!["nice", "sort"]);
+        assert_eq!(bash("command sort"), vec!["command", "sort"]);
+    }
+
+    #[test]
+    fn command_lookup_does_not_invoke() {
+        assert_eq!(bash("command -v sort"), vec!["command"]);
+        assert_eq!(
+            bash("command -V sort; command -pv uniq"),
+            vec!["command", "command"]
+        );
+        assert_eq!(
+            bash("if command -v sort; then uniq; fi"),
+            vec!["command", "uniq"]
+        );
+    }
+
+    #[test]
+    fn same_command_strips_path_and_case() {
+        assert!(same_command(&Script::Bash, "/usr/bin/sort", "sort"));
+        assert!(!same_command(&Script::Bash, "Sort", "sort"));
+        assert!(same_command(
+            &Script::PowerShell,
+            "C:\\bin\\SORT.exe",
+            "sort"
+        ));
+    }
+
+    #[test]
+    fn unsupported_script_type() {
+        assert!(invoked_commands(&Script::Python3, "import os").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let nested = |open: &str, close: &str, depth: usize| {
+            format!("{}sort{}", open.repeat(depth), close.repeat(depth))
+        };
+        assert_eq!(
+            bash(&nested("echo $(", ")", MAX_NESTING - 1)).len(),
+            MAX_NESTING
+        );
+        for script in &[
+            nested("$(", ")", 10_000),
+            nested("echo \"$(", ")\"", 10_000),
+            "eval ".repeat(10_000) + "sort",
+        ] {
+            match invoked_commands(&Script::Bash, script) {
+                Err(Error::TooDeeplyNested(_)) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+        match invoked_commands(&Script::PowerShell, &nested("$(", ")", 10_000)) {
+            Err(Error::TooDeeplyNested(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}